@@ -0,0 +1,178 @@
+use crate::computer::{Computer, Stop};
+use crate::memory::KBD;
+use crate::test_runner::{FileLoader, TestRunner};
+use hack_assembler::assembler::Assembler;
+use std::{error::Error, fs, path::Path};
+
+const DEFAULT_MAX_CYCLES: u64 = 1_000_000;
+
+pub struct Config {
+    filepath: String,
+    max_cycles: u64,
+    pokes: Vec<(u16, u16)>,
+    peeks: Vec<u16>,
+}
+
+impl Config {
+    pub fn new<I>(mut args: I) -> Result<Config, &'static str>
+    where
+        I: Iterator<Item = String>,
+    {
+        args.next();
+
+        let mut filepath = None;
+        let mut max_cycles = DEFAULT_MAX_CYCLES;
+        let mut pokes = Vec::new();
+        let mut peeks = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--cycles" => {
+                    max_cycles = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--cycles expects a number.")?;
+                }
+                "--set" => {
+                    let poke = args
+                        .next()
+                        .and_then(|assignment| Config::parse_assignment(&assignment))
+                        .ok_or("--set expects ADDRESS=VALUE with an address up to 24576 (KBD).")?;
+                    pokes.push(poke);
+                }
+                "--show" => {
+                    let address = args
+                        .next()
+                        .and_then(|address| address.parse().ok())
+                        .ok_or("--show expects an address.")?;
+                    peeks.push(address);
+                }
+                _ if arg.starts_with('-') => return Err("Unknown option."),
+                _ => filepath = Some(arg),
+            }
+        }
+
+        let filepath = match filepath {
+            Some(f) => f,
+            None => return Err("Did not receive a file path."),
+        };
+
+        Ok(Config {
+            filepath,
+            max_cycles,
+            pokes,
+            peeks,
+        })
+    }
+
+    /// Values may be signed or unsigned, as in test scripts.
+    fn parse_assignment(assignment: &str) -> Option<(u16, u16)> {
+        let mut parts = assignment.splitn(2, '=');
+        let address = parts
+            .next()?
+            .parse()
+            .ok()
+            .filter(|&address| address <= KBD)?;
+        let value = parts
+            .next()?
+            .parse::<i32>()
+            .ok()
+            .filter(|value| (-32768..=65535).contains(value))?;
+        Some((address, value as u16))
+    }
+}
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    if config.filepath.ends_with(".tst") {
+        return run_script(&config.filepath);
+    }
+
+    let contents = fs::read_to_string(&config.filepath)?;
+    let machine_code = if config.filepath.ends_with(".asm") {
+        Assembler::assemble_file(&config.filepath, contents).inspect_err(|errors| {
+            for error in errors.errors() {
+                eprintln!("{}", error);
+            }
+        })?
+    } else {
+        contents
+    };
+
+    let mut computer = Computer::new();
+    computer.load_hack(&machine_code)?;
+
+    for &(address, value) in &config.pokes {
+        computer
+            .poke(address, value)
+            .ok_or_else(|| format!("RAM[{}] is out of range", address))?;
+    }
+
+    let stop = computer.run(config.max_cycles)?;
+    match stop {
+        Stop::Halted => println!("Halted after {} cycles", computer.cycles()),
+        Stop::CycleLimit => println!("Stopped at cycle limit ({})", computer.cycles()),
+    }
+
+    println!("A={} D={} PC={}", computer.a(), computer.d(), computer.pc());
+    for &address in &config.peeks {
+        println!("RAM[{}]={}", address, computer.peek(address) as i16);
+    }
+
+    Ok(())
+}
+
+fn run_script(filepath: &str) -> Result<(), Box<dyn Error>> {
+    let outcome = TestRunner::run_file(Path::new(filepath), &mut FileLoader)?;
+
+    for message in &outcome.echo {
+        println!("{}", message);
+    }
+
+    if let Some(output_file) = &outcome.output_file {
+        fs::write(output_file, &outcome.output)?;
+    }
+
+    match (&outcome.compare_to, &outcome.mismatch) {
+        (_, Some(mismatch)) => Err(format!(
+            "Comparison failure at line {}, column {}: expected `{}`, got `{}`",
+            mismatch.row, mismatch.column, mismatch.expected, mismatch.actual
+        )
+        .into()),
+        (Some(_), None) => {
+            println!("End of script - Comparison ended successfully");
+            Ok(())
+        }
+        (None, None) => {
+            println!("End of script");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.into_iter()
+    }
+
+    #[test]
+    fn config() {
+        let config = Config::new(args(&[
+            "rust", "Max.hack", "--cycles", "50", "--set", "0=3", "--set", "1=-1", "--show", "2",
+        ]))
+        .unwrap();
+
+        assert_eq!(config.filepath, "Max.hack");
+        assert_eq!(config.max_cycles, 50);
+        assert_eq!(config.pokes, vec![(0, 3), (1, 0xFFFF)]);
+        assert_eq!(config.peeks, vec![2]);
+
+        assert!(Config::new(args(&["rust", "--cycles"])).is_err());
+        assert!(Config::new(args(&["rust", "Max.hack", "--set", "0=70000"])).is_err());
+        assert!(Config::new(args(&["rust", "Max.hack", "--set", "30000=5"])).is_err());
+        assert!(Config::new(args(&["rust"])).is_err());
+    }
+}
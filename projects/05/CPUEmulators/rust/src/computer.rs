@@ -0,0 +1,323 @@
+use crate::error::EmulatorError;
+use crate::memory::Memory;
+use hack_assembler::instruction::{Comp, Dest, Instruction, Jump};
+
+pub const ROM_SIZE: usize = 32768;
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    /// The program reached an `@X; 0;JMP` loop onto itself.
+    Halted,
+    CycleLimit,
+}
+
+/// The Hack computer from projects/05: a CPU with A, D and PC registers
+/// wired to 32K of instruction ROM and the data memory.
+pub struct Computer {
+    a: u16,
+    d: u16,
+    pc: u16,
+    cycles: u64,
+    rom: Vec<u16>,
+    memory: Memory,
+}
+
+impl Computer {
+    pub fn new() -> Computer {
+        Computer {
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+            rom: vec![0; ROM_SIZE],
+            memory: Memory::new(),
+        }
+    }
+
+    pub fn load_rom(&mut self, program: &[u16]) -> Result<(), EmulatorError> {
+        if program.len() > ROM_SIZE {
+            return Err(EmulatorError::ProgramTooLarge(program.len()));
+        }
+
+        self.rom = vec![0; ROM_SIZE];
+        self.rom[..program.len()].copy_from_slice(program);
+        self.reset();
+        Ok(())
+    }
+
+    /// Loads the ASCII `.hack` format, one 16 character binary word per line.
+    pub fn load_hack(&mut self, machine_code: &str) -> Result<(), EmulatorError> {
+        let mut program = Vec::new();
+
+        for (index, line) in machine_code.lines().enumerate() {
+            let word = line.trim();
+            if word.is_empty() {
+                continue;
+            }
+
+            match u16::from_str_radix(word, 2) {
+                Ok(value) if word.len() == 16 => program.push(value),
+                _ => {
+                    return Err(EmulatorError::InvalidWord {
+                        line: index + 1,
+                        text: String::from(word),
+                    })
+                }
+            }
+        }
+
+        self.load_rom(&program)
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.cycles = 0;
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Reads the data memory, returning 0 for addresses outside of it.
+    pub fn peek(&self, address: u16) -> u16 {
+        self.memory.read(address).unwrap_or(0)
+    }
+
+    /// Writes RAM like `Memory::write`, returning `None` past the keyboard.
+    pub fn poke(&mut self, address: u16, value: u16) -> Option<()> {
+        self.memory.write(address, value)
+    }
+
+    pub fn set_a(&mut self, value: u16) {
+        self.a = value;
+    }
+
+    pub fn set_d(&mut self, value: u16) {
+        self.d = value;
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value;
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let pc = self.pc;
+        let instruction = self.rom[pc as usize % ROM_SIZE];
+        self.cycles += 1;
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = pc.wrapping_add(1);
+            return Ok(());
+        }
+
+        let address = self.a;
+        let y = if instruction & 0x1000 != 0 {
+            self.memory.checked_read(pc, address)?
+        } else {
+            self.a
+        };
+        let out = Computer::alu(self.d, y, (instruction >> 6) & 0x3F);
+
+        if instruction & 0x08 != 0 {
+            self.memory.checked_write(pc, address, out)?;
+        }
+        if instruction & 0x10 != 0 {
+            self.d = out;
+        }
+        if instruction & 0x20 != 0 {
+            self.a = out;
+        }
+
+        let negative = out & 0x8000 != 0;
+        let zero = out == 0;
+        let jump = (instruction & 0x04 != 0 && negative)
+            || (instruction & 0x02 != 0 && zero)
+            || (instruction & 0x01 != 0 && !negative && !zero);
+
+        self.pc = if jump { address } else { pc.wrapping_add(1) };
+        Ok(())
+    }
+
+    /// Runs until the program halts or `max_cycles` instructions have been
+    /// executed.
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop, EmulatorError> {
+        for _ in 0..max_cycles {
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            self.step()?;
+        }
+
+        if self.is_halted() {
+            Ok(Stop::Halted)
+        } else {
+            Ok(Stop::CycleLimit)
+        }
+    }
+
+    /// True when PC sits on `@X; 0;JMP` where X is the address of the
+    /// A-instruction itself, which is how Hack programs end.
+    fn is_halted(&self) -> bool {
+        let pc = self.pc as usize % ROM_SIZE;
+        let load = self.rom[pc];
+        let jump = self.rom[(pc + 1) % ROM_SIZE];
+
+        let infinite_loop = Instruction::Compute {
+            dest: Dest::NONE,
+            comp: Comp::Zero,
+            jump: Jump::Jmp,
+        };
+        load as usize == pc && jump == infinite_loop.encode()
+    }
+
+    /// The Hack ALU, driven by the zx, nx, zy, ny, f and no control bits.
+    fn alu(x: u16, y: u16, control: u16) -> u16 {
+        let bit = |n: u16| control & (1 << (5 - n)) != 0;
+
+        let x = if bit(0) { 0 } else { x };
+        let x = if bit(1) { !x } else { x };
+        let y = if bit(2) { 0 } else { y };
+        let y = if bit(3) { !y } else { y };
+        let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+
+        if bit(5) {
+            !out
+        } else {
+            out
+        }
+    }
+}
+
+impl Default for Computer {
+    fn default() -> Computer {
+        Computer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::SCREEN;
+    use hack_assembler::assembler::Assembler;
+    use std::fs;
+
+    fn load_asm(computer: &mut Computer, path: &str) {
+        let source = fs::read_to_string(path).unwrap();
+        let machine_code = Assembler::assemble(source).unwrap();
+        computer.load_hack(&machine_code).unwrap();
+    }
+
+    #[test]
+    fn alu() {
+        let x = 12;
+        let y = 5;
+
+        assert_eq!(Computer::alu(x, y, 0b101010), 0);
+        assert_eq!(Computer::alu(x, y, 0b111111), 1);
+        assert_eq!(Computer::alu(x, y, 0b111010), 0xFFFF);
+        assert_eq!(Computer::alu(x, y, 0b001100), x);
+        assert_eq!(Computer::alu(x, y, 0b001111), (-12i16) as u16);
+        assert_eq!(Computer::alu(x, y, 0b011111), 13);
+        assert_eq!(Computer::alu(x, y, 0b000010), 17);
+        assert_eq!(Computer::alu(x, y, 0b010011), 7);
+        assert_eq!(Computer::alu(x, y, 0b000111), (-7i16) as u16);
+        assert_eq!(Computer::alu(x, y, 0b000000), 4);
+        assert_eq!(Computer::alu(x, y, 0b010101), 13);
+    }
+
+    #[test]
+    fn step() {
+        let mut computer = Computer::new();
+        // @7, D=A, @3, M=D, D;JGT
+        computer.load_rom(&[7, 0xEC10, 3, 0xE308, 0xE301]).unwrap();
+
+        computer.step().unwrap();
+        assert_eq!((computer.a(), computer.pc()), (7, 1));
+
+        computer.step().unwrap();
+        assert_eq!(computer.d(), 7);
+
+        computer.step().unwrap();
+        computer.step().unwrap();
+        assert_eq!(computer.peek(3), 7);
+
+        computer.step().unwrap();
+        assert_eq!(computer.pc(), 3);
+        assert_eq!(computer.cycles(), 5);
+    }
+
+    #[test]
+    fn invalid_address() {
+        let mut computer = Computer::new();
+        // @32767, M=1
+        computer.load_rom(&[0x7FFF, 0xEFC8]).unwrap();
+
+        computer.step().unwrap();
+        assert_eq!(
+            computer.step(),
+            Err(EmulatorError::InvalidAddress {
+                pc: 1,
+                address: 0x7FFF
+            })
+        );
+    }
+
+    #[test]
+    fn run_max() {
+        let mut computer = Computer::new();
+        load_asm(&mut computer, "../../../06/max/Max.asm");
+
+        computer.poke(0, 3);
+        computer.poke(1, 9);
+        assert_eq!(computer.run(1000), Ok(Stop::Halted));
+        assert_eq!(computer.peek(2), 9);
+    }
+
+    #[test]
+    fn run_mult() {
+        let mut computer = Computer::new();
+        load_asm(&mut computer, "../../../04/mult/mult.asm");
+
+        computer.poke(0, 6);
+        computer.poke(1, 7);
+        assert_eq!(computer.run(1000), Ok(Stop::Halted));
+        assert_eq!(computer.peek(2), 42);
+    }
+
+    #[test]
+    fn cycle_limit() {
+        let mut computer = Computer::new();
+        load_asm(&mut computer, "../../../06/rect/Rect.asm");
+
+        computer.poke(0, 4);
+        assert_eq!(computer.run(10), Ok(Stop::CycleLimit));
+        assert_eq!(computer.cycles(), 10);
+
+        assert_eq!(computer.run(1000), Ok(Stop::Halted));
+        assert_eq!(computer.peek(SCREEN), 0xFFFF);
+        assert_eq!(computer.peek(SCREEN + 96), 0xFFFF);
+        assert_eq!(computer.peek(SCREEN + 128), 0);
+    }
+}
@@ -0,0 +1,63 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum EmulatorError {
+    InvalidAddress { pc: u16, address: u16 },
+    InvalidWord { line: usize, text: String },
+    ProgramTooLarge(usize),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidAddress { pc, address } => write!(
+                f,
+                "instruction at ROM[{}] accessed invalid memory address {}",
+                pc, address
+            ),
+            EmulatorError::InvalidWord { line, text } => {
+                write!(f, "line {}: invalid machine word `{}`", line, text)
+            }
+            EmulatorError::ProgramTooLarge(size) => {
+                write!(f, "program of {} words does not fit in ROM", size)
+            }
+        }
+    }
+}
+
+impl Error for EmulatorError {}
+
+#[derive(Debug, PartialEq)]
+pub enum ScriptError {
+    Syntax { line: usize, message: String },
+    Runtime { line: usize, message: String },
+}
+
+impl ScriptError {
+    pub fn syntax(line: usize, message: &str) -> ScriptError {
+        ScriptError::Syntax {
+            line,
+            message: String::from(message),
+        }
+    }
+
+    pub fn runtime(line: usize, message: &str) -> ScriptError {
+        ScriptError::Runtime {
+            line,
+            message: String::from(message),
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Syntax { line, message } => {
+                write!(f, "line {}: syntax error: {}", line, message)
+            }
+            ScriptError::Runtime { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ScriptError {}
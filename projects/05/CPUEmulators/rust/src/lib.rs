@@ -0,0 +1,6 @@
+pub mod cli;
+pub mod computer;
+pub mod error;
+pub mod memory;
+pub mod test_runner;
+pub mod test_script;
@@ -0,0 +1,92 @@
+use crate::error::EmulatorError;
+
+pub const RAM_SIZE: usize = 16384;
+pub const SCREEN: u16 = 16384;
+pub const SCREEN_SIZE: usize = 8192;
+pub const KBD: u16 = 24576;
+
+/// The Hack data memory: 16K of RAM followed by the memory mapped
+/// screen and keyboard.
+pub struct Memory {
+    ram: Vec<u16>,
+    screen: Vec<u16>,
+    keyboard: u16,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory {
+            ram: vec![0; RAM_SIZE],
+            screen: vec![0; SCREEN_SIZE],
+            keyboard: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> Option<u16> {
+        match address {
+            a if a < SCREEN => Some(self.ram[a as usize]),
+            a if a < KBD => Some(self.screen[(a - SCREEN) as usize]),
+            KBD => Some(self.keyboard),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u16) -> Option<()> {
+        match address {
+            a if a < SCREEN => self.ram[a as usize] = value,
+            a if a < KBD => self.screen[(a - SCREEN) as usize] = value,
+            KBD => self.keyboard = value,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    pub fn checked_read(&self, pc: u16, address: u16) -> Result<u16, EmulatorError> {
+        self.read(address)
+            .ok_or(EmulatorError::InvalidAddress { pc, address })
+    }
+
+    pub fn checked_write(
+        &mut self,
+        pc: u16,
+        address: u16,
+        value: u16,
+    ) -> Result<(), EmulatorError> {
+        self.write(address, value)
+            .ok_or(EmulatorError::InvalidAddress { pc, address })
+    }
+
+    pub fn screen(&self) -> &[u16] {
+        &self.screen
+    }
+
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.keyboard = key;
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_map() {
+        let mut memory = Memory::new();
+        memory.write(0, 42).unwrap();
+        memory.write(SCREEN + 1, 7).unwrap();
+        memory.set_keyboard(65);
+
+        assert_eq!(memory.read(0), Some(42));
+        assert_eq!(memory.read(SCREEN + 1), Some(7));
+        assert_eq!(memory.screen()[1], 7);
+        assert_eq!(memory.read(KBD), Some(65));
+        assert_eq!(memory.read(KBD + 1), None);
+        assert_eq!(memory.write(KBD + 1, 0), None);
+    }
+}
@@ -0,0 +1,423 @@
+use crate::computer::Computer;
+use crate::error::ScriptError;
+use crate::test_script::{
+    Command, Comparison, Condition, Format, OutputColumn, Radix, Statement, TestScript, Variable,
+};
+use hack_assembler::assembler::Assembler;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Turns the file named by a script's `load` command into `.hack` machine code.
+pub trait ProgramLoader {
+    fn load(&mut self, path: &Path) -> Result<String, Box<dyn Error>>;
+}
+
+/// Assembles `.asm` files with our assembler. A `.hack` file is rebuilt from
+/// the `.asm` next to it when there is one, and read as is otherwise.
+pub struct FileLoader;
+
+impl ProgramLoader for FileLoader {
+    fn load(&mut self, path: &Path) -> Result<String, Box<dyn Error>> {
+        let asm_path = resolve(&path.with_extension("asm"));
+
+        if asm_path.is_file() {
+            let source = fs::read_to_string(&asm_path)?;
+            let machine_code = Assembler::assemble_file(&asm_path.to_string_lossy(), source)?;
+            Ok(machine_code)
+        } else {
+            Ok(fs::read_to_string(resolve(path))?)
+        }
+    }
+}
+
+/// Course scripts were written on case insensitive file systems, e.g.
+/// `load Mult.hack` for `mult.hack`, so fall back to a case insensitive match.
+pub fn resolve(path: &Path) -> PathBuf {
+    if path.exists() {
+        return path.to_path_buf();
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    dir.read_dir()
+        .ok()
+        .and_then(|entries| {
+            entries.filter_map(|entry| entry.ok()).find(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(&name)
+            })
+        })
+        .map_or_else(|| path.to_path_buf(), |entry| entry.path())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub row: usize,
+    pub column: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+pub struct TestOutcome {
+    pub output: String,
+    pub output_file: Option<PathBuf>,
+    pub compare_to: Option<PathBuf>,
+    pub mismatch: Option<Mismatch>,
+    pub echo: Vec<String>,
+}
+
+pub struct TestRunner<'a, L: ProgramLoader> {
+    computer: Computer,
+    loader: &'a mut L,
+    dir: PathBuf,
+    half_cycles: u64,
+    output_list: Vec<OutputColumn>,
+    output: String,
+    output_file: Option<PathBuf>,
+    compare_to: Option<PathBuf>,
+    echo: Vec<String>,
+}
+
+impl<'a, L: ProgramLoader> TestRunner<'a, L> {
+    pub fn run_file(path: &Path, loader: &'a mut L) -> Result<TestOutcome, ScriptError> {
+        let source = fs::read_to_string(path)
+            .map_err(|error| ScriptError::runtime(0, &format!("{}: {}", path.display(), error)))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+        TestRunner::run(&source, dir, loader)
+    }
+
+    pub fn run(source: &str, dir: PathBuf, loader: &'a mut L) -> Result<TestOutcome, ScriptError> {
+        let statements = TestScript::parse(source)?;
+        let mut runner = TestRunner {
+            computer: Computer::new(),
+            loader,
+            dir,
+            half_cycles: 0,
+            output_list: Vec::new(),
+            output: String::new(),
+            output_file: None,
+            compare_to: None,
+            echo: Vec::new(),
+        };
+
+        runner.execute_all(&statements)?;
+
+        let mismatch = match &runner.compare_to {
+            Some(path) => {
+                let expected = fs::read_to_string(path).map_err(|error| {
+                    ScriptError::runtime(0, &format!("{}: {}", path.display(), error))
+                })?;
+                compare(&runner.output, &expected)
+            }
+            None => None,
+        };
+
+        Ok(TestOutcome {
+            output: runner.output,
+            output_file: runner.output_file,
+            compare_to: runner.compare_to,
+            mismatch,
+            echo: runner.echo,
+        })
+    }
+
+    fn execute_all(&mut self, statements: &[Statement]) -> Result<(), ScriptError> {
+        for statement in statements {
+            self.execute(statement)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, statement: &Statement) -> Result<(), ScriptError> {
+        let line = statement.line;
+
+        match &statement.command {
+            Command::Load(name) => {
+                let path = self.dir.join(name);
+                let machine_code = self.loader.load(&path).map_err(|error| {
+                    ScriptError::runtime(line, &format!("could not load {}: {}", name, error))
+                })?;
+                self.computer
+                    .load_hack(&machine_code)
+                    .map_err(|error| ScriptError::runtime(line, &error.to_string()))?;
+                self.half_cycles = 0;
+            }
+            Command::OutputFile(name) => self.output_file = Some(self.dir.join(name)),
+            Command::CompareTo(name) => self.compare_to = Some(resolve(&self.dir.join(name))),
+            Command::OutputList(columns) => {
+                self.output_list = columns.clone();
+                let header = self.row(|column| center(&column.name, column.format));
+                self.output.push_str(&header);
+            }
+            Command::Set(variable, value) => self.set(line, *variable, *value)?,
+            Command::Tick => {
+                self.step(line)?;
+                self.half_cycles += 1;
+            }
+            Command::Tock => self.half_cycles += 1,
+            Command::TickTock => {
+                self.step(line)?;
+                self.half_cycles += 2;
+            }
+            Command::Output => {
+                let row = self.row(|column| self.format(column));
+                self.output.push_str(&row);
+            }
+            Command::Echo(message) => self.echo.push(message.clone()),
+            Command::ClearEcho => self.echo.clear(),
+            Command::Repeat(Some(count), body) => {
+                for _ in 0..*count {
+                    self.execute_all(body)?;
+                }
+            }
+            Command::Repeat(None, _) => {
+                return Err(ScriptError::runtime(
+                    line,
+                    "`repeat` without a count only ends interactively",
+                ))
+            }
+            Command::While(condition, body) => {
+                while self.holds(condition) {
+                    self.execute_all(body)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step(&mut self, line: usize) -> Result<(), ScriptError> {
+        self.computer
+            .step()
+            .map_err(|error| ScriptError::runtime(line, &error.to_string()))
+    }
+
+    fn set(&mut self, line: usize, variable: Variable, value: u16) -> Result<(), ScriptError> {
+        match variable {
+            Variable::A => self.computer.set_a(value),
+            Variable::D => self.computer.set_d(value),
+            Variable::PC => self.computer.set_pc(value),
+            Variable::Ram(address) => {
+                if self.computer.memory_mut().write(address, value).is_none() {
+                    return Err(ScriptError::runtime(
+                        line,
+                        &format!("RAM[{}] is out of range", address),
+                    ));
+                }
+            }
+            Variable::Time => return Err(ScriptError::runtime(line, "`time` is read only")),
+        }
+        Ok(())
+    }
+
+    fn value(&self, variable: Variable) -> u16 {
+        match variable {
+            Variable::A => self.computer.a(),
+            Variable::D => self.computer.d(),
+            Variable::PC => self.computer.pc(),
+            Variable::Ram(address) => self.computer.peek(address),
+            Variable::Time => (self.half_cycles / 2) as u16,
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        let value = self.value(condition.variable) as i16;
+        let expected = condition.value as i16;
+
+        match condition.comparison {
+            Comparison::Equal => value == expected,
+            Comparison::NotEqual => value != expected,
+            Comparison::Less => value < expected,
+            Comparison::Greater => value > expected,
+            Comparison::LessOrEqual => value <= expected,
+            Comparison::GreaterOrEqual => value >= expected,
+        }
+    }
+
+    fn format(&self, column: &OutputColumn) -> String {
+        let Format {
+            radix,
+            left,
+            width,
+            right,
+        } = column.format;
+        let value = self.value(column.variable);
+
+        let text = match (column.variable, radix) {
+            (Variable::Time, _) => {
+                let plus = if self.half_cycles % 2 == 1 { "+" } else { "" };
+                format!("{:<width$}", format!("{}{}", value, plus), width = width)
+            }
+            (_, Radix::Decimal) => format!("{:>width$}", value as i16, width = width),
+            (_, Radix::Binary) => {
+                let bits = format!("{:016b}", value);
+                bits[16 - width.min(16)..].to_string()
+            }
+            (_, Radix::Hex) => {
+                let digits = format!("{:04X}", value);
+                digits[4 - width.min(4)..].to_string()
+            }
+            (_, Radix::String) => format!("{:<width$}", value, width = width),
+        };
+
+        format!("{}{}{}", " ".repeat(left), text, " ".repeat(right))
+    }
+
+    fn row<F>(&self, cell: F) -> String
+    where
+        F: Fn(&OutputColumn) -> String,
+    {
+        let mut row = String::from("|");
+        for column in &self.output_list {
+            row.push_str(&cell(column));
+            row.push('|');
+        }
+        row.push('\n');
+        row
+    }
+}
+
+fn center(name: &str, format: Format) -> String {
+    let width = format.left + format.width + format.right;
+    if name.len() >= width {
+        return name[..width].to_string();
+    }
+
+    let left = (width - name.len()) / 2;
+    format!(
+        "{}{}{}",
+        " ".repeat(left),
+        name,
+        " ".repeat(width - name.len() - left)
+    )
+}
+
+fn cells(line: &str) -> Vec<&str> {
+    line.trim()
+        .trim_matches('|')
+        .split('|')
+        .map(|cell| cell.trim())
+        .collect()
+}
+
+/// Compares output rows cell by cell, ignoring padding, and returns the first
+/// difference using 1-based row and column numbers.
+pub fn compare(output: &str, expected: &str) -> Option<Mismatch> {
+    let actual_rows: Vec<&str> = output.lines().collect();
+    let expected_rows: Vec<&str> = expected.lines().filter(|l| !l.trim().is_empty()).collect();
+
+    for row in 0..actual_rows.len().max(expected_rows.len()) {
+        let actual = actual_rows.get(row).map_or(Vec::new(), |line| cells(line));
+        let expected = expected_rows
+            .get(row)
+            .map_or(Vec::new(), |line| cells(line));
+
+        for column in 0..actual.len().max(expected.len()) {
+            let actual_cell = actual.get(column).copied().unwrap_or("");
+            let expected_cell = expected.get(column).copied().unwrap_or("");
+
+            if actual_cell != expected_cell {
+                return Some(Mismatch {
+                    row: row + 1,
+                    column: column + 1,
+                    expected: String::from(expected_cell),
+                    actual: String::from(actual_cell),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticLoader(String);
+
+    impl ProgramLoader for StaticLoader {
+        fn load(&mut self, _path: &Path) -> Result<String, Box<dyn Error>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn loader(program: &str) -> StaticLoader {
+        StaticLoader(Assembler::assemble(program.to_string()).unwrap())
+    }
+
+    #[test]
+    fn output() {
+        let mut loader = loader("@R0\nD=M\n@R1\nM=D+1\n(END)\n@END\n0;JMP");
+        let script = "
+            load Test.asm,
+            output-list RAM[0]%D2.6.2 RAM[1]%D1.6.1 RAM[1]%B1.8.1 RAM[1]%X1.4.1 time%S1.4.1;
+            set RAM[0] -5;
+            repeat 4 { ticktock; }
+            output;
+        ";
+
+        let outcome = TestRunner::run(script, PathBuf::new(), &mut loader).unwrap();
+        assert_eq!(
+            outcome.output,
+            [
+                "|  RAM[0]  | RAM[1] |  RAM[1]  |RAM[1]| time |",
+                "|      -5  |     -4 | 11111100 | FFFC | 4    |",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(outcome.mismatch, None);
+    }
+
+    #[test]
+    fn while_loop() {
+        let mut loader = loader("@R0\nM=M-1\n@0\n0;JMP");
+        let script = "
+            load Test.asm,
+            set RAM[0] 3;
+            while RAM[0] > 0 { ticktock; ticktock; }
+            output-list RAM[0]%D1.2.1 time;
+            output;
+        ";
+
+        let outcome = TestRunner::run(script, PathBuf::new(), &mut loader).unwrap();
+        assert_eq!(outcome.output.lines().nth(1), Some("|  0 | 10     |"));
+    }
+
+    #[test]
+    fn compare_rows() {
+        let expected = "|  RAM[0]  |\r\n|      1  |\r\n|      2  |\r\n";
+
+        assert_eq!(compare("|RAM[0]|\n|1|\n|2|\n", expected), None);
+        assert_eq!(
+            compare("|RAM[0]|\n|1|\n|3|\n", expected),
+            Some(Mismatch {
+                row: 3,
+                column: 1,
+                expected: String::from("2"),
+                actual: String::from("3"),
+            })
+        );
+        assert_eq!(compare("|RAM[0]|\n|1|\n", expected).map(|m| m.row), Some(3));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        // The assembler rejects `@32767` followed by `M=1`, so go through D
+        let mut loader = loader("@32767\nD=A\nA=D\nM=1");
+        let script = "load Test.asm;\nrepeat 4 {\n  ticktock;\n}";
+
+        match TestRunner::run(script, PathBuf::new(), &mut loader) {
+            Err(ScriptError::Runtime { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+}
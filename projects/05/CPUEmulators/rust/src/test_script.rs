@@ -0,0 +1,511 @@
+use crate::error::ScriptError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Variable {
+    A,
+    D,
+    PC,
+    Ram(u16),
+    Time,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Radix {
+    Binary,
+    Decimal,
+    Hex,
+    String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Format {
+    pub radix: Radix,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OutputColumn {
+    pub name: String,
+    pub variable: Variable,
+    pub format: Format,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Condition {
+    pub variable: Variable,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    Load(String),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<OutputColumn>),
+    Set(Variable, u16),
+    Tick,
+    Tock,
+    TickTock,
+    Output,
+    Echo(String),
+    ClearEcho,
+    Repeat(Option<usize>, Vec<Statement>),
+    While(Condition, Vec<Statement>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement {
+    pub line: usize,
+    pub command: Command,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Terminator,
+    Open,
+    Close,
+}
+
+const DEFAULT_FORMAT: Format = Format {
+    radix: Radix::Decimal,
+    left: 1,
+    width: 6,
+    right: 1,
+};
+
+/// Parser for the nand2tetris test script language (`.tst` files).
+pub struct TestScript {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl TestScript {
+    pub fn parse(source: &str) -> Result<Vec<Statement>, ScriptError> {
+        let mut script = TestScript {
+            tokens: TestScript::tokenize(source)?,
+            position: 0,
+        };
+
+        let statements = script.parse_block()?;
+        match script.tokens.get(script.position) {
+            Some((line, _)) => Err(ScriptError::syntax(*line, "unexpected `}`")),
+            None => Ok(statements),
+        }
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ScriptError> {
+        let mut tokens = Vec::new();
+        let mut chars = source.chars().peekable();
+        let mut line = 1;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => line += 1,
+                c if c.is_whitespace() => {}
+                ',' | ';' | '!' => tokens.push((line, Token::Terminator)),
+                '{' => tokens.push((line, Token::Open)),
+                '}' => tokens.push((line, Token::Close)),
+                '/' if chars.peek() == Some(&'/') => {
+                    while chars.peek().is_some_and(|&c| c != '\n') {
+                        chars.next();
+                    }
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    let start = line;
+                    chars.next();
+                    let mut previous = ' ';
+                    loop {
+                        match chars.next() {
+                            Some('/') if previous == '*' => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                previous = c;
+                            }
+                            None => return Err(ScriptError::syntax(start, "unterminated comment")),
+                        }
+                    }
+                }
+                '"' => {
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => text.push(c),
+                            None => return Err(ScriptError::syntax(line, "unterminated string")),
+                        }
+                    }
+                    tokens.push((line, Token::Text(text)));
+                }
+                _ => {
+                    let mut word = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || ",;!{}\"".contains(c) {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    tokens.push((line, Token::Word(word)));
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Statement>, ScriptError> {
+        let mut statements = Vec::new();
+
+        while let Some((line, token)) = self.tokens.get(self.position) {
+            let line = *line;
+            let name = match token {
+                Token::Close => break,
+                Token::Terminator => {
+                    self.position += 1;
+                    continue;
+                }
+                Token::Word(word) => word.clone(),
+                _ => return Err(ScriptError::syntax(line, "expected a command")),
+            };
+            self.position += 1;
+
+            let command = match name.as_str() {
+                "repeat" => {
+                    let count = match self.peek_word() {
+                        Some(count) => {
+                            let count = count
+                                .parse()
+                                .map_err(|_| ScriptError::syntax(line, "invalid repeat count"))?;
+                            self.position += 1;
+                            Some(count)
+                        }
+                        None => None,
+                    };
+                    Command::Repeat(count, self.parse_body(line)?)
+                }
+                "while" => {
+                    let args = self.words_until_open();
+                    let condition = TestScript::parse_condition(line, &args)?;
+                    Command::While(condition, self.parse_body(line)?)
+                }
+                _ => {
+                    let args = self.arguments();
+                    TestScript::parse_command(line, &name, args)?
+                }
+            };
+
+            statements.push(Statement { line, command });
+        }
+
+        Ok(statements)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.position) {
+            Some((_, Token::Word(word))) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn words_until_open(&mut self) -> Vec<String> {
+        let mut words = Vec::new();
+        while let Some(word) = self.peek_word() {
+            words.push(word.to_string());
+            self.position += 1;
+        }
+        words
+    }
+
+    /// Collects the arguments of a simple command, up to its terminator.
+    fn arguments(&mut self) -> Vec<Token> {
+        let mut args = Vec::new();
+        while let Some((_, token)) = self.tokens.get(self.position) {
+            match token {
+                Token::Word(word) => args.push(Token::Word(word.clone())),
+                Token::Text(text) => args.push(Token::Text(text.clone())),
+                _ => break,
+            }
+            self.position += 1;
+        }
+        args
+    }
+
+    fn parse_body(&mut self, line: usize) -> Result<Vec<Statement>, ScriptError> {
+        match self.tokens.get(self.position) {
+            Some((_, Token::Open)) => self.position += 1,
+            _ => return Err(ScriptError::syntax(line, "expected `{`")),
+        }
+
+        let body = self.parse_block()?;
+
+        match self.tokens.get(self.position) {
+            Some((_, Token::Close)) => {
+                self.position += 1;
+                Ok(body)
+            }
+            _ => Err(ScriptError::syntax(line, "expected `}`")),
+        }
+    }
+
+    fn parse_command(line: usize, name: &str, args: Vec<Token>) -> Result<Command, ScriptError> {
+        let words: Vec<&str> = args
+            .iter()
+            .filter_map(|arg| match arg {
+                Token::Word(word) => Some(word.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let single = |what: &str| match words.as_slice() {
+            [word] => Ok(word.to_string()),
+            _ => Err(ScriptError::syntax(line, &format!("expected {}", what))),
+        };
+
+        let command = match name {
+            "load" => Command::Load(single("a file name")?),
+            "output-file" => Command::OutputFile(single("a file name")?),
+            "compare-to" => Command::CompareTo(single("a file name")?),
+            "output-list" => Command::OutputList(
+                words
+                    .iter()
+                    .map(|column| TestScript::parse_column(line, column))
+                    .collect::<Result<_, _>>()?,
+            ),
+            "set" => match words.as_slice() {
+                [variable, value] => Command::Set(
+                    TestScript::parse_variable(line, variable)?,
+                    TestScript::parse_value(line, value)?,
+                ),
+                _ => return Err(ScriptError::syntax(line, "expected `set VARIABLE VALUE`")),
+            },
+            "tick" => Command::Tick,
+            "tock" => Command::Tock,
+            "ticktock" => Command::TickTock,
+            "output" => Command::Output,
+            "echo" => match args.as_slice() {
+                [Token::Text(text)] | [Token::Word(text)] => Command::Echo(text.clone()),
+                _ => return Err(ScriptError::syntax(line, "expected a message")),
+            },
+            "clear-echo" => Command::ClearEcho,
+            _ => {
+                return Err(ScriptError::syntax(
+                    line,
+                    &format!("unknown command `{}`", name),
+                ))
+            }
+        };
+
+        Ok(command)
+    }
+
+    fn parse_variable(line: usize, name: &str) -> Result<Variable, ScriptError> {
+        let variable = match name {
+            "A" => Variable::A,
+            "D" => Variable::D,
+            "PC" => Variable::PC,
+            "time" => Variable::Time,
+            _ => {
+                let address = name
+                    .strip_prefix("RAM[")
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(|address| address.parse().ok())
+                    .ok_or_else(|| {
+                        ScriptError::syntax(line, &format!("unknown variable `{}`", name))
+                    })?;
+                Variable::Ram(address)
+            }
+        };
+
+        Ok(variable)
+    }
+
+    /// Values are decimal by default, or prefixed with `%B`, `%X` or `%D`.
+    fn parse_value(line: usize, value: &str) -> Result<u16, ScriptError> {
+        let parsed = if let Some(binary) = value.strip_prefix("%B") {
+            u16::from_str_radix(binary, 2).ok()
+        } else if let Some(hex) = value.strip_prefix("%X") {
+            u16::from_str_radix(hex, 16).ok()
+        } else {
+            let decimal = value.strip_prefix("%D").unwrap_or(value);
+            decimal
+                .parse::<i32>()
+                .ok()
+                .filter(|n| (-32768..=65535).contains(n))
+                .map(|n| n as u16)
+        };
+
+        parsed.ok_or_else(|| ScriptError::syntax(line, &format!("invalid value `{}`", value)))
+    }
+
+    fn parse_column(line: usize, column: &str) -> Result<OutputColumn, ScriptError> {
+        let mut parts = column.splitn(2, '%');
+        let name = parts.next().unwrap_or("");
+        let variable = TestScript::parse_variable(line, name)?;
+
+        let format = match parts.next() {
+            Some(format) => TestScript::parse_format(format).ok_or_else(|| {
+                ScriptError::syntax(line, &format!("invalid format `{}`", column))
+            })?,
+            None => DEFAULT_FORMAT,
+        };
+
+        Ok(OutputColumn {
+            name: String::from(name),
+            variable,
+            format,
+        })
+    }
+
+    fn parse_format(format: &str) -> Option<Format> {
+        let radix = match format.chars().next()? {
+            'B' => Radix::Binary,
+            'D' => Radix::Decimal,
+            'X' => Radix::Hex,
+            'S' => Radix::String,
+            _ => return None,
+        };
+
+        let sizes: Vec<usize> = format[1..]
+            .split('.')
+            .map(|size| size.parse().ok())
+            .collect::<Option<_>>()?;
+
+        match sizes.as_slice() {
+            [left, width, right] => Some(Format {
+                radix,
+                left: *left,
+                width: *width,
+                right: *right,
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_condition(line: usize, args: &[String]) -> Result<Condition, ScriptError> {
+        let (variable, comparison, value) = match args {
+            [variable, comparison, value] => (variable, comparison, value),
+            _ => {
+                return Err(ScriptError::syntax(
+                    line,
+                    "expected `while VARIABLE OP VALUE`",
+                ))
+            }
+        };
+
+        let comparison = match comparison.as_str() {
+            "=" => Comparison::Equal,
+            "<>" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return Err(ScriptError::syntax(line, "invalid comparison")),
+        };
+
+        Ok(Condition {
+            variable: TestScript::parse_variable(line, variable)?,
+            comparison,
+            value: TestScript::parse_value(line, value)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let script = "
+            // header comment
+            load SimpleAdd.asm,
+            output-file SimpleAdd.out,
+            output-list RAM[0]%D2.6.2 RAM[256];
+
+            set RAM[0] 256,  /* stack
+                                pointer */
+            set RAM[1] -1,
+            repeat 60 {
+              ticktock;
+            }
+            output;
+        ";
+
+        let statements = TestScript::parse(script).unwrap();
+        let commands: Vec<&Command> = statements.iter().map(|s| &s.command).collect();
+
+        assert_eq!(
+            commands,
+            vec![
+                &Command::Load(String::from("SimpleAdd.asm")),
+                &Command::OutputFile(String::from("SimpleAdd.out")),
+                &Command::OutputList(vec![
+                    OutputColumn {
+                        name: String::from("RAM[0]"),
+                        variable: Variable::Ram(0),
+                        format: Format {
+                            radix: Radix::Decimal,
+                            left: 2,
+                            width: 6,
+                            right: 2,
+                        },
+                    },
+                    OutputColumn {
+                        name: String::from("RAM[256]"),
+                        variable: Variable::Ram(256),
+                        format: DEFAULT_FORMAT,
+                    },
+                ]),
+                &Command::Set(Variable::Ram(0), 256),
+                &Command::Set(Variable::Ram(1), 0xFFFF),
+                &Command::Repeat(
+                    Some(60),
+                    vec![Statement {
+                        line: 11,
+                        command: Command::TickTock,
+                    }],
+                ),
+                &Command::Output,
+            ]
+        );
+        assert_eq!(statements[6].line, 13);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(TestScript::parse_value(1, "%B101"), Ok(5));
+        assert_eq!(TestScript::parse_value(1, "%XFF"), Ok(255));
+        assert_eq!(TestScript::parse_value(1, "%D-2"), Ok(0xFFFE));
+        assert!(TestScript::parse_value(1, "x").is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            TestScript::parse("load Foo.asm,\nfrobnicate;"),
+            Err(ScriptError::syntax(2, "unknown command `frobnicate`"))
+        );
+        assert_eq!(
+            TestScript::parse("repeat 3 {\n ticktock;\n"),
+            Err(ScriptError::syntax(1, "expected `}`"))
+        );
+        assert!(TestScript::parse("set RAM[x] 1;").is_err());
+    }
+}
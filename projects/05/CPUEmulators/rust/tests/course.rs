@@ -0,0 +1,122 @@
+//! Runs the course's CPU emulator test scripts against programs built by our
+//! own assembler and VM translator.
+
+use hack_assembler::assembler::Assembler;
+use hack_emulator::test_runner::{FileLoader, ProgramLoader, TestRunner};
+use std::{error::Error, path::Path};
+use vm_translator::file_reader::{FileData, FileReader};
+use vm_translator::vm_translator::Translator;
+
+/// Builds the `.asm` a script loads by translating every `.vm` file in the
+/// script's directory.
+struct VmLoader {
+    optimize: bool,
+}
+
+impl ProgramLoader for VmLoader {
+    fn load(&mut self, path: &Path) -> Result<String, Box<dyn Error>> {
+        let dir = path.parent().ok_or("script has no directory")?;
+        let file_container = FileReader::process(dir)?;
+
+        let mut translator = Translator::with_bootstrap(file_container.bootstrap());
+        for FileData { filename, contents } in file_container.files {
+            translator.translate(filename, contents)?;
+        }
+
+        let assembly_code = if self.optimize {
+            translator.output_optimized()?.0
+        } else {
+            translator.output()?
+        };
+
+        let machine_code = Assembler::assemble(assembly_code)?;
+        Ok(machine_code)
+    }
+}
+
+fn run<L: ProgramLoader>(script: &str, loader: &mut L) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../..")
+        .join(script);
+
+    let outcome =
+        TestRunner::run_file(&path, loader).unwrap_or_else(|error| panic!("{}: {}", script, error));
+
+    if let Some(mismatch) = outcome.mismatch {
+        panic!(
+            "{}: comparison failure at line {}, column {}: expected `{}`, got `{}`",
+            script, mismatch.row, mismatch.column, mismatch.expected, mismatch.actual
+        );
+    }
+}
+
+/// VM programs must behave the same with and without the optimizer.
+fn run_vm(script: &str) {
+    run(script, &mut VmLoader { optimize: false });
+    run(script, &mut VmLoader { optimize: true });
+}
+
+#[test]
+fn mult() {
+    run("04/mult/Mult.tst", &mut FileLoader);
+}
+
+#[test]
+fn fill_automatic() {
+    run("04/fill/FillAutomatic.tst", &mut FileLoader);
+}
+
+#[test]
+fn simple_add() {
+    run_vm("07/StackArithmetic/SimpleAdd/SimpleAdd.tst");
+}
+
+#[test]
+fn stack_test() {
+    run_vm("07/StackArithmetic/StackTest/StackTest.tst");
+}
+
+#[test]
+fn basic_test() {
+    run_vm("07/MemoryAccess/BasicTest/BasicTest.tst");
+}
+
+#[test]
+fn pointer_test() {
+    run_vm("07/MemoryAccess/PointerTest/PointerTest.tst");
+}
+
+#[test]
+fn static_test() {
+    run_vm("07/MemoryAccess/StaticTest/StaticTest.tst");
+}
+
+#[test]
+fn basic_loop() {
+    run_vm("08/ProgramFlow/BasicLoop/BasicLoop.tst");
+}
+
+#[test]
+fn fibonacci_series() {
+    run_vm("08/ProgramFlow/FibonacciSeries/FibonacciSeries.tst");
+}
+
+#[test]
+fn simple_function() {
+    run_vm("08/FunctionCalls/SimpleFunction/SimpleFunction.tst");
+}
+
+#[test]
+fn nested_call() {
+    run_vm("08/FunctionCalls/NestedCall/NestedCall.tst");
+}
+
+#[test]
+fn fibonacci_element() {
+    run_vm("08/FunctionCalls/FibonacciElement/FibonacciElement.tst");
+}
+
+#[test]
+fn statics_test() {
+    run_vm("08/FunctionCalls/StaticsTest/StaticsTest.tst");
+}
@@ -0,0 +1,59 @@
+//! Measures assembler throughput on the `projects/06` programs.
+//!
+//! Run with `cargo bench`. Each program is assembled repeatedly for about a
+//! second, and the average time is reported with the source bytes and lines
+//! assembled per second.
+
+use hack_assembler::assembler::Assembler;
+use std::{
+    fs,
+    hint::black_box,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const PROGRAMS: [&str; 7] = [
+    "add/Add.asm",
+    "max/Max.asm",
+    "max/MaxL.asm",
+    "rect/Rect.asm",
+    "rect/RectL.asm",
+    "pong/Pong.asm",
+    "pong/PongL.asm",
+];
+
+const TARGET: Duration = Duration::from_secs(1);
+
+fn main() {
+    let projects = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+
+    println!(
+        "{:<16} {:>8} {:>12} {:>10} {:>14}",
+        "program", "lines", "time", "MB/s", "lines/s"
+    );
+    for program in PROGRAMS.iter() {
+        let source = fs::read_to_string(projects.join(program))
+            .unwrap_or_else(|error| panic!("cannot read {}: {}", program, error));
+        let lines = source.lines().count();
+
+        let mut runs = 0;
+        let start = Instant::now();
+        while start.elapsed() < TARGET {
+            let mut output = Vec::new();
+            Assembler::assemble_io(program, black_box(source.as_bytes()), &mut output)
+                .expect("the course programs assemble");
+            black_box(output);
+            runs += 1;
+        }
+
+        let average = start.elapsed().as_secs_f64() / runs as f64;
+        println!(
+            "{:<16} {:>8} {:>10.3}ms {:>10.2} {:>14.0}",
+            program.rsplit('/').next().unwrap_or(program),
+            lines,
+            average * 1000.0,
+            source.len() as f64 / average / 1e6,
+            lines as f64 / average
+        );
+    }
+}
@@ -0,0 +1,695 @@
+//! Control flow analysis of an assembled program. The program is split
+//! into basic blocks, and each jump's target is found by tracking the value
+//! A holds: loaded by the preceding `@` instruction, or computed from
+//! constants, as in `@5`, `D=A`, `A=D+1`. Values are only tracked within a
+//! block, so a jump whose target comes from another block, from memory or
+//! from the keyboard is an indirect jump.
+//!
+//! From the graph, `analyze` reports code no path reaches, loops no jump
+//! leaves, indirect jumps and jumps outside the program. The conventional
+//! end of a program, `(END) @END 0;JMP`, is not reported as a loop.
+
+use crate::assembler::{AssembledLine, Assembly};
+use crate::error::{AssembleWarning, WarningKind};
+use crate::instruction::{Comp, Dest, Instruction, Jump};
+use crate::lint;
+use crate::platform::Platform;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// A run of instructions only entered at its first and only left after
+/// its last.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub start: u16,
+    /// The address after the last instruction.
+    pub end: u16,
+    pub successors: Vec<Successor>,
+    /// How control may leave the known program from the end of the block.
+    pub exit: Option<Exit>,
+    pub reachable: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Successor {
+    pub block: usize,
+    /// The condition of the jump to the block, or `None` when execution
+    /// runs on into it.
+    pub jump: Option<Jump>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Exit {
+    /// A jump whose target is not known.
+    Indirect,
+    /// A jump to an address past the end of the program.
+    OutOfRange(u16),
+    /// Execution runs past the last instruction.
+    End,
+}
+
+/// The values of A and D, where they are known.
+#[derive(Debug, Default, Clone, Copy)]
+struct Registers {
+    a: Option<u16>,
+    d: Option<u16>,
+}
+
+impl Registers {
+    fn step(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Address(value) => self.a = Some(value),
+            Instruction::Compute { dest, comp, .. } => {
+                let value = self.compute(comp);
+                if dest.contains(Dest::A) {
+                    self.a = value;
+                }
+                if dest.contains(Dest::D) {
+                    self.d = value;
+                }
+            }
+        }
+    }
+
+    /// What the ALU computes, unless it reads memory or an unknown register.
+    fn compute(&self, comp: Comp) -> Option<u16> {
+        let (a, d) = (self.a, self.d);
+        let value = match comp {
+            Comp::Zero => 0,
+            Comp::One => 1,
+            Comp::MinusOne => 0xFFFF,
+            Comp::D => d?,
+            Comp::A => a?,
+            Comp::NotD => !d?,
+            Comp::NotA => !a?,
+            Comp::NegD => d?.wrapping_neg(),
+            Comp::NegA => a?.wrapping_neg(),
+            Comp::DPlusOne => d?.wrapping_add(1),
+            Comp::APlusOne => a?.wrapping_add(1),
+            Comp::DMinusOne => d?.wrapping_sub(1),
+            Comp::AMinusOne => a?.wrapping_sub(1),
+            Comp::DPlusA => d?.wrapping_add(a?),
+            Comp::DMinusA => d?.wrapping_sub(a?),
+            Comp::AMinusD => a?.wrapping_sub(d?),
+            Comp::DAndA => d? & a?,
+            Comp::DOrA => d? | a?,
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Whether a jump with condition `jump` is taken when the ALU output is
+/// `value`.
+fn is_taken(jump: Jump, value: u16) -> bool {
+    let value = value as i16;
+    match jump {
+        Jump::Null => false,
+        Jump::Jgt => value > 0,
+        Jump::Jeq => value == 0,
+        Jump::Jge => value >= 0,
+        Jump::Jlt => value < 0,
+        Jump::Jne => value != 0,
+        Jump::Jle => value <= 0,
+        Jump::Jmp => true,
+    }
+}
+
+/// What is known about the jump an instruction makes.
+#[derive(Debug, Clone, Copy)]
+struct Branch {
+    target: Option<u16>,
+    /// Whether the jump is taken, if the ALU output is known.
+    taken: Option<bool>,
+}
+
+pub struct ControlFlowGraph {
+    pub blocks: Vec<Block>,
+    /// Each instruction as written, by address.
+    code: Vec<String>,
+    labels: HashMap<u16, String>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(assembly: &Assembly) -> ControlFlowGraph {
+        let lines = instruction_lines(assembly);
+        let instructions: Vec<Instruction> =
+            lines.iter().filter_map(|line| line.instruction).collect();
+        let length = instructions.len();
+        let mut labels: HashMap<u16, String> = HashMap::new();
+        for (label, address) in assembly.symbols.labels() {
+            labels.entry(address).or_insert_with(|| String::from(label));
+        }
+
+        // Jump targets split blocks and splitting blocks forgets registers,
+        // so look for targets until no new block starts
+        let mut leaders: BTreeSet<u16> = std::iter::once(0)
+            .chain(
+                instructions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instruction)| instruction.is_jump())
+                    .map(|(address, _)| address as u16 + 1),
+            )
+            .filter(|&address| usize::from(address) < length)
+            .collect();
+        let branches = loop {
+            let branches = branches(&instructions, &leaders);
+            let targets: Vec<u16> = branches
+                .values()
+                .filter_map(|branch| branch.target)
+                .filter(|&target| usize::from(target) < length && !leaders.contains(&target))
+                .collect();
+            if targets.is_empty() {
+                break branches;
+            }
+            leaders.extend(targets);
+        };
+
+        let starts: Vec<u16> = leaders.iter().copied().collect();
+        let block_at = |address: u16| starts.binary_search(&address).ok();
+        let mut blocks = Vec::new();
+        for (index, &start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(length as u16);
+            let mut block = Block {
+                start,
+                end,
+                successors: Vec::new(),
+                exit: None,
+                reachable: false,
+            };
+
+            let falls_through = match branches.get(&(end - 1)) {
+                Some(branch) => {
+                    let jump = match instructions[usize::from(end - 1)] {
+                        Instruction::Compute { jump, .. } => jump,
+                        Instruction::Address(_) => Jump::Null,
+                    };
+                    if branch.taken != Some(false) {
+                        match branch.target {
+                            Some(target) => match block_at(target) {
+                                Some(target) => block.successors.push(Successor {
+                                    block: target,
+                                    jump: Some(jump),
+                                }),
+                                None => block.exit = Some(Exit::OutOfRange(target)),
+                            },
+                            None => block.exit = Some(Exit::Indirect),
+                        }
+                    }
+                    branch.taken != Some(true) && jump != Jump::Jmp
+                }
+                None => true,
+            };
+            if falls_through {
+                if usize::from(end) < length {
+                    block.successors.push(Successor {
+                        block: index + 1,
+                        jump: None,
+                    });
+                } else if block.exit.is_none() {
+                    block.exit = Some(Exit::End);
+                }
+            }
+            blocks.push(block);
+        }
+
+        let mut graph = ControlFlowGraph {
+            blocks,
+            code: lines.iter().map(|line| lint::code(line)).collect(),
+            labels,
+        };
+        graph.mark_reachable();
+        graph
+    }
+
+    /// The block starting at `address`, if one does.
+    pub fn block_at(&self, address: u16) -> Option<usize> {
+        self.blocks
+            .binary_search_by_key(&address, |block| block.start)
+            .ok()
+    }
+
+    /// Marks the blocks a path from the first instruction reaches. An
+    /// indirect jump may go to any label, so once one is reached, so are
+    /// the blocks starting at labels.
+    fn mark_reachable(&mut self) {
+        let mut stack = Vec::new();
+        if !self.blocks.is_empty() {
+            stack.push(0);
+        }
+        let mut indirect = false;
+
+        loop {
+            while let Some(index) = stack.pop() {
+                let block = &mut self.blocks[index];
+                if block.reachable {
+                    continue;
+                }
+                block.reachable = true;
+                indirect |= block.exit == Some(Exit::Indirect);
+                stack.extend(block.successors.iter().map(|successor| successor.block));
+            }
+
+            if indirect {
+                stack.extend(
+                    self.blocks
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, block)| {
+                            !block.reachable && self.labels.contains_key(&block.start)
+                        })
+                        .map(|(index, _)| index),
+                );
+            }
+            if stack.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// The strongly connected components of the graph, as block indices.
+    fn components(&self) -> Vec<Vec<usize>> {
+        // Kosaraju's algorithm, with explicit stacks as a program may have
+        // thousands of blocks
+        let count = self.blocks.len();
+        let mut visited = vec![false; count];
+        let mut order = Vec::with_capacity(count);
+        for root in 0..count {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((index, next)) = stack.pop() {
+                match self.blocks[index].successors.get(next) {
+                    Some(successor) => {
+                        stack.push((index, next + 1));
+                        if !visited[successor.block] {
+                            visited[successor.block] = true;
+                            stack.push((successor.block, 0));
+                        }
+                    }
+                    None => order.push(index),
+                }
+            }
+        }
+
+        let mut predecessors = vec![Vec::new(); count];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in &block.successors {
+                predecessors[successor.block].push(index);
+            }
+        }
+        let mut component = vec![None; count];
+        let mut components = Vec::new();
+        for &root in order.iter().rev() {
+            if component[root].is_some() {
+                continue;
+            }
+            let mut members = Vec::new();
+            let mut stack = vec![root];
+            component[root] = Some(components.len());
+            while let Some(index) = stack.pop() {
+                members.push(index);
+                for &predecessor in &predecessors[index] {
+                    if component[predecessor].is_none() {
+                        component[predecessor] = Some(components.len());
+                        stack.push(predecessor);
+                    }
+                }
+            }
+            members.sort_unstable();
+            components.push(members);
+        }
+        components
+    }
+
+    /// Reachable loops no path leaves, apart from the conventional end of a
+    /// program. Each is a list of block indices.
+    pub fn infinite_loops(&self) -> Vec<Vec<usize>> {
+        let mut loops: Vec<Vec<usize>> = self
+            .components()
+            .into_iter()
+            .filter(|members| {
+                let first = &self.blocks[members[0]];
+                let is_loop = members.len() > 1
+                    || first
+                        .successors
+                        .iter()
+                        .any(|successor| successor.block == members[0]);
+                let closed = members.iter().all(|&index| {
+                    let block = &self.blocks[index];
+                    block.exit.is_none()
+                        && block
+                            .successors
+                            .iter()
+                            .all(|successor| members.contains(&successor.block))
+                });
+                first.reachable && is_loop && closed && !self.is_halt(members)
+            })
+            .collect();
+        loops.sort();
+        loops
+    }
+
+    /// Whether a loop is a single block like `(END) @END 0;JMP`, which
+    /// only jumps to itself.
+    fn is_halt(&self, members: &[usize]) -> bool {
+        let block = &self.blocks[members[0]];
+        members.len() == 1
+            && block.end - block.start == 2
+            && self.code[usize::from(block.start)].starts_with('@')
+            && self.code[usize::from(block.start) + 1]
+                .split(';')
+                .next()
+                .is_some_and(|comp| !comp.contains('='))
+    }
+
+    /// The graph in Graphviz's DOT language. Blocks list their
+    /// instructions under their address and label; unreachable blocks are
+    /// dashed, and indirect jumps point to a `?` node.
+    pub fn write_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut indirect = false;
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut label = match self.labels.get(&block.start) {
+                Some(name) => format!("{}: {}\\l", block.start, escape(name)),
+                None => format!("{}:\\l", block.start),
+            };
+            for address in block.start..block.end {
+                label.push_str(&format!(
+                    "    {}\\l",
+                    escape(&self.code[usize::from(address)])
+                ));
+            }
+            let style = if block.reachable {
+                ""
+            } else {
+                ", style=dashed"
+            };
+            dot.push_str(&format!("    b{} [label=\"{}\"{}];\n", index, label, style));
+
+            for successor in &block.successors {
+                match successor.jump {
+                    Some(jump) => dot.push_str(&format!(
+                        "    b{} -> b{} [label=\"{}\"];\n",
+                        index, successor.block, jump
+                    )),
+                    None => dot.push_str(&format!("    b{} -> b{};\n", index, successor.block)),
+                }
+            }
+            match block.exit {
+                Some(Exit::Indirect) => {
+                    indirect = true;
+                    dot.push_str(&format!("    b{} -> indirect [style=dotted];\n", index));
+                }
+                Some(Exit::OutOfRange(target)) => dot.push_str(&format!(
+                    "    b{} -> out{} [color=red];\n    out{} [label=\"{}\", shape=plaintext];\n",
+                    index, index, index, target
+                )),
+                _ => {}
+            }
+        }
+        if indirect {
+            dot.push_str("    indirect [label=\"?\", shape=circle];\n");
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// The line each instruction comes from, by address.
+fn instruction_lines(assembly: &Assembly) -> Vec<&AssembledLine> {
+    assembly
+        .lines
+        .iter()
+        .filter(|line| line.instruction.is_some())
+        .collect()
+}
+
+/// The jumps of the program, by address, tracking registers from the start
+/// of each block.
+fn branches(instructions: &[Instruction], leaders: &BTreeSet<u16>) -> HashMap<u16, Branch> {
+    let mut branches = HashMap::new();
+    let mut registers = Registers::default();
+
+    for (address, &instruction) in instructions.iter().enumerate() {
+        let address = address as u16;
+        if leaders.contains(&address) {
+            registers = Registers::default();
+        }
+        if let Instruction::Compute { comp, jump, .. } = instruction {
+            if jump != Jump::Null {
+                branches.insert(
+                    address,
+                    Branch {
+                        target: registers.a,
+                        taken: registers.compute(comp).map(|value| is_taken(jump, value)),
+                    },
+                );
+            }
+        }
+        registers.step(instruction);
+    }
+    branches
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Builds the control flow graph of `assembly` and reports what looks wrong
+/// in it. Warnings can be allowed with comments like the assembler's own.
+pub fn analyze(
+    platform: &Platform,
+    assembly: &Assembly,
+) -> (ControlFlowGraph, Vec<AssembleWarning>) {
+    let graph = ControlFlowGraph::new(assembly);
+    let lines = instruction_lines(assembly);
+    let allowed = lint::allowed(assembly);
+    let mut warnings = Vec::new();
+    let mut warn = |kind: WarningKind, address: u16, note: Option<String>| {
+        let line = lines[usize::from(address)];
+        let span = &line.span;
+        let allowed = allowed
+            .get(&(&span.file, span.line))
+            .is_some_and(|names| names.contains(&kind.name()));
+        if !allowed {
+            warnings.push(AssembleWarning {
+                file: Rc::clone(&span.file),
+                line: span.line,
+                column: span.column,
+                text: lint::code(line),
+                kind,
+                note,
+            });
+        }
+    };
+
+    // Runs of unreachable blocks are reported once, at their start
+    let mut run: Option<u16> = None;
+    for (index, block) in graph.blocks.iter().enumerate() {
+        if !block.reachable && run.is_none() {
+            run = Some(block.start);
+        }
+        let next_reachable = graph
+            .blocks
+            .get(index + 1)
+            .is_none_or(|next| next.reachable);
+        if let (Some(start), true) = (run, next_reachable) {
+            let count = block.end - start;
+            let plural = if count == 1 { "" } else { "s" };
+            let note = format!("{} instruction{} never run", count, plural);
+            warn(WarningKind::UnreachableCode, start, Some(note));
+            run = None;
+        }
+    }
+
+    for members in graph.infinite_loops() {
+        let start = graph.blocks[members[0]].start;
+        let end = members
+            .iter()
+            .map(|&index| graph.blocks[index].end)
+            .max()
+            .unwrap_or(start + 1);
+        let note = format!("no jump leaves ROM {} to {}", start, end - 1);
+        warn(WarningKind::InfiniteLoop, start, Some(note));
+    }
+
+    for block in graph.blocks.iter().filter(|block| block.reachable) {
+        let jump = block.end - 1;
+        match block.exit {
+            Some(Exit::Indirect) => warn(WarningKind::IndirectJump, jump, None),
+            Some(Exit::OutOfRange(target)) => {
+                let note = if usize::from(target) >= platform.rom_size {
+                    format!(
+                        "A holds {}, and {} has {} words of ROM",
+                        target, platform.name, platform.rom_size
+                    )
+                } else {
+                    format!(
+                        "A holds {}, and the program ends at {}",
+                        target,
+                        lines.len() - 1
+                    )
+                };
+                warn(WarningKind::JumpOutOfRange, jump, Some(note));
+            }
+            _ => {}
+        }
+    }
+
+    lint::sort(&mut warnings, assembly);
+    (graph, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    fn analysis(program: &str) -> (ControlFlowGraph, Vec<String>) {
+        let assembly = Assembler::build("", program.to_string()).unwrap();
+        let (graph, warnings) = analyze(&Platform::hack(), &assembly);
+        (
+            graph,
+            warnings.iter().map(|warning| warning.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn blocks() {
+        let (graph, warnings) = analysis(
+            "
+            @i
+            M=0
+            (LOOP)
+            @i
+            MD=M+1
+            @LOOP
+            D;JLT
+            (END)
+            @END
+            0;JMP
+            ",
+        );
+
+        let spans: Vec<(u16, u16)> = graph
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect();
+        assert_eq!(spans, [(0, 2), (2, 6), (6, 8)]);
+        assert_eq!(
+            graph.blocks[1].successors,
+            [
+                Successor {
+                    block: 1,
+                    jump: Some(Jump::Jlt),
+                },
+                Successor {
+                    block: 2,
+                    jump: None,
+                },
+            ]
+        );
+        assert_eq!(graph.block_at(6), Some(2));
+        assert!(graph.blocks.iter().all(|block| block.reachable));
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn tracks_constants() {
+        // The target is computed through D, and the jump is always taken
+        let (graph, warnings) = analysis(
+            "
+            @3
+            D=A
+            A=D+1
+            0;JEQ
+            @9
+            0;JMP
+            ",
+        );
+
+        assert_eq!(graph.blocks[0].successors.len(), 1);
+        assert_eq!(graph.block_at(4), Some(1));
+        assert_eq!(
+            warnings,
+            ["7:13: warning: jump outside the program `0;JMP` \
+                 (A holds 9, and the program ends at 5) [jump-out-of-range]",]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let (_, warnings) = analysis(
+            "
+            @R0
+            D=M
+            @SPIN
+            D;JEQ
+            @R1
+            A=M
+            0;JMP
+            @R2
+            M=0
+            (SPIN)
+            @KBD
+            D=M
+            @SPIN
+            0;JMP
+            (END)
+            A=-1
+            0;JMP
+            @R3 // allow(unreachable-code)
+            M=0
+            ",
+        );
+
+        assert_eq!(
+            warnings,
+            [
+                "8:13: warning: jump target is not known `0;JMP` [indirect-jump]",
+                "9:13: warning: unreachable code `@R2` (2 instructions never run) \
+                 [unreachable-code]",
+                "12:13: warning: loop never exits `@KBD` (no jump leaves ROM 9 to 12) \
+                 [infinite-loop]",
+                "18:13: warning: jump outside the program `0;JMP` \
+                 (A holds 65535, and hack has 32768 words of ROM) [jump-out-of-range]",
+            ]
+        );
+    }
+
+    #[test]
+    fn write_dot() {
+        let (graph, _) = analysis(
+            "
+            @R0
+            A=M
+            D;JGT
+            (END)
+            @END
+            0;JMP
+            ",
+        );
+
+        assert_eq!(
+            graph.write_dot(),
+            "digraph cfg {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"0:\\l    @R0\\l    A=M\\l    D;JGT\\l\"];
+    b0 -> b1;
+    b0 -> indirect [style=dotted];
+    b1 [label=\"3: END\\l    @END\\l    0;JMP\\l\"];
+    b1 -> b1 [label=\"JMP\"];
+    indirect [label=\"?\", shape=circle];
+}
+"
+        );
+    }
+}
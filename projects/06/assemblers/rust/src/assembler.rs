@@ -1,56 +1,564 @@
 use crate::code_gen::generate_command;
-use crate::parser::{CommandType, Parser};
+use crate::error::{AssembleError, AssembleErrors, AssembleIoError, AssembleWarning, ErrorKind};
+use crate::expression::Expression;
+use crate::formats::Format;
+use crate::instruction::{Comp, Dest, Instruction};
+use crate::lexer;
+use crate::lint::lint;
+use crate::object::ObjectFile;
+use crate::parser::{location, Command, CommandType, Loader, Parser, Span};
+use crate::platform::Platform;
 use crate::symbols::Symbols;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read, Write},
+    rc::Rc,
+};
+
+// A-instructions carry a 15 bit value
+const MAX_ADDRESS: u16 = 0x7FFF;
+
+/// A source command together with the ROM address it was placed at.
+/// Labels occupy no ROM, so they have no `instruction`.
+pub struct AssembledLine {
+    pub address: u16,
+    pub instruction: Option<Instruction>,
+    pub command: CommandType,
+    /// The label the line defines, or the symbols its A-instruction or
+    /// constant refers to, qualified like in the symbol table.
+    pub symbols: Vec<String>,
+    pub span: Span,
+}
+
+pub struct Assembly {
+    pub lines: Vec<AssembledLine>,
+    pub symbols: Symbols,
+    pub warnings: Vec<AssembleWarning>,
+}
+
+impl Assembly {
+    /// The encoded program, one word per ROM address.
+    pub fn words(&self) -> Vec<u16> {
+        self.lines
+            .iter()
+            .filter_map(|line| line.instruction.map(|instruction| instruction.encode()))
+            .collect()
+    }
+
+    /// The program in the ASCII `.hack` format.
+    pub fn machine_code(&self) -> String {
+        let mut machine_code = Vec::new();
+        self.write_to(&mut machine_code)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(machine_code).expect("machine code is ASCII")
+    }
+
+    /// Writes the program to `output` in the ASCII `.hack` format.
+    pub fn write_to<W: Write>(&self, output: W) -> io::Result<()> {
+        Format::Hack.write_to(&self.words(), output)
+    }
+
+    /// The files the program was read from: the files given, in order, with
+    /// each included file after the file including it.
+    pub fn files<'a>(&'a self, sources: &[(&'a str, &str)]) -> Vec<&'a str> {
+        let mut files: Vec<&str> = Vec::new();
+        let read = self.lines.iter().map(|line| &*line.span.file);
+        for file in read.chain(sources.iter().map(|&(file, _)| file)) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        files
+    }
+}
+
+/// The name `symbol` has in the symbol table when it appears in `file`:
+/// symbols starting with `_` are qualified with the file they are private
+/// to.
+pub fn scoped_name(file: &str, symbol: &str) -> String {
+    if symbol.starts_with('_') && !file.is_empty() {
+        format!("{}::{}", file, symbol)
+    } else {
+        symbol.to_string()
+    }
+}
 
 pub struct Assembler {}
 
 impl Assembler {
-    pub fn assemble(raw_program: String) -> String {
-        let mut parser = Parser::new(&raw_program);
-        let mut symbol_table = Symbols::new();
+    pub fn assemble(raw_program: String) -> Result<String, AssembleErrors> {
+        Assembler::assemble_file("", raw_program)
+    }
+
+    pub fn assemble_file(filename: &str, raw_program: String) -> Result<String, AssembleErrors> {
+        Assembler::build(filename, raw_program).map(|assembly| assembly.machine_code())
+    }
+
+    /// Assembles the program read from `input`, writing its `.hack` machine
+    /// code to `output`. Includes are read from disk, relative to `filename`.
+    /// This does not stream: labels can be used before they are defined, so
+    /// all of `input` is read first, and nothing is written unless the whole
+    /// program assembles.
+    pub fn assemble_io<R: Read, W: Write>(
+        filename: &str,
+        mut input: R,
+        output: W,
+    ) -> Result<(), AssembleIoError> {
+        let mut raw_program = String::new();
+        input.read_to_string(&mut raw_program)?;
+
+        let assembly = Assembler::build(filename, raw_program)?;
+        assembly.write_to(output)?;
+        Ok(())
+    }
+
+    pub fn build(filename: &str, raw_program: String) -> Result<Assembly, AssembleErrors> {
+        Assembler::build_files(&[(filename, &raw_program)], &|path| {
+            fs::read_to_string(path)
+        })
+    }
+
+    /// Assembles several files into one ROM image, in order. Symbols starting
+    /// with `_` are private to the file they appear in, all others are shared
+    /// by every file.
+    pub fn build_files(sources: &[(&str, &str)], load: Loader) -> Result<Assembly, AssembleErrors> {
+        Assembler::build_for(&Platform::default(), sources, load)
+    }
+
+    /// Like `build_files`, for a platform other than the course's. The
+    /// program must fit in its ROM and its variables in its variable range.
+    pub fn build_for(
+        platform: &Platform,
+        sources: &[(&str, &str)],
+        load: Loader,
+    ) -> Result<Assembly, AssembleErrors> {
+        Assembler::run(platform, sources, load, None)
+    }
+
+    /// Assembles files into a relocatable object for the linker. Labels not
+    /// starting with `_` are exported, `.extern` labels are imported, and
+    /// any other symbol that is not defined is a variable.
+    pub fn build_object(
+        platform: &Platform,
+        sources: &[(&str, &str)],
+        load: Loader,
+    ) -> Result<ObjectFile, AssembleErrors> {
+        let mut object = ObjectFile::default();
+        let assembly = Assembler::run(platform, sources, load, Some(&mut object))?;
+        object.code = assembly.words();
+        Ok(object)
+    }
+
+    /// Assembles as much of the program as it can, returning it together
+    /// with every error, for editors that need its symbols while the source
+    /// is broken. Lines in error have no instruction, and the program is
+    /// only checked for warnings when there are no errors.
+    pub fn check(
+        platform: &Platform,
+        sources: &[(&str, &str)],
+        load: Loader,
+    ) -> (Assembly, AssembleErrors) {
+        let (mut assembly, mut errors) = Assembler::assemble_lines(platform, sources, load, None);
+        if errors.is_empty() {
+            assembly.warnings = lint(&assembly);
+        }
+        errors.sort(&assembly.files(sources));
+        (assembly, errors)
+    }
+
+    fn run(
+        platform: &Platform,
+        sources: &[(&str, &str)],
+        load: Loader,
+        object: Option<&mut ObjectFile>,
+    ) -> Result<Assembly, AssembleErrors> {
+        let linking = object.is_some();
+        let (mut assembly, mut errors) = Assembler::assemble_lines(platform, sources, load, object);
+
+        if errors.is_empty() {
+            // Exported labels and variables are used by other objects
+            if !linking {
+                assembly.warnings = lint(&assembly);
+            }
+            Ok(assembly)
+        } else {
+            errors.sort(&assembly.files(sources));
+            Err(errors)
+        }
+    }
+
+    fn assemble_lines(
+        platform: &Platform,
+        sources: &[(&str, &str)],
+        load: Loader,
+        mut object: Option<&mut ObjectFile>,
+    ) -> (Assembly, AssembleErrors) {
+        let mut parser = Parser::with_files(sources, load);
+        let mut symbol_table = Symbols::with_platform(platform);
+        let mut definitions = HashMap::new();
+        let mut labels = HashSet::new();
+        let mut externs = HashSet::new();
+        let mut errors = AssembleErrors::new();
+        for error in parser.errors() {
+            errors.push(error.clone());
+        }
+
+        // Programs past the end of ROM fail with RomOverflow, so addresses
+        // stop counting there rather than wrapping around
+        let rom_address = |index: usize| index.min(platform.rom_size) as u16;
 
         // first pass to record jump symbols
         let mut line_index = 0;
         while parser.has_more_commands() {
             match parser.command_type() {
-                CommandType::Jump => {
-                    symbol_table.add_jump_symbol(parser.get_symbol(), line_index);
-                }
-                _ => {
+                CommandType::Jump => match parser.get_symbol().and_then(|symbol| {
+                    let name = Assembler::scoped(&parser, &symbol.node);
+                    Assembler::check_duplicate(&parser, &name, 1, &symbol_table, &mut definitions)
+                        .map(|_| name)
+                }) {
+                    Ok(name) => {
+                        if let Some(object) = object.as_deref_mut() {
+                            if !name.starts_with('_') && !name.contains("::") {
+                                object.exports.push((name.clone(), rom_address(line_index)));
+                            }
+                        }
+                        labels.insert(name.clone());
+                        symbol_table.add_jump_symbol(name, rom_address(line_index));
+                    }
+                    Err(error) => errors.push(error),
+                },
+                CommandType::Extern => match parser.get_symbol() {
+                    Ok(symbol) => {
+                        externs.insert(symbol.node);
+                    }
+                    Err(error) => errors.push(error),
+                },
+                // The instructions a macro or pseudo-instruction expands to
+                // follow it as lines of their own and are counted there
+                command_type if command_type.is_instruction() => {
                     line_index += 1;
                 }
+                _ => {}
             }
             parser.advance();
         }
 
         parser.reset();
 
-        // start assembly
-        let mut assembly_code = String::new();
+        // constants may refer to any label and to constants defined above them
         while parser.has_more_commands() {
             match parser.command_type() {
+                CommandType::Constant => {
+                    if let Err(error) =
+                        Assembler::define_constant(&parser, &mut symbol_table, &mut definitions)
+                    {
+                        errors.push(error);
+                    }
+                }
+                // Without a linker every external label must be in the sources
+                CommandType::Extern if object.is_none() => {
+                    if let Ok(symbol) = parser.get_symbol() {
+                        if !labels.contains(&symbol.node) {
+                            errors.push(parser.error(ErrorKind::UndefinedSymbol, 7, &symbol.node));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            parser.advance();
+        }
+
+        parser.reset();
+
+        // start assembly
+        let mut lines = Vec::new();
+        let mut line_index = 0;
+        // A value past the end of RAM, reported if the next instruction
+        // uses it to access memory
+        let mut outside_ram: Option<AssembleError> = None;
+        while parser.has_more_commands() {
+            let address = rom_address(line_index);
+            let mut symbols = Vec::new();
+            let instruction = match parser.command_type() {
                 CommandType::Address => {
-                    let symbol = parser.get_symbol();
-                    let address = symbol
-                        .parse::<u16>()
-                        .unwrap_or_else(|_| symbol_table.get_address(symbol));
-
-                    let binary_str = format!("{:0>16b}", address);
-                    assembly_code.push_str(&binary_str);
-                    assembly_code.push_str("\n");
+                    match parser.get_expression().and_then(|expression| {
+                        symbols = Assembler::references(&parser, &expression.node);
+                        match object.as_deref_mut() {
+                            Some(object) => Assembler::resolve_relocatable(
+                                &parser,
+                                expression.node,
+                                &symbol_table,
+                                (&labels, &externs),
+                                (address, object),
+                            ),
+                            None => Assembler::resolve_address(
+                                &parser,
+                                expression.node,
+                                &mut symbol_table,
+                                platform,
+                            ),
+                        }
+                    }) {
+                        Ok(value) => Some(Instruction::Address(value)),
+                        Err(error) => {
+                            errors.push(error);
+                            None
+                        }
+                    }
                 }
                 CommandType::Computation => {
-                    let command = parser.parse_command();
-                    assembly_code.push_str(&generate_command(command));
-                    assembly_code.push_str("\n");
+                    let command = parser.parse_command().node;
+                    match generate_command(&command) {
+                        Ok(instruction) => Some(instruction),
+                        Err(kinds) => {
+                            for kind in kinds {
+                                errors.push(Assembler::command_error(&parser, &command, kind));
+                            }
+                            None
+                        }
+                    }
+                }
+                CommandType::Jump => {
+                    if let Ok(label) = parser.get_symbol() {
+                        symbols.push(Assembler::scoped(&parser, &label.node));
+                    }
+                    None
+                }
+                CommandType::Constant => {
+                    if let Ok(constant) = parser.get_constant() {
+                        symbols = Assembler::references(&parser, &constant.node.1);
+                    }
+                    None
+                }
+                _ => None,
+            };
+
+            match instruction {
+                Some(Instruction::Address(value)) if usize::from(value) >= platform.ram_size => {
+                    let mut error =
+                        parser.error(ErrorKind::AddressOutOfRange, 1, &value.to_string());
+                    error.note = Some(format!(
+                        "{} has {} words of RAM",
+                        platform.name, platform.ram_size
+                    ));
+                    outside_ram = Some(error);
+                }
+                Some(Instruction::Compute { dest, comp, .. }) => {
+                    if let Some(error) = outside_ram.take() {
+                        if comp.reads_memory() || dest.contains(Dest::M) {
+                            errors.push(error);
+                        }
+                    }
+                }
+                _ if parser.command_type() == CommandType::Jump
+                    || parser.command_type().is_instruction() =>
+                {
+                    outside_ram = None;
                 }
                 _ => {}
             }
 
+            lines.push(AssembledLine {
+                address,
+                instruction,
+                command: parser.command_type(),
+                symbols,
+                span: parser.span().clone(),
+            });
+            if parser.command_type().is_instruction() {
+                if line_index == platform.rom_size {
+                    let code = lexer::strip_comment(&parser.span().raw).trim();
+                    let mut error = parser.error(ErrorKind::RomOverflow, 0, code);
+                    error.note = Some(format!(
+                        "{} has {} words of ROM",
+                        platform.name, platform.rom_size
+                    ));
+                    errors.push(error);
+                }
+                line_index += 1;
+            }
+
             parser.advance();
         }
 
-        assembly_code
+        let assembly = Assembly {
+            lines,
+            symbols: symbol_table,
+            warnings: Vec::new(),
+        };
+        (assembly, errors)
+    }
+
+    /// A lone symbol is a label, constant or variable, with variables
+    /// allocated on first use. Symbols in larger expressions must already
+    /// be defined.
+    fn resolve_address(
+        parser: &Parser,
+        expression: Expression,
+        symbol_table: &mut Symbols,
+        platform: &Platform,
+    ) -> Result<u16, AssembleError> {
+        match expression {
+            Expression::Symbol(symbol) => {
+                let name = Assembler::scoped(parser, &symbol);
+                symbol_table.get_address(name).ok_or_else(|| {
+                    let mut error = parser.error(ErrorKind::OutOfVariables, 1, &symbol);
+                    error.note = Some(format!(
+                        "{} allocates variables from {} to {}",
+                        platform.name,
+                        platform.variables.start,
+                        platform.variables.end.saturating_sub(1)
+                    ));
+                    error
+                })
+            }
+            expression => Assembler::evaluate(parser, &expression, symbol_table),
+        }
+    }
+
+    /// Like `resolve_address`, but leaves the word at `address` for the
+    /// linker to fill in when it refers to a label, an imported label or a
+    /// variable.
+    fn resolve_relocatable(
+        parser: &Parser,
+        expression: Expression,
+        symbol_table: &Symbols,
+        (labels, externs): (&HashSet<String>, &HashSet<String>),
+        (address, object): (u16, &mut ObjectFile),
+    ) -> Result<u16, AssembleError> {
+        if let Expression::Symbol(symbol) = &expression {
+            let name = Assembler::scoped(parser, symbol);
+            if externs.contains(&name) && !labels.contains(&name) {
+                object.imports.push((address, name));
+                return Ok(0);
+            }
+            if symbol_table.get(&name).is_none() {
+                object.variables.push((address, name));
+                return Ok(0);
+            }
+        }
+
+        // The value moves with the object exactly when moving every label by
+        // one moves it by one, as for `@LOOP+2` but not `@END-LOOP`
+        let value = |offset: i64| {
+            let lookup = |symbol: &str| {
+                let name = Assembler::scoped(parser, symbol);
+                let moved = if labels.contains(&name) { offset } else { 0 };
+                symbol_table
+                    .get(&name)
+                    .map(|value| i64::from(value) + moved)
+            };
+            Assembler::evaluate_with(parser, &expression, &lookup)
+        };
+
+        let (value, moved) = (value(0)?, value(1)?);
+        match moved - value {
+            0 => Assembler::in_range(parser, value),
+            1 => {
+                object.relocations.push(address);
+                Assembler::in_range(parser, value)
+            }
+            _ => Err(parser.error(ErrorKind::NotRelocatable, 1, &expression.to_string())),
+        }
+    }
+
+    fn references(parser: &Parser, expression: &Expression) -> Vec<String> {
+        expression
+            .symbols()
+            .iter()
+            .map(|symbol| Assembler::scoped(parser, symbol))
+            .collect()
+    }
+
+    /// Qualifies private symbols with the file of the current line.
+    fn scoped(parser: &Parser, symbol: &str) -> String {
+        scoped_name(&parser.span().file, symbol)
+    }
+
+    /// Records where the label or constant `name` is defined, failing if
+    /// anything already was.
+    fn check_duplicate(
+        parser: &Parser,
+        name: &str,
+        offset: usize,
+        symbol_table: &Symbols,
+        definitions: &mut HashMap<String, (Rc<str>, usize)>,
+    ) -> Result<(), AssembleError> {
+        let note = match definitions.get(name) {
+            Some((file, line)) => format!("first defined at {}", location(file, *line)),
+            None if symbol_table.get(name).is_some() => String::from("a predefined symbol"),
+            None => {
+                let span = parser.span();
+                definitions.insert(name.to_string(), (Rc::clone(&span.file), span.line));
+                return Ok(());
+            }
+        };
+
+        let mut error = parser.error(ErrorKind::DuplicateSymbol, offset, name);
+        error.note = Some(note);
+        Err(error)
+    }
+
+    fn define_constant(
+        parser: &Parser,
+        symbol_table: &mut Symbols,
+        definitions: &mut HashMap<String, (Rc<str>, usize)>,
+    ) -> Result<(), AssembleError> {
+        let (name, expression) = parser.get_constant()?.node;
+        let name = Assembler::scoped(parser, &name);
+        Assembler::check_duplicate(parser, &name, 0, symbol_table, definitions)?;
+
+        let value = Assembler::evaluate(parser, &expression, symbol_table)?;
+        symbol_table.add_constant(name, value);
+        Ok(())
+    }
+
+    fn evaluate(
+        parser: &Parser,
+        expression: &Expression,
+        symbol_table: &Symbols,
+    ) -> Result<u16, AssembleError> {
+        let lookup = |symbol: &str| {
+            symbol_table
+                .get(&Assembler::scoped(parser, symbol))
+                .map(i64::from)
+        };
+
+        let value = Assembler::evaluate_with(parser, expression, &lookup)?;
+        Assembler::in_range(parser, value)
+    }
+
+    fn evaluate_with(
+        parser: &Parser,
+        expression: &Expression,
+        lookup: &dyn Fn(&str) -> Option<i64>,
+    ) -> Result<i64, AssembleError> {
+        expression
+            .evaluate(lookup)
+            .map_err(|(kind, text)| parser.error(kind, 1, &text))
+    }
+
+    fn in_range(parser: &Parser, value: i64) -> Result<u16, AssembleError> {
+        if (0..=i64::from(MAX_ADDRESS)).contains(&value) {
+            Ok(value as u16)
+        } else {
+            Err(parser.error(ErrorKind::AddressOutOfRange, 1, &value.to_string()))
+        }
+    }
+
+    fn command_error(parser: &Parser, command: &Command, kind: ErrorKind) -> AssembleError {
+        let Command { dest, comp, jump } = command;
+        let comp_offset = if dest.is_empty() { 0 } else { dest.len() + 1 };
+
+        match kind {
+            ErrorKind::BadDest => parser.error(kind, 0, dest),
+            ErrorKind::BadJump => parser.error(kind, comp_offset + comp.len() + 1, jump),
+            _ => AssembleError {
+                note: Comp::diagnose(comp).map(String::from),
+                ..parser.error(kind, comp_offset, comp)
+            },
+        }
     }
 }
 
@@ -86,6 +594,373 @@ mod tests {
         output.push_str("0000000000000101\n");
         output.push_str("1110101010000111\n");
 
-        assert_eq!(Assembler::assemble(program), output);
+        assert_eq!(Assembler::assemble(program), Ok(output));
+    }
+
+    #[test]
+    fn reads_and_writes() {
+        let program = "@2\nD=A\n(END)\n@END\n0;JMP\n";
+        let mut output = Vec::new();
+        Assembler::assemble_io("", program.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output),
+            Ok(Assembler::assemble(program.to_string()).unwrap())
+        );
+
+        let error = Assembler::assemble_io("", "D=Q".as_bytes(), Vec::new()).unwrap_err();
+        assert!(matches!(error, AssembleIoError::Assemble(errors) if errors.errors().len() == 1));
+    }
+
+    #[test]
+    fn collects_errors() {
+        let program = "
+           @40000
+           D=D+X
+           DX=M;JMZ
+           (BAD-LABEL)
+        "
+        .to_string();
+
+        let errors = Assembler::assemble(program).unwrap_err();
+        let errors: Vec<(usize, usize, &str, ErrorKind)> = errors
+            .errors()
+            .iter()
+            .map(|e| (e.line, e.column, e.text.as_str(), e.kind))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (2, 13, "40000", ErrorKind::AddressOutOfRange),
+                (3, 14, "D+X", ErrorKind::UnknownComp),
+                (4, 12, "DX", ErrorKind::BadDest),
+                (4, 17, "JMZ", ErrorKind::BadJump),
+                (5, 12, "(BAD-LABEL)", ErrorKind::MalformedLabel),
+            ]
+        );
+    }
+
+    #[test]
+    fn platforms() {
+        let platform =
+            Platform::read("extends hack\nname tiny\nrom 4\nvariables 16 17\nsymbol LED 100")
+                .unwrap();
+        let build = |source: &str| {
+            Assembler::build_for(&platform, &[("", source)], &|_| Ok(String::new()))
+                .map(|assembly| assembly.words())
+        };
+
+        assert_eq!(
+            build("@LED\nM=1\n@i\nM=0"),
+            Ok(vec![100, 0xEFC8, 16, 0xEA88])
+        );
+
+        let errors = build("@i\nM=0\n@j\nM=0\n@i").unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "3:2: error: no RAM left for variable `j` (tiny allocates variables from 16 to 16)",
+                "5:1: error: program does not fit in ROM `@i` (tiny has 4 words of ROM)",
+            ]
+        );
+    }
+
+    #[test]
+    fn rom_overflow_stops_counting() {
+        let source = format!("{}(END)\n@END\n0;JMP", "D=A\n".repeat(70000));
+        let errors = Assembler::assemble(source).unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec!["32769:1: error: program does not fit in ROM `D=A` (hack has 32768 words of ROM)"]
+        );
+    }
+
+    #[test]
+    fn addresses_checked_against_platform() {
+        let build = |platform: &Platform, source: &str| {
+            Assembler::build_for(platform, &[("", source)], &|_| Ok(String::new()))
+                .map(|assembly| assembly.words().len())
+                .map_err(|errors| -> Vec<String> {
+                    errors.errors().iter().map(|e| e.to_string()).collect()
+                })
+        };
+        let hack = Platform::hack();
+        let hack32k = Platform::from_name("hack32k").unwrap();
+
+        // Values past RAM can still be loaded as constants or ROM addresses
+        let source = "@30000\nM=1\n@KBD+1\nD=M\n@30000\nD=A\n@24576\nD=M";
+        assert_eq!(
+            build(&hack, source).unwrap_err(),
+            vec![
+                "1:2: error: address out of range `30000` (hack has 24577 words of RAM)",
+                "3:2: error: address out of range `24577` (hack has 24577 words of RAM)",
+            ]
+        );
+        assert_eq!(build(&hack32k, source), Ok(8));
+
+        // A holds 15 bits whatever the platform's memory sizes
+        let small = Platform::read("name small\nrom 1024\nram 8192\nvariables 16 8192").unwrap();
+        assert_eq!(build(&small, "@8192\nD=A"), Ok(2));
+        assert_eq!(
+            build(&small, "@8192\nM=0").unwrap_err(),
+            vec!["1:2: error: address out of range `8192` (small has 8192 words of RAM)"]
+        );
+        assert_eq!(
+            build(&small, "@32768\nD=A").unwrap_err(),
+            vec!["1:2: error: address out of range `32768`"]
+        );
+    }
+
+    #[test]
+    fn flexible_c_instructions() {
+        let canonical = Assembler::assemble(String::from("AMD=D+A\nMD=D|M;JNE\nD=D+1\n"));
+        let flexible = Assembler::assemble(String::from("DMA=A+D\nDM=M|D;JNE\nD=1+D\n"));
+        assert_eq!(flexible, canonical);
+
+        let errors = Assembler::assemble(String::from("D=D+D\nMM=D")).unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:3: error: unknown computation `D+D` \
+                 (the ALU cannot combine a register with itself)",
+                "2:1: error: invalid destination `MM`",
+            ]
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let program = "
+           .macro PUSH_D
+               @SP
+               M=M+1
+               A=M-1
+               M=D
+           .endm
+           .macro DEC_JUMP counter
+               @counter
+               MD=M-1
+               @SKIP
+               D;JEQ
+               PUSH_D
+           (SKIP)
+           .endm
+           DEC_JUMP R1
+           DEC_JUMP R2
+           (END)
+           @END
+        "
+        .to_string();
+
+        let assembly = Assembler::build("", program).unwrap();
+        assert_eq!(assembly.words().len(), 17);
+        assert_eq!(assembly.symbols.labels().len(), 3);
+
+        // Each expansion jumps to its own SKIP
+        assert_eq!(assembly.words()[2], 8);
+        assert_eq!(assembly.words()[10], 16);
+        assert_eq!(assembly.words()[16], 16);
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        let program = "
+           D=42
+           (LOOP)
+           M[counter]=D
+           if D-1 > 0 goto END
+           @-1
+           goto LOOP
+           (END)
+        "
+        .to_string();
+
+        let assembly = Assembler::build("", program).unwrap();
+        assert_eq!(assembly.symbols.labels(), vec![("LOOP", 2), ("END", 10)]);
+
+        let instructions: Vec<String> = assembly
+            .lines
+            .iter()
+            .filter_map(|line| line.instruction.map(|i| i.to_string()))
+            .collect();
+        assert_eq!(
+            instructions,
+            vec!["@42", "D=A", "@16", "M=D", "@10", "D-1;JGT", "@1", "A=-A", "@2", "0;JMP"]
+        );
+    }
+
+    #[test]
+    fn pseudo_errors() {
+        let program = "
+           M=42
+           if M>0 goto END
+              goto 99999
+        D=M[40000] // lowered lines point at the pseudo-instruction
+        "
+        .to_string();
+
+        let errors = Assembler::assemble(program).unwrap_err();
+        let errors: Vec<(usize, usize, &str, ErrorKind)> = errors
+            .errors()
+            .iter()
+            .map(|e| (e.line, e.column, e.text.as_str(), e.kind))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (2, 12, "M=42", ErrorKind::MalformedPseudo),
+                (3, 12, "if M>0 goto END", ErrorKind::MalformedPseudo),
+                (4, 15, "99999", ErrorKind::AddressOutOfRange),
+                (5, 9, "40000", ErrorKind::AddressOutOfRange),
+            ]
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let program = "
+           .equ ROWS 256
+           .define WORDS ROWS * 32
+           @SCREEN+32*10+4
+           @(WORDS)
+           (LOOP)
+           @LOOP+2
+           @END-LOOP
+           (END)
+        "
+        .to_string();
+
+        let assembly = Assembler::build("", program).unwrap();
+        assert_eq!(assembly.words(), vec![16708, 8192, 4, 2]);
+        assert_eq!(
+            assembly.symbols.constants(),
+            vec![("ROWS", 256), ("WORDS", 8192)]
+        );
+    }
+
+    #[test]
+    fn constant_errors() {
+        let program = "
+           .equ BIG 16384*2
+           .equ SCREEN 1
+           @UNKNOWN+1
+           @4/0
+           @0-1
+        "
+        .to_string();
+
+        let errors = Assembler::assemble(program).unwrap_err();
+        let errors: Vec<(usize, &str, ErrorKind)> = errors
+            .errors()
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (2, "32768", ErrorKind::AddressOutOfRange),
+                (3, "SCREEN", ErrorKind::DuplicateSymbol),
+                (4, "UNKNOWN", ErrorKind::UndefinedSymbol),
+                (5, "(4/0)", ErrorKind::DivisionByZero),
+                (6, "-1", ErrorKind::AddressOutOfRange),
+            ]
+        );
+    }
+
+    fn no_includes(_: &std::path::Path) -> std::io::Result<String> {
+        Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+
+    #[test]
+    fn multiple_files() {
+        let main = "
+           (_loop)
+           @Math.multiply
+           0;JMP
+           @_loop
+           @_tmp
+        ";
+        let math = "
+           (Math.multiply)
+           @_tmp
+           (_loop)
+           @_loop
+        ";
+
+        let assembly =
+            Assembler::build_files(&[("Main.asm", main), ("Math.asm", math)], &no_includes)
+                .unwrap();
+        assert_eq!(assembly.words(), vec![4, 0xEA87, 0, 16, 17, 5]);
+        assert_eq!(
+            assembly.symbols.labels(),
+            vec![
+                ("Main.asm::_loop", 0),
+                ("Math.multiply", 4),
+                ("Math.asm::_loop", 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_labels() {
+        let main = "
+           (LOOP)
+           (SCREEN)
+        ";
+        let other = "
+           (LOOP)
+           .equ LOOP 3
+        ";
+
+        let errors =
+            Assembler::build_files(&[("Main.asm", main), ("Other.asm", other)], &no_includes)
+                .map(|assembly| assembly.words())
+                .unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Main.asm:3:13: error: symbol is already defined `SCREEN` (a predefined symbol)",
+                "Other.asm:2:13: error: symbol is already defined `LOOP` \
+                 (first defined at Main.asm:2)",
+                "Other.asm:3:12: error: symbol is already defined `LOOP` \
+                 (first defined at Main.asm:2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_follow_file_order() {
+        let main = "X=1\n.include \"lib/b.asm\"\nD=Q";
+        let load = |path: &std::path::Path| {
+            assert_eq!(path, std::path::Path::new("lib/b.asm"));
+            Ok(String::from("Y=1"))
+        };
+
+        let errors = Assembler::build_files(&[("Main.asm", main), ("A.asm", "Z=1")], &load)
+            .map(|assembly| assembly.words())
+            .unwrap_err();
+        let errors: Vec<(&str, usize)> = errors
+            .errors()
+            .iter()
+            .map(|e| (e.file.as_ref(), e.line))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("Main.asm", 1),
+                ("Main.asm", 3),
+                ("lib/b.asm", 1),
+                ("A.asm", 1)
+            ]
+        );
     }
 }
@@ -0,0 +1,14 @@
+use hack_assembler::cli;
+use std::{env, process};
+
+fn main() {
+    let config = cli::LinkConfig::new(env::args()).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+
+    if let Err(err) = cli::run_linker(config) {
+        eprintln!("Application error: {}", err);
+        process::exit(1);
+    }
+}
@@ -1,8 +1,42 @@
+use crate::analysis::analyze;
 use crate::assembler::Assembler;
-use std::{error::Error, fs};
+use crate::disassembler::Disassembler;
+use crate::error::{AssembleErrors, AssembleWarning};
+use crate::formats::Format;
+use crate::formatter;
+use crate::linker::link;
+use crate::lint;
+use crate::listing::generate_listing;
+use crate::lsp::Server;
+use crate::object::ObjectFile;
+use crate::platform::Platform;
+use crate::symbol_map::SymbolMap;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const OBJECT_EXTENSION: &str = "hobj";
 
 pub struct Config {
-    filepath: String,
+    /// The files to assemble into one program. Outputs are named after the
+    /// first.
+    filepaths: Vec<String>,
+    listing: bool,
+    /// Also write a symbol map, as text or as JSON.
+    symbols: Option<SymbolFormat>,
+    /// Report what control flow analysis finds.
+    analyze: bool,
+    /// Also write the control flow graph in Graphviz's DOT language.
+    cfg: bool,
+    /// Write a relocatable object for `hack-ld` instead of a program.
+    object: bool,
+    format: Option<Format>,
+    output: Option<String>,
+    /// A built-in platform name or a profile file, if not the course's.
+    platform: Option<String>,
 }
 
 impl Config {
@@ -12,21 +46,505 @@ impl Config {
     {
         args.next();
 
-        let filepath = match args.next() {
-            Some(f) => f,
-            None => return Err("Did not receive a file path."),
-        };
+        let mut filepaths = Vec::new();
+        let mut listing = false;
+        let mut symbols = None;
+        let mut analyze = false;
+        let mut cfg = false;
+        let mut object = false;
+        let mut format = None;
+        let mut output = None;
+        let mut platform = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--listing" | "-l" => listing = true,
+                "--symbols" | "-s" => match args.next().as_deref() {
+                    Some("text") => symbols = Some(SymbolFormat::Text),
+                    Some("json") => symbols = Some(SymbolFormat::Json),
+                    _ => return Err("--symbols expects text or json."),
+                },
+                "--analyze" | "-a" => analyze = true,
+                "--cfg" => cfg = true,
+                "--object" | "-c" => object = true,
+                "--format" | "-f" => format = Some(format_arg(args.next())?),
+                "--output" | "-o" => match args.next() {
+                    Some(path) => output = Some(path),
+                    None => return Err("--output expects a file path."),
+                },
+                "--platform" | "-p" => match args.next() {
+                    Some(name) => platform = Some(name),
+                    None => return Err("--platform expects a platform name or file."),
+                },
+                _ if arg.starts_with('-') => return Err("Unknown option."),
+                _ => filepaths.push(arg),
+            }
+        }
+
+        if filepaths.is_empty() {
+            return Err("Did not receive a file path.");
+        }
+        let machine_code = filepaths
+            .iter()
+            .any(|filepath| Format::from_extension(filepath).is_some());
+        if machine_code && filepaths.len() > 1 {
+            return Err("Can only disassemble one file at a time.");
+        }
+        if object && (listing || symbols.is_some() || format.is_some()) {
+            return Err("--object cannot be combined with --listing, --symbols or --format.");
+        }
+        if object && (analyze || cfg) {
+            return Err("--object cannot be combined with --analyze or --cfg.");
+        }
+
+        Ok(Config {
+            filepaths,
+            listing,
+            symbols,
+            analyze,
+            cfg,
+            object,
+            format,
+            output,
+            platform,
+        })
+    }
+
+    /// The output format: `--format`, else the `--output` extension, else
+    /// the course's `.hack` format.
+    fn output_format(&self) -> Format {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(Format::from_extension))
+            .unwrap_or(Format::Hack)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum SymbolFormat {
+    Text,
+    Json,
+}
+
+fn format_arg(name: Option<String>) -> Result<Format, &'static str> {
+    name.as_deref()
+        .and_then(Format::from_name)
+        .ok_or("--format expects one of hack, bin, bin-le, ihex, memb, memh or logisim.")
+}
+
+/// The options of `hack-ld`, which links object files into a program.
+pub struct LinkConfig {
+    /// The objects to link, placed in ROM in this order. The program is
+    /// named after the first.
+    filepaths: Vec<String>,
+    format: Option<Format>,
+    output: Option<String>,
+    platform: Option<String>,
+}
+
+impl LinkConfig {
+    pub fn new<I>(mut args: I) -> Result<LinkConfig, &'static str>
+    where
+        I: Iterator<Item = String>,
+    {
+        args.next();
+
+        let mut filepaths = Vec::new();
+        let mut format = None;
+        let mut output = None;
+        let mut platform = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" | "-f" => format = Some(format_arg(args.next())?),
+                "--output" | "-o" => match args.next() {
+                    Some(path) => output = Some(path),
+                    None => return Err("--output expects a file path."),
+                },
+                "--platform" | "-p" => match args.next() {
+                    Some(name) => platform = Some(name),
+                    None => return Err("--platform expects a platform name or file."),
+                },
+                _ if arg.starts_with('-') => return Err("Unknown option."),
+                _ => filepaths.push(arg),
+            }
+        }
+
+        if filepaths.is_empty() {
+            return Err("Did not receive an object file.");
+        }
+
+        Ok(LinkConfig {
+            filepaths,
+            format,
+            output,
+            platform,
+        })
+    }
+}
+
+/// The options of `hackfmt`, which rewrites assembly files in canonical
+/// style.
+pub struct FormatConfig {
+    filepaths: Vec<String>,
+    /// Report files that are not formatted instead of rewriting them.
+    check: bool,
+}
+
+impl FormatConfig {
+    pub fn new<I>(mut args: I) -> Result<FormatConfig, &'static str>
+    where
+        I: Iterator<Item = String>,
+    {
+        args.next();
+
+        let mut filepaths = Vec::new();
+        let mut check = false;
+
+        for arg in args {
+            match arg.as_str() {
+                "--check" => check = true,
+                _ if arg.starts_with('-') => return Err("Unknown option."),
+                _ => filepaths.push(arg),
+            }
+        }
+
+        if filepaths.is_empty() {
+            return Err("Did not receive a file path.");
+        }
+
+        Ok(FormatConfig { filepaths, check })
+    }
+}
+
+/// The options of `hack-lsp`, the language server editors start.
+pub struct ServerConfig {
+    platform: Option<String>,
+}
+
+impl ServerConfig {
+    pub fn new<I>(mut args: I) -> Result<ServerConfig, &'static str>
+    where
+        I: Iterator<Item = String>,
+    {
+        args.next();
+
+        let mut platform = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--platform" | "-p" => match args.next() {
+                    Some(name) => platform = Some(name),
+                    None => return Err("--platform expects a platform name or file."),
+                },
+                // Editors often pass `--stdio`, the only transport there is
+                "--stdio" => {}
+                _ => return Err("Unknown option."),
+            }
+        }
 
-        Ok(Config { filepath })
+        Ok(ServerConfig { platform })
     }
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filepath.clone())?;
+    let filepath = &config.filepaths[0];
+    if let Some(format) = Format::from_extension(filepath) {
+        return disassemble(filepath, format);
+    }
+
+    let platform = load_platform(config.platform.as_deref())?;
+    let mut contents = Vec::new();
+    for filepath in &config.filepaths {
+        contents.push(fs::read_to_string(filepath)?);
+    }
+    let sources: Vec<(&str, &str)> = config
+        .filepaths
+        .iter()
+        .map(String::as_str)
+        .zip(contents.iter().map(String::as_str))
+        .collect();
+    let print_errors = |errors: &AssembleErrors| {
+        for error in errors.errors() {
+            eprintln!("{}", error);
+        }
+    };
+
+    if config.object {
+        let object = Assembler::build_object(&platform, &sources, &|path| fs::read_to_string(path))
+            .inspect_err(print_errors)?;
+        let output_file = match &config.output {
+            Some(path) => path.clone(),
+            None => with_extension(filepath, OBJECT_EXTENSION),
+        };
+        fs::write(output_file, object.write())?;
+        return Ok(());
+    }
 
-    let machine_code = Assembler::assemble(contents);
+    let mut assembly = Assembler::build_for(&platform, &sources, &|path| fs::read_to_string(path))
+        .inspect_err(print_errors)?;
 
-    let output_file = config.filepath.replace(".asm", ".hack");
-    fs::write(output_file, machine_code)?;
+    let graph = if config.analyze || config.cfg {
+        let (graph, found) = analyze(&platform, &assembly);
+        if config.analyze {
+            // Unreachable code the linter found is already reported
+            let mut warnings = std::mem::take(&mut assembly.warnings);
+            let reported = |found: &AssembleWarning| {
+                warnings.iter().any(|warning| {
+                    (&warning.file, warning.line, warning.kind)
+                        == (&found.file, found.line, found.kind)
+                })
+            };
+            let found: Vec<AssembleWarning> = found
+                .into_iter()
+                .filter(|warning| !reported(warning))
+                .collect();
+            warnings.extend(found);
+            lint::sort(&mut warnings, &assembly);
+            assembly.warnings = warnings;
+        }
+        Some(graph)
+    } else {
+        None
+    };
+    for warning in &assembly.warnings {
+        eprintln!("{}", warning);
+    }
+
+    let format = config.output_format();
+    let output_file = match &config.output {
+        Some(path) => path.clone(),
+        None => with_extension(filepath, format.extension()),
+    };
+    write_program(&output_file, format, &assembly.words())?;
+
+    if config.listing {
+        let listing_file = with_extension(filepath, "lst");
+        fs::write(listing_file, generate_listing(&assembly))?;
+    }
+
+    if let Some(symbols) = config.symbols {
+        let map = SymbolMap::new(&platform, &assembly);
+        let (extension, contents) = match symbols {
+            SymbolFormat::Text => ("sym", map.write()),
+            SymbolFormat::Json => ("json", map.write_json()),
+        };
+        fs::write(with_extension(filepath, extension), contents)?;
+    }
+
+    if let Some(graph) = graph.filter(|_| config.cfg) {
+        fs::write(with_extension(filepath, "dot"), graph.write_dot())?;
+    }
+
+    Ok(())
+}
+
+pub fn run_linker(config: LinkConfig) -> Result<(), Box<dyn Error>> {
+    let platform = load_platform(config.platform.as_deref())?;
+    let mut objects = Vec::new();
+    for filepath in &config.filepaths {
+        let object = ObjectFile::read(&fs::read_to_string(filepath)?)
+            .map_err(|error| format!("{}: {}", filepath, error))?;
+        objects.push((filepath.as_str(), object));
+    }
+
+    let words = link(&platform, &objects).inspect_err(|errors| {
+        for error in errors.errors() {
+            eprintln!("{}", error);
+        }
+    })?;
+
+    let format = config
+        .format
+        .or_else(|| config.output.as_deref().and_then(Format::from_extension))
+        .unwrap_or(Format::Hack);
+    let output_file = match &config.output {
+        Some(path) => path.clone(),
+        None => with_extension(&config.filepaths[0], format.extension()),
+    };
+    write_program(&output_file, format, &words)?;
+
+    Ok(())
+}
+
+pub fn run_formatter(config: FormatConfig) -> Result<(), Box<dyn Error>> {
+    let mut unformatted = 0;
+    for filepath in &config.filepaths {
+        let source = fs::read_to_string(filepath)?;
+        let formatted = formatter::format(&source);
+        if formatted == source {
+            continue;
+        }
+
+        if config.check {
+            eprintln!("{}: not formatted", filepath);
+            unformatted += 1;
+        } else {
+            fs::write(filepath, formatted)?;
+        }
+    }
+
+    match unformatted {
+        0 => Ok(()),
+        1 => Err("1 file is not formatted".into()),
+        count => Err(format!("{} files are not formatted", count).into()),
+    }
+}
+
+pub fn run_server(config: ServerConfig) -> Result<(), Box<dyn Error>> {
+    let platform = load_platform(config.platform.as_deref())?;
+    let mut server = Server::new(platform);
+    server.serve(io::stdin().lock(), io::stdout().lock())?;
+
+    if server.exit_code() != 0 {
+        return Err("the client exited without shutting the server down".into());
+    }
     Ok(())
 }
+
+/// A built-in platform, else a profile read from the file `name`.
+fn load_platform(name: Option<&str>) -> Result<Platform, Box<dyn Error>> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(Platform::default()),
+    };
+    if let Some(platform) = Platform::from_name(name) {
+        return Ok(platform);
+    }
+
+    let text = fs::read_to_string(name).map_err(|error| {
+        format!(
+            "{}: not a built-in platform or a readable file ({})",
+            name, error
+        )
+    })?;
+    let platform = Platform::read(&text).map_err(|error| format!("{}: {}", name, error))?;
+    Ok(platform)
+}
+
+fn write_program(path: &str, format: Format, words: &[u16]) -> Result<(), Box<dyn Error>> {
+    let mut output = BufWriter::new(File::create(path)?);
+    format.write_to(words, &mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn with_extension(filepath: &str, extension: &str) -> String {
+    Path::new(filepath)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn disassemble(filepath: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    let words = format.read(&fs::read(filepath)?)?;
+    let assembly = Disassembler::disassemble_words(filepath, &words).inspect_err(|errors| {
+        for error in errors.errors() {
+            eprintln!("{}", error);
+        }
+    })?;
+
+    let output_file = with_extension(filepath, "dis.asm");
+    fs::write(output_file, assembly)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.into_iter()
+    }
+
+    #[test]
+    fn config() {
+        let config = Config::new(args(&["rust", "Max.asm"])).unwrap();
+        assert_eq!(config.filepaths, vec!["Max.asm"]);
+        assert!(!config.listing);
+
+        let config = Config::new(args(&["rust", "--listing", "Max.asm"])).unwrap();
+        assert_eq!(config.filepaths, vec!["Max.asm"]);
+        assert!(config.listing);
+
+        assert_eq!(config.output_format(), Format::Hack);
+
+        let config = Config::new(args(&["rust", "-o", "out/Max.hex", "Max.asm"])).unwrap();
+        assert_eq!(config.output, Some(String::from("out/Max.hex")));
+        assert_eq!(config.output_format(), Format::IntelHex);
+
+        let config =
+            Config::new(args(&["rust", "Max.asm", "-o", "Max.hex", "-f", "memh"])).unwrap();
+        assert_eq!(config.output_format(), Format::ReadMemH);
+
+        assert!(Config::new(args(&["rust", "--format", "elf", "Max.asm"])).is_err());
+        assert!(Config::new(args(&["rust", "Max.asm", "--output"])).is_err());
+        assert!(Config::new(args(&["rust"])).is_err());
+        assert!(Config::new(args(&["rust", "--bogus", "Max.asm"])).is_err());
+
+        let config = Config::new(args(&["rust", "Main.asm", "-l", "Math.asm"])).unwrap();
+        assert_eq!(config.filepaths, vec!["Main.asm", "Math.asm"]);
+        assert!(Config::new(args(&["rust", "Main.hack", "Math.hack"])).is_err());
+
+        let config = Config::new(args(&["rust", "-c", "Math.asm"])).unwrap();
+        assert!(config.object);
+        assert!(Config::new(args(&["rust", "-c", "-l", "Math.asm"])).is_err());
+
+        let config = Config::new(args(&["rust", "-s", "json", "Math.asm"])).unwrap();
+        assert_eq!(config.symbols, Some(SymbolFormat::Json));
+        assert!(Config::new(args(&["rust", "--symbols", "Math.asm"])).is_err());
+        assert!(Config::new(args(&["rust", "-c", "-s", "text", "Math.asm"])).is_err());
+
+        let config = Config::new(args(&["rust", "-a", "--cfg", "Math.asm"])).unwrap();
+        assert!(config.analyze && config.cfg);
+        assert!(Config::new(args(&["rust", "-c", "--cfg", "Math.asm"])).is_err());
+
+        let config = Config::new(args(&["rust", "-p", "hack32k", "Math.asm"])).unwrap();
+        assert_eq!(config.platform, Some(String::from("hack32k")));
+        assert!(Config::new(args(&["rust", "Math.asm", "--platform"])).is_err());
+    }
+
+    #[test]
+    fn link_config() {
+        let config = LinkConfig::new(args(&[
+            "hack-ld",
+            "Main.hobj",
+            "Math.hobj",
+            "-o",
+            "Pong.hex",
+        ]))
+        .unwrap();
+        assert_eq!(config.filepaths, vec!["Main.hobj", "Math.hobj"]);
+        assert_eq!(config.output, Some(String::from("Pong.hex")));
+
+        assert!(LinkConfig::new(args(&["hack-ld"])).is_err());
+        assert!(LinkConfig::new(args(&["hack-ld", "-f", "elf", "Main.hobj"])).is_err());
+    }
+
+    #[test]
+    fn server_config() {
+        let config = ServerConfig::new(args(&["hack-lsp", "--stdio", "-p", "hack32k"])).unwrap();
+        assert_eq!(config.platform, Some(String::from("hack32k")));
+
+        assert!(ServerConfig::new(args(&["hack-lsp"]))
+            .unwrap()
+            .platform
+            .is_none());
+        assert!(ServerConfig::new(args(&["hack-lsp", "Max.asm"])).is_err());
+    }
+
+    #[test]
+    fn format_config() {
+        let config =
+            FormatConfig::new(args(&["hackfmt", "--check", "Max.asm", "Add.asm"])).unwrap();
+        assert!(config.check);
+        assert_eq!(config.filepaths, vec!["Max.asm", "Add.asm"]);
+
+        assert!(
+            !FormatConfig::new(args(&["hackfmt", "Max.asm"]))
+                .unwrap()
+                .check
+        );
+        assert!(FormatConfig::new(args(&["hackfmt", "--check"])).is_err());
+        assert!(FormatConfig::new(args(&["hackfmt", "-w", "Max.asm"])).is_err());
+    }
+}
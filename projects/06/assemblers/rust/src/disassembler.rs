@@ -0,0 +1,250 @@
+use crate::error::{AssembleError, AssembleErrors, ErrorKind};
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+pub struct Disassembler {}
+
+impl Disassembler {
+    pub fn disassemble(filename: &str, machine_code: &str) -> Result<String, AssembleErrors> {
+        let file: Rc<str> = Rc::from(filename);
+        let mut errors = AssembleErrors::new();
+        let mut words = Vec::new();
+        // The line and column each word was read from
+        let mut positions = Vec::new();
+
+        for (index, line) in machine_code.lines().enumerate() {
+            let word = line.trim();
+            if word.is_empty() {
+                continue;
+            }
+
+            let position = (index + 1, line.find(word).unwrap_or(0) + 1);
+            match Disassembler::parse_word(word) {
+                Some(parsed) => {
+                    words.push(parsed);
+                    positions.push(position);
+                }
+                None => errors.push(AssembleError {
+                    file: Rc::clone(&file),
+                    line: position.0,
+                    column: position.1,
+                    text: String::from(word),
+                    kind: ErrorKind::InvalidWord,
+                    note: None,
+                }),
+            }
+        }
+
+        match Disassembler::disassemble_words(filename, &words) {
+            Ok(assembly) if errors.is_empty() => Ok(assembly),
+            Ok(_) => Err(errors),
+            Err(invalid) => {
+                for error in invalid.errors() {
+                    let (line, column) = positions[error.line - 1];
+                    errors.push(AssembleError {
+                        line,
+                        column,
+                        ..error.clone()
+                    });
+                }
+                errors.sort(&[filename]);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Disassembles words read from any of the machine-code formats.
+    pub fn disassemble_words(filename: &str, words: &[u16]) -> Result<String, AssembleErrors> {
+        let file: Rc<str> = Rc::from(filename);
+        let mut errors = AssembleErrors::new();
+        let mut program = Vec::new();
+
+        for (index, word) in words.iter().enumerate() {
+            match Instruction::decode(*word) {
+                Some(instruction) => program.push(instruction),
+                None => errors.push(AssembleError {
+                    file: Rc::clone(&file),
+                    line: index + 1,
+                    column: 1,
+                    text: format!("{:016b}", word),
+                    kind: ErrorKind::InvalidWord,
+                    note: None,
+                }),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Disassembler::render(&program))
+    }
+
+    fn render(program: &[Instruction]) -> String {
+        let labels = Disassembler::jump_targets(program);
+        let mut assembly = String::new();
+
+        for (address, decoded) in program.iter().enumerate() {
+            if labels.contains(&(address as u16)) {
+                assembly.push_str(&format!("(L{})\n", address));
+            }
+
+            let next = program.get(address + 1);
+            let instruction = match decoded {
+                Instruction::Address(value) => match next {
+                    Some(next) if next.is_jump() && labels.contains(value) => {
+                        format!("@L{}", value)
+                    }
+                    Some(next) if next.reads_or_writes_memory() => {
+                        format!("@{}", Disassembler::memory_symbol(*value))
+                    }
+                    _ => format!("@{}", value),
+                },
+                Instruction::Compute { .. } => decoded.to_string(),
+            };
+
+            assembly.push_str("    ");
+            assembly.push_str(&instruction);
+            assembly.push('\n');
+        }
+
+        if labels.contains(&(program.len() as u16)) {
+            assembly.push_str(&format!("(L{})\n", program.len()));
+        }
+
+        assembly
+    }
+
+    fn parse_word(word: &str) -> Option<u16> {
+        if word.len() != 16 || !word.chars().all(|c| c == '0' || c == '1') {
+            return None;
+        }
+
+        u16::from_str_radix(word, 2).ok()
+    }
+
+    /// Addresses loaded into A right before a jump, which become labels as long
+    /// as they land inside the program (or directly after its last instruction).
+    fn jump_targets(program: &[Instruction]) -> BTreeSet<u16> {
+        program
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [Instruction::Address(target), next] if next.is_jump() => Some(*target),
+                _ => None,
+            })
+            .filter(|&target| target as usize <= program.len())
+            .collect()
+    }
+
+    fn memory_symbol(address: u16) -> String {
+        match address {
+            0..=15 => format!("R{}", address),
+            16384 => String::from("SCREEN"),
+            24576 => String::from("KBD"),
+            _ => address.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use std::fs;
+
+    #[test]
+    fn disassemble() {
+        let machine_code = [
+            "0000000000000000",
+            "1111110000010000",
+            "0000000000000111",
+            "1110001100000001",
+            "0100000000000000",
+            "1110111010001000",
+            "0000000000101010",
+            "1110110000010000",
+            "0000000000001000",
+            "1110101010000111",
+        ]
+        .join("\n");
+
+        let assembly = [
+            "    @R0",
+            "    D=M",
+            "    @L7",
+            "    D;JGT",
+            "    @SCREEN",
+            "    M=-1",
+            "    @42",
+            "(L7)",
+            "    D=A",
+            "(L8)",
+            "    @L8",
+            "    0;JMP",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(
+            Disassembler::disassemble("", &machine_code).unwrap(),
+            assembly
+        );
+    }
+
+    #[test]
+    fn invalid_words() {
+        let machine_code = "0000000000000000\n101\n\n  1010101010101010\n";
+        let errors = Disassembler::disassemble("", machine_code).unwrap_err();
+        let positions: Vec<(usize, usize)> = errors
+            .errors()
+            .iter()
+            .map(|error| (error.line, error.column))
+            .collect();
+
+        assert_eq!(positions, vec![(2, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn disassemble_words() {
+        let words = [0x0007, 0xE301, 0xA000];
+        assert_eq!(
+            Disassembler::disassemble_words("", &words[..2]).unwrap(),
+            "    @7\n    D;JGT\n"
+        );
+
+        let errors = Disassembler::disassemble_words("Max.bin", &words).unwrap_err();
+        assert_eq!(
+            errors.errors()[0].to_string(),
+            "Max.bin:3:1: error: invalid machine word `1010000000000000`"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for path in &[
+            "../../add/Add.asm",
+            "../../max/Max.asm",
+            "../../rect/Rect.asm",
+        ] {
+            let source = fs::read_to_string(path).unwrap();
+            let machine_code = Assembler::assemble(source).unwrap();
+
+            let disassembly = Disassembler::disassemble("", &machine_code).unwrap();
+            let round_trip = Assembler::assemble(disassembly).unwrap();
+
+            assert_eq!(machine_code, round_trip, "{}", path);
+        }
+    }
+
+    #[test]
+    fn disassemble_hack_file() {
+        let machine_code = fs::read_to_string("../../../05/Max.hack").unwrap();
+        let disassembly = Disassembler::disassemble("", &machine_code).unwrap();
+
+        assert_eq!(
+            Assembler::assemble(disassembly).unwrap(),
+            machine_code.replace("\r\n", "\n")
+        );
+    }
+}
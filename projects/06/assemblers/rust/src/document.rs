@@ -0,0 +1,365 @@
+//! What the language server knows about one open file: the program
+//! assembled as far as it goes, its symbols, and where each symbol appears
+//! in the text. Lines and characters count from 0, as in the language
+//! server protocol, and characters are counted as `char`s, which matches
+//! the protocol's UTF-16 offsets for the ASCII that assembly is written in.
+
+use crate::assembler::{scoped_name, AssembledLine, Assembler, Assembly};
+use crate::error::{AssembleError, AssembleErrors, AssembleWarning};
+use crate::instruction::Instruction;
+use crate::lexer;
+use crate::parser::{CommandType, Loader, Parser};
+use crate::platform::Platform;
+use crate::symbol_map::{SymbolKind, SymbolMap};
+use std::collections::{HashMap, HashSet};
+
+/// Part of one line of the text.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Range {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Range {
+    fn contains(&self, line: usize, character: usize) -> bool {
+        self.line == line && (self.start..=self.end).contains(&character)
+    }
+}
+
+/// A symbol written in the text, by the name it has in the symbol table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Occurrence {
+    pub name: String,
+    pub range: Range,
+    /// Whether the occurrence defines a label or constant.
+    pub definition: bool,
+}
+
+pub struct Diagnostic {
+    pub range: Range,
+    pub error: bool,
+    pub message: String,
+    /// The name that allows a warning in an `allow` comment.
+    pub code: Option<&'static str>,
+}
+
+pub struct Document {
+    pub path: String,
+    pub text: String,
+    pub assembly: Assembly,
+    pub errors: AssembleErrors,
+    pub symbols: SymbolMap,
+    occurrences: Vec<Occurrence>,
+}
+
+impl Document {
+    /// Assembles `text` as the file at `path`, reading its includes with
+    /// `load`.
+    pub fn new(platform: &Platform, path: &str, text: String, load: Loader) -> Document {
+        let (assembly, errors) = Assembler::check(platform, &[(path, &text)], load);
+        let symbols = SymbolMap::new(platform, &assembly);
+        let mut document = Document {
+            path: String::from(path),
+            text,
+            assembly,
+            errors,
+            symbols,
+            occurrences: Vec::new(),
+        };
+        document.occurrences = document.find_occurrences();
+        document
+    }
+
+    /// Matches the symbols the assembler found on each line of this file to
+    /// the words of its text. Symbols that only appear in a macro's
+    /// expansion have no occurrence.
+    fn find_occurrences(&self) -> Vec<Occurrence> {
+        let mut used: HashMap<usize, HashSet<&str>> = HashMap::new();
+        let mut defined: HashMap<usize, String> = HashMap::new();
+        for line in self.lines() {
+            let number = line.span.line;
+            used.entry(number)
+                .or_default()
+                .extend(line.symbols.iter().map(String::as_str));
+            match line.command {
+                CommandType::Jump if line.span.depth == 0 => {
+                    if let Some(label) = line.symbols.first() {
+                        defined.insert(number, label.clone());
+                    }
+                }
+                CommandType::Constant => {
+                    if let Some(name) = lexer::words(lexer::strip_comment(&line.span.raw)).nth(1) {
+                        defined.insert(number, scoped_name(&self.path, name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut occurrences = Vec::new();
+        for line in lexer::lines(&self.text) {
+            for (start, word) in symbol_words(line.code()) {
+                let name = scoped_name(&self.path, word);
+                let definition = defined.get(&line.number) == Some(&name);
+                let used = used
+                    .get(&line.number)
+                    .is_some_and(|used| used.contains(name.as_str()));
+                if definition || used {
+                    occurrences.push(Occurrence {
+                        name,
+                        range: Range {
+                            line: line.number - 1,
+                            start,
+                            end: start + word.chars().count(),
+                        },
+                        definition,
+                    });
+                }
+            }
+        }
+        occurrences
+    }
+
+    /// The assembled lines written in this file rather than an included one.
+    fn lines(&self) -> impl Iterator<Item = &AssembledLine> {
+        self.assembly
+            .lines
+            .iter()
+            .filter(move |line| *line.span.file == *self.path)
+    }
+
+    pub fn occurrence_at(&self, line: usize, character: usize) -> Option<&Occurrence> {
+        self.occurrences
+            .iter()
+            .find(|occurrence| occurrence.range.contains(line, character))
+    }
+
+    /// Where `name` is defined: its label or constant definition, or the
+    /// first use of a variable. Predefined symbols are not defined in the
+    /// text.
+    pub fn definition(&self, name: &str) -> Option<&Occurrence> {
+        let mut occurrences = self
+            .occurrences
+            .iter()
+            .filter(|occurrence| occurrence.name == name);
+        match self.kind(name)? {
+            SymbolKind::Variable => occurrences.next(),
+            SymbolKind::Predefined => None,
+            _ => occurrences.find(|occurrence| occurrence.definition),
+        }
+    }
+
+    pub fn references<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Occurrence> {
+        self.occurrences
+            .iter()
+            .filter(move |occurrence| occurrence.name == name)
+    }
+
+    pub fn kind(&self, name: &str) -> Option<SymbolKind> {
+        self.entry(name).map(|(kind, _)| kind)
+    }
+
+    fn entry(&self, name: &str) -> Option<(SymbolKind, u16)> {
+        self.symbols
+            .symbols
+            .iter()
+            .find(|entry| entry.scoped_name() == name)
+            .map(|entry| (entry.kind, entry.address))
+    }
+
+    /// What a symbol stands for, such as `label, ROM 2`.
+    pub fn describe(&self, name: &str) -> Option<String> {
+        let (kind, address) = self.entry(name)?;
+        Some(match kind.memory() {
+            Some(memory) => format!("{}, {} {}", kind.name(), memory.to_uppercase(), address),
+            None => format!("{} {}", kind.name(), address),
+        })
+    }
+
+    /// The instructions line `line` assembled to, with their ROM address.
+    /// A pseudo-instruction gives several, while the lines a macro expands
+    /// to belong to its definition.
+    pub fn instructions(&self, line: usize) -> Vec<(u16, Instruction)> {
+        self.lines()
+            .filter(|assembled| assembled.span.line == line + 1)
+            .filter_map(|assembled| Some((assembled.address, assembled.instruction?)))
+            .collect()
+    }
+
+    /// Label definitions, in the order they are written.
+    pub fn labels(&self) -> impl Iterator<Item = &Occurrence> {
+        self.occurrences.iter().filter(move |occurrence| {
+            occurrence.definition && self.kind(&occurrence.name) == Some(SymbolKind::Label)
+        })
+    }
+
+    /// Errors and warnings for this file. Errors in included files are
+    /// reported at the top, with their location in the message.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for error in self.errors.errors() {
+            diagnostics.push(if *error.file == *self.path {
+                Diagnostic {
+                    range: self.range(error.line, error.column, &error.text),
+                    error: true,
+                    message: message(error),
+                    code: None,
+                }
+            } else {
+                Diagnostic {
+                    range: Range {
+                        line: 0,
+                        start: 0,
+                        end: 0,
+                    },
+                    error: true,
+                    message: error.to_string(),
+                    code: None,
+                }
+            });
+        }
+        for warning in &self.assembly.warnings {
+            if *warning.file == *self.path {
+                diagnostics.push(Diagnostic {
+                    range: self.range(warning.line, warning.column, &warning.text),
+                    error: false,
+                    message: warning_message(warning),
+                    code: Some(warning.kind.name()),
+                });
+            }
+        }
+
+        diagnostics
+    }
+
+    /// The range of `text` reported at a line and column counting from 1,
+    /// kept within the line.
+    fn range(&self, line: usize, column: usize, text: &str) -> Range {
+        let length = lexer::lines(&self.text)
+            .nth(line.saturating_sub(1))
+            .map_or(0, |line| line.text.chars().count());
+        let start = column.saturating_sub(1).min(length);
+        Range {
+            line: line.saturating_sub(1),
+            start,
+            end: (start + text.chars().count()).min(length),
+        }
+    }
+
+    /// The text of line `line` before `character`.
+    pub fn prefix(&self, line: usize, character: usize) -> &str {
+        let text = lexer::lines(&self.text)
+            .nth(line)
+            .map_or("", |line| line.text);
+        match text.char_indices().nth(character) {
+            Some((index, _)) => &text[..index],
+            None => text,
+        }
+    }
+}
+
+fn message(error: &AssembleError) -> String {
+    match &error.note {
+        Some(note) => format!("{} `{}` ({})", error.kind, error.text, note),
+        None => format!("{} `{}`", error.kind, error.text),
+    }
+}
+
+fn warning_message(warning: &AssembleWarning) -> String {
+    match &warning.note {
+        Some(note) => format!("{} `{}` ({})", warning.kind, warning.text, note),
+        None => format!("{} `{}`", warning.kind, warning.text),
+    }
+}
+
+/// The words of `code` that could be symbols, with the character they
+/// start at.
+fn symbol_words(code: &str) -> Vec<(usize, &str)> {
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || "_.$:".contains(c);
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (character, (index, c)) in code.char_indices().chain([(code.len(), ' ')]).enumerate() {
+        match (start, is_symbol_char(c)) {
+            (None, true) => start = Some((character, index)),
+            (Some((first, from)), false) => {
+                let word = &code[from..index];
+                if Parser::is_valid_symbol(word) {
+                    words.push((first, word));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn document(text: &str) -> Document {
+        Document::new(&Platform::hack(), "Main.asm", String::from(text), &|_| {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        })
+    }
+
+    #[test]
+    fn occurrences() {
+        let document = document(
+            "@i\nM=1\n.equ ROWS 256\n(LOOP) // back to @LOOP\n  goto LOOP\n@ROWS+i\n@_tmp",
+        );
+        let range = |line, start, end| Range { line, start, end };
+        let ranges = |name| -> Vec<Range> {
+            document
+                .references(name)
+                .map(|occurrence| occurrence.range)
+                .collect()
+        };
+
+        assert_eq!(ranges("i"), [range(0, 1, 2), range(5, 6, 7)]);
+        assert_eq!(ranges("LOOP"), [range(3, 1, 5), range(4, 7, 11)]);
+        assert_eq!(ranges("ROWS"), [range(2, 5, 9), range(5, 1, 5)]);
+        assert_eq!(ranges("Main.asm::_tmp"), [range(6, 1, 5)]);
+
+        assert_eq!(document.occurrence_at(4, 9).unwrap().name, "LOOP");
+        assert_eq!(document.occurrence_at(4, 3), None);
+        assert_eq!(document.definition("LOOP").unwrap().range, range(3, 1, 5));
+        assert_eq!(document.definition("i").unwrap().range, range(0, 1, 2));
+        assert_eq!(document.definition("SCREEN"), None);
+
+        assert_eq!(document.describe("LOOP").unwrap(), "label, ROM 2");
+        assert_eq!(document.describe("i").unwrap(), "variable, RAM 16");
+        assert_eq!(document.describe("ROWS").unwrap(), "constant 256");
+        assert_eq!(document.describe("KBD").unwrap(), "predefined, RAM 24576");
+        assert_eq!(document.instructions(4).len(), 2);
+        assert_eq!(document.labels().count(), 1);
+        assert_eq!(document.prefix(4, 4), "  go");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let document = document("@i\nM=1\nD=X\n(LOOP)\n@LOOP\n0;JMP\n.include \"lib.asm\"");
+        let diagnostics = document.diagnostics();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                line: 2,
+                start: 2,
+                end: 3,
+            }
+        );
+        assert_eq!(diagnostics[0].message, "unknown computation `X`");
+        assert!(diagnostics[1]
+            .message
+            .starts_with("cannot read included file"));
+        // The rest of the program still has its symbols
+        assert_eq!(document.describe("LOOP").unwrap(), "label, ROM 3");
+    }
+}
@@ -0,0 +1,323 @@
+use std::{error::Error, fmt, io, rc::Rc};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorKind {
+    UnknownComp,
+    BadDest,
+    BadJump,
+    MalformedLabel,
+    AddressOutOfRange,
+    InvalidWord,
+    MalformedMacro,
+    MacroArguments,
+    MacroRecursion,
+    MacroSize,
+    MalformedPseudo,
+    MalformedExpression,
+    MalformedConstant,
+    UndefinedSymbol,
+    DuplicateSymbol,
+    DivisionByZero,
+    MalformedInclude,
+    IncludeNotFound,
+    IncludeCycle,
+    NotRelocatable,
+    OutOfVariables,
+    RomOverflow,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            ErrorKind::UnknownComp => "unknown computation",
+            ErrorKind::BadDest => "invalid destination",
+            ErrorKind::BadJump => "invalid jump",
+            ErrorKind::MalformedLabel => "malformed label",
+            ErrorKind::AddressOutOfRange => "address out of range",
+            ErrorKind::InvalidWord => "invalid machine word",
+            ErrorKind::MalformedMacro => "malformed macro definition",
+            ErrorKind::MacroArguments => "wrong number of macro arguments",
+            ErrorKind::MacroRecursion => "macro expansion too deep",
+            ErrorKind::MacroSize => "macro expansion too large",
+            ErrorKind::MalformedPseudo => "malformed pseudo-instruction",
+            ErrorKind::MalformedExpression => "malformed expression",
+            ErrorKind::MalformedConstant => "malformed constant definition",
+            ErrorKind::UndefinedSymbol => "undefined symbol",
+            ErrorKind::DuplicateSymbol => "symbol is already defined",
+            ErrorKind::DivisionByZero => "division by zero",
+            ErrorKind::MalformedInclude => "malformed include",
+            ErrorKind::IncludeNotFound => "cannot read included file",
+            ErrorKind::IncludeCycle => "include cycle",
+            ErrorKind::NotRelocatable => "expression cannot be relocated",
+            ErrorKind::OutOfVariables => "no RAM left for variable",
+            ErrorKind::RomOverflow => "program does not fit in ROM",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssembleError {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub kind: ErrorKind,
+    /// More context, such as where a duplicate symbol was first defined.
+    pub note: Option<String>,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(
+            f,
+            "{}:{}: error: {} `{}`",
+            self.line, self.column, self.kind, self.text
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Suspicious code that still assembles. Each kind can be allowed on a
+/// line with a comment naming it, such as `// allow(unused-label)`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WarningKind {
+    SingleUseVariable,
+    UnusedLabel,
+    CaseMismatch,
+    JumpToData,
+    UncheckedMemory,
+    UnreachableCode,
+    InfiniteLoop,
+    IndirectJump,
+    JumpOutOfRange,
+}
+
+impl WarningKind {
+    /// How the warning is named in an `allow` comment.
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningKind::SingleUseVariable => "single-use-variable",
+            WarningKind::UnusedLabel => "unused-label",
+            WarningKind::CaseMismatch => "case-mismatch",
+            WarningKind::JumpToData => "jump-to-data",
+            WarningKind::UncheckedMemory => "unchecked-memory",
+            WarningKind::UnreachableCode => "unreachable-code",
+            WarningKind::InfiniteLoop => "infinite-loop",
+            WarningKind::IndirectJump => "indirect-jump",
+            WarningKind::JumpOutOfRange => "jump-out-of-range",
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            WarningKind::SingleUseVariable => "variable is used only once",
+            WarningKind::UnusedLabel => "label is never used",
+            WarningKind::CaseMismatch => "symbol differs from another only in case",
+            WarningKind::JumpToData => "jump to a data address",
+            WarningKind::UncheckedMemory => "memory address may be out of range",
+            WarningKind::UnreachableCode => "unreachable code",
+            WarningKind::InfiniteLoop => "loop never exits",
+            WarningKind::IndirectJump => "jump target is not known",
+            WarningKind::JumpOutOfRange => "jump outside the program",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssembleWarning {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub kind: WarningKind,
+    pub note: Option<String>,
+}
+
+impl fmt::Display for AssembleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(
+            f,
+            "{}:{}: warning: {} `{}`",
+            self.line, self.column, self.kind, self.text
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        write!(f, " [{}]", self.kind.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct AssembleErrors {
+    errors: Vec<AssembleError>,
+}
+
+impl AssembleErrors {
+    pub fn new() -> AssembleErrors {
+        AssembleErrors { errors: Vec::new() }
+    }
+
+    pub fn push(&mut self, error: AssembleError) {
+        self.errors.push(error);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Sorts errors by file, in the order of `files`, then by line and
+    /// column. Files missing from `files` come last.
+    pub fn sort(&mut self, files: &[&str]) {
+        let position = |file: &str| {
+            files
+                .iter()
+                .position(|&other| other == file)
+                .unwrap_or(files.len())
+        };
+        self.errors.sort_by(|a, b| {
+            (position(&a.file), &a.file, a.line, a.column).cmp(&(
+                position(&b.file),
+                &b.file,
+                b.line,
+                b.column,
+            ))
+        });
+    }
+
+    pub fn errors(&self) -> &Vec<AssembleError> {
+        &self.errors
+    }
+}
+
+impl fmt::Display for AssembleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.errors.len() == 1 { "" } else { "s" };
+        write!(f, "aborting due to {} error{}", self.errors.len(), plural)
+    }
+}
+
+impl Error for AssembleErrors {}
+
+/// Why assembling from a reader into a writer failed.
+#[derive(Debug)]
+pub enum AssembleIoError {
+    Io(io::Error),
+    Assemble(AssembleErrors),
+}
+
+impl fmt::Display for AssembleIoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleIoError::Io(error) => write!(f, "{}", error),
+            AssembleIoError::Assemble(errors) => write!(f, "{}", errors),
+        }
+    }
+}
+
+impl Error for AssembleIoError {}
+
+impl From<io::Error> for AssembleIoError {
+    fn from(error: io::Error) -> AssembleIoError {
+        AssembleIoError::Io(error)
+    }
+}
+
+impl From<AssembleErrors> for AssembleIoError {
+    fn from(errors: AssembleErrors) -> AssembleIoError {
+        AssembleIoError::Assemble(errors)
+    }
+}
+
+/// A machine-code file that could not be read. `line` is 0 for binary files.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FormatError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl FormatError {
+    pub fn new(line: usize, message: &str) -> FormatError {
+        FormatError {
+            line,
+            message: String::from(message),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}: ", self.line)?;
+        }
+        write!(f, "error: {}", self.message)
+    }
+}
+
+impl Error for FormatError {}
+
+/// A problem combining object files, reported against the object at fault.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LinkError {
+    pub file: String,
+    pub message: String,
+}
+
+impl LinkError {
+    pub fn new(file: &str, message: &str) -> LinkError {
+        LinkError {
+            file: String::from(file),
+            message: String::from(message),
+        }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: error: {}", self.file, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct LinkErrors {
+    errors: Vec<LinkError>,
+}
+
+impl LinkErrors {
+    pub fn new() -> LinkErrors {
+        LinkErrors { errors: Vec::new() }
+    }
+
+    pub fn push(&mut self, error: LinkError) {
+        self.errors.push(error);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &Vec<LinkError> {
+        &self.errors
+    }
+}
+
+impl fmt::Display for LinkErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.errors.len() == 1 { "" } else { "s" };
+        write!(f, "aborting due to {} error{}", self.errors.len(), plural)
+    }
+}
+
+impl Error for LinkErrors {}
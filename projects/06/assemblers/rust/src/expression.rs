@@ -0,0 +1,332 @@
+//! Constant expressions, as used by `@SCREEN+32*10+4` and `.equ` directives.
+//! Operators bind like in C, from loosest to tightest: `|`, `&`, `<< >>`,
+//! `+ -`, `* /`, then unary `-`.
+
+use crate::error::ErrorKind;
+use std::{convert::TryFrom, fmt};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Or,
+    And,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// Binary operators by precedence level, loosest first.
+const LEVELS: [&[(&str, Operator)]; 5] = [
+    &[("|", Operator::Or)],
+    &[("&", Operator::And)],
+    &[("<<", Operator::ShiftLeft), (">>", Operator::ShiftRight)],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+    &[("*", Operator::Multiply), ("/", Operator::Divide)],
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
+    Number(i64),
+    Symbol(String),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+/// Where in the expression text parsing failed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExpressionError {
+    pub kind: ErrorKind,
+    pub offset: usize,
+}
+
+impl ExpressionError {
+    fn new(kind: ErrorKind, offset: usize) -> ExpressionError {
+        ExpressionError { kind, offset }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.$:".contains(c)
+}
+
+/// Splits `text` into tokens paired with their offset.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < text.len() {
+        let rest = &text[index..];
+        let c = rest.chars().next().unwrap_or_default();
+
+        if c.is_whitespace() {
+            index += c.len_utf8();
+            continue;
+        }
+
+        let (token, length) = if is_symbol_char(c) {
+            let length = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+            let word = &rest[..length];
+            if !c.is_ascii_digit() {
+                (Token::Symbol(word.to_string()), length)
+            } else {
+                match word.parse::<i64>() {
+                    Ok(number) => (Token::Number(number), length),
+                    Err(_) => return Err(ExpressionError::new(ErrorKind::MalformedLabel, index)),
+                }
+            }
+        } else if c == '(' {
+            (Token::Open, 1)
+        } else if c == ')' {
+            (Token::Close, 1)
+        } else {
+            match ["<<", ">>", "+", "-", "*", "/", "&", "|"]
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+            {
+                Some(operator) => (Token::Operator(operator), operator.len()),
+                None => return Err(ExpressionError::new(ErrorKind::MalformedExpression, index)),
+            }
+        };
+
+        tokens.push((index, token));
+        index += length;
+    }
+
+    Ok(tokens)
+}
+
+struct ExpressionParser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(offset, _)| *offset)
+    }
+
+    fn error(&self) -> ExpressionError {
+        ExpressionError::new(ErrorKind::MalformedExpression, self.offset())
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, ExpressionError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Operator(symbol)) = self.peek() {
+            let operator = match LEVELS[level].iter().find(|(entry, _)| entry == symbol) {
+                Some((_, operator)) => *operator,
+                None => break,
+            };
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ExpressionError> {
+        let token = self.peek().cloned();
+        self.position += 1;
+
+        match token {
+            Some(Token::Operator("-")) => Ok(Expression::Negate(Box::new(self.unary()?))),
+            Some(Token::Number(number)) => Ok(Expression::Number(number)),
+            Some(Token::Symbol(symbol)) => Ok(Expression::Symbol(symbol)),
+            Some(Token::Open) => {
+                let inner = self.binary(0)?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.error());
+                }
+                self.position += 1;
+                Ok(inner)
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error())
+            }
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, ExpressionError> {
+        let mut parser = ExpressionParser {
+            tokens: tokenize(text)?,
+            position: 0,
+            end: text.len(),
+        };
+
+        let expression = parser.binary(0)?;
+        if parser.position < parser.tokens.len() {
+            return Err(parser.error());
+        }
+        Ok(expression)
+    }
+
+    /// The symbols the expression refers to, in order of appearance.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expression::Number(_) => Vec::new(),
+            Expression::Symbol(symbol) => vec![symbol],
+            Expression::Negate(inner) => inner.symbols(),
+            Expression::Binary(_, left, right) => {
+                let mut symbols = left.symbols();
+                symbols.extend(right.symbols());
+                symbols
+            }
+        }
+    }
+
+    /// Evaluates the expression, looking symbols up with `lookup`. Errors
+    /// carry the text they are about, such as the undefined symbol.
+    pub fn evaluate(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<i64>,
+    ) -> Result<i64, (ErrorKind, String)> {
+        let overflow = || (ErrorKind::AddressOutOfRange, self.to_string());
+
+        match self {
+            Expression::Number(number) => Ok(*number),
+            Expression::Symbol(symbol) => {
+                lookup(symbol).ok_or_else(|| (ErrorKind::UndefinedSymbol, symbol.clone()))
+            }
+            Expression::Negate(inner) => inner.evaluate(lookup)?.checked_neg().ok_or_else(overflow),
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(lookup)?;
+                let right = right.evaluate(lookup)?;
+                let shift = u32::try_from(right).unwrap_or(u32::MAX);
+
+                let value = match operator {
+                    Operator::Or => Some(left | right),
+                    Operator::And => Some(left & right),
+                    Operator::ShiftLeft => left.checked_shl(shift),
+                    Operator::ShiftRight => left.checked_shr(shift),
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide if right == 0 => {
+                        return Err((ErrorKind::DivisionByZero, self.to_string()))
+                    }
+                    Operator::Divide => left.checked_div(right),
+                };
+                value.ok_or_else(overflow)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Number(number) => write!(f, "{}", number),
+            Expression::Symbol(symbol) => write!(f, "{}", symbol),
+            Expression::Negate(inner) => write!(f, "-{}", inner),
+            Expression::Binary(operator, left, right) => {
+                let symbol = LEVELS
+                    .iter()
+                    .flat_map(|level| level.iter())
+                    .find(|(_, entry)| entry == operator)
+                    .map(|(symbol, _)| *symbol)
+                    .unwrap_or_default();
+                write!(f, "({}{}{})", left, symbol, right)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(text: &str) -> Result<i64, (ErrorKind, String)> {
+        let lookup = |symbol: &str| match symbol {
+            "SCREEN" => Some(16384),
+            "ROWS" => Some(256),
+            "COLS" => Some(32),
+            _ => None,
+        };
+        Expression::parse(text).unwrap().evaluate(&lookup)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("SCREEN+32*10+4"), Ok(16708));
+        assert_eq!(evaluate("(ROWS*COLS)"), Ok(8192));
+        assert_eq!(evaluate("1+2<<3"), Ok(24));
+        assert_eq!(evaluate("6&3|8"), Ok(10));
+        assert_eq!(evaluate("100/7-2"), Ok(12));
+        assert_eq!(evaluate("-(2-5)*2"), Ok(6));
+        assert_eq!(evaluate("ROWS>>4"), Ok(16));
+    }
+
+    #[test]
+    fn symbols() {
+        let expression = Expression::parse("SCREEN+(ROWS-1)*COLS+SCREEN").unwrap();
+        assert_eq!(expression.symbols(), ["SCREEN", "ROWS", "COLS", "SCREEN"]);
+        assert!(Expression::parse("-4").unwrap().symbols().is_empty());
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        assert_eq!(
+            evaluate("SCREEN+WIDTH"),
+            Err((ErrorKind::UndefinedSymbol, String::from("WIDTH")))
+        );
+        assert_eq!(
+            evaluate("4/(2-2)").unwrap_err().0,
+            ErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            evaluate("1<<100").unwrap_err().0,
+            ErrorKind::AddressOutOfRange
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text| Expression::parse(text).unwrap_err();
+
+        assert_eq!(
+            error("SCREEN+"),
+            ExpressionError::new(ErrorKind::MalformedExpression, 7)
+        );
+        assert_eq!(
+            error("(1+2"),
+            ExpressionError::new(ErrorKind::MalformedExpression, 4)
+        );
+        assert_eq!(
+            error("2 3"),
+            ExpressionError::new(ErrorKind::MalformedExpression, 2)
+        );
+        assert_eq!(
+            error("1+2nd"),
+            ExpressionError::new(ErrorKind::MalformedLabel, 2)
+        );
+        assert_eq!(
+            error("4%2"),
+            ExpressionError::new(ErrorKind::MalformedExpression, 1)
+        );
+    }
+}
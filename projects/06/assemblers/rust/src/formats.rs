@@ -0,0 +1,431 @@
+use crate::error::FormatError;
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+/// The machine-code file formats the assembler can write and read back.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    /// The course's ASCII format, one 16 character binary word per line.
+    Hack,
+    /// Two raw bytes per word, most significant first.
+    BinaryBigEndian,
+    /// Two raw bytes per word, least significant first.
+    BinaryLittleEndian,
+    /// Intel HEX with byte addresses, each word stored big endian.
+    IntelHex,
+    /// A Verilog `$readmemb` memory-init file.
+    ReadMemB,
+    /// A Verilog `$readmemh` memory-init file.
+    ReadMemH,
+    /// A Logisim `v2.0 raw` ROM image.
+    Logisim,
+}
+
+/// The most words a program image can hold, the ROM of the course's
+/// computer. Readers reject images past it rather than allocate for them.
+const ROM_SIZE: usize = 0x8000;
+
+const FORMATS: [(Format, &str, &str); 7] = [
+    (Format::Hack, "hack", "hack"),
+    (Format::BinaryBigEndian, "bin", "bin"),
+    (Format::BinaryLittleEndian, "bin-le", "binle"),
+    (Format::IntelHex, "ihex", "hex"),
+    (Format::ReadMemB, "memb", "memb"),
+    (Format::ReadMemH, "memh", "memh"),
+    (Format::Logisim, "logisim", "rom"),
+];
+
+// Intel HEX data records carry at most this many bytes
+const HEX_RECORD_SIZE: usize = 16;
+const LOGISIM_HEADER: &str = "v2.0 raw";
+
+impl Format {
+    /// Looks a format up by the name given to `--format`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        FORMATS
+            .iter()
+            .find(|(_, entry, _)| *entry == name)
+            .map(|(format, _, _)| *format)
+    }
+
+    pub fn from_extension(path: &str) -> Option<Format> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        if extension == "mem" {
+            return Some(Format::ReadMemH);
+        }
+
+        FORMATS
+            .iter()
+            .find(|(_, _, entry)| *entry == extension)
+            .map(|(format, _, _)| *format)
+    }
+
+    pub fn extension(self) -> &'static str {
+        FORMATS
+            .iter()
+            .find(|(format, _, _)| *format == self)
+            .map(|(_, _, extension)| *extension)
+            .expect("every format is in the table")
+    }
+
+    pub fn write(self, words: &[u16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.write_to(words, &mut data)
+            .expect("writing to a Vec cannot fail");
+        data
+    }
+
+    /// Writes `words` to `output` as they are encoded, without building the
+    /// whole file in memory first.
+    pub fn write_to<W: Write>(self, words: &[u16], mut output: W) -> io::Result<()> {
+        match self {
+            Format::Hack | Format::ReadMemB => words
+                .iter()
+                .try_for_each(|word| writeln!(output, "{:016b}", word)),
+            Format::ReadMemH => words
+                .iter()
+                .try_for_each(|word| writeln!(output, "{:04x}", word)),
+            Format::BinaryBigEndian => words
+                .iter()
+                .try_for_each(|word| output.write_all(&word.to_be_bytes())),
+            Format::BinaryLittleEndian => words
+                .iter()
+                .try_for_each(|word| output.write_all(&word.to_le_bytes())),
+            Format::IntelHex => output.write_all(write_intel_hex(words).as_bytes()),
+            Format::Logisim => output.write_all(write_logisim(words).as_bytes()),
+        }
+    }
+
+    pub fn read(self, data: &[u8]) -> Result<Vec<u16>, FormatError> {
+        match self {
+            Format::BinaryBigEndian => read_binary(data, u16::from_be_bytes),
+            Format::BinaryLittleEndian => read_binary(data, u16::from_le_bytes),
+            _ => {
+                let text = std::str::from_utf8(data)
+                    .map_err(|_| FormatError::new(0, "file is not valid text"))?;
+
+                match self {
+                    Format::Hack => read_hack(text),
+                    Format::IntelHex => read_intel_hex(text),
+                    Format::ReadMemB => read_memory_init(text, 2),
+                    Format::ReadMemH => read_memory_init(text, 16),
+                    _ => read_logisim(text),
+                }
+            }
+        }
+    }
+}
+
+fn hex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![
+        data.len() as u8,
+        (address >> 8) as u8,
+        address as u8,
+        record_type,
+    ];
+    bytes.extend(data);
+
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", hex.concat())
+}
+
+fn write_intel_hex(words: &[u16]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut text = String::new();
+
+    for (index, chunk) in bytes.chunks(HEX_RECORD_SIZE).enumerate() {
+        text.push_str(&hex_record((index * HEX_RECORD_SIZE) as u16, 0x00, chunk));
+    }
+    text.push_str(&hex_record(0, 0x01, &[]));
+    text
+}
+
+fn write_logisim(words: &[u16]) -> String {
+    let mut text = format!("{}\n", LOGISIM_HEADER);
+    for line in words.chunks(8) {
+        let hex: Vec<String> = line.iter().map(|word| format!("{:x}", word)).collect();
+        text.push_str(&hex.join(" "));
+        text.push('\n');
+    }
+    text
+}
+
+fn read_binary(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<Vec<u16>, FormatError> {
+    if !data.len().is_multiple_of(2) {
+        return Err(FormatError::new(0, "file ends in the middle of a word"));
+    }
+
+    Ok(data
+        .chunks(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+fn parse_word(text: &str, radix: u32, line: usize) -> Result<u16, FormatError> {
+    u16::from_str_radix(text, radix)
+        .map_err(|_| FormatError::new(line, &format!("invalid word `{}`", text)))
+}
+
+fn read_hack(text: &str) -> Result<Vec<u16>, FormatError> {
+    let mut words = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let word = line.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if word.len() != 16 {
+            return Err(FormatError::new(
+                index + 1,
+                &format!("invalid word `{}`", word),
+            ));
+        }
+        words.push(parse_word(word, 2, index + 1)?);
+    }
+
+    Ok(words)
+}
+
+fn read_intel_hex(text: &str) -> Result<Vec<u16>, FormatError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut base = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |message: &str| FormatError::new(number, message);
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("expected `:`"))?;
+        if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error("invalid hex digits"));
+        }
+
+        let record: Vec<u8> = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap_or_default())
+            .collect();
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(error("record length does not match its byte count"));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+
+        let address = base + ((record[1] as usize) << 8 | record[2] as usize);
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => {
+                if address + data.len() > 2 * ROM_SIZE {
+                    return Err(error("address past the end of ROM"));
+                }
+                if bytes.len() < address + data.len() {
+                    bytes.resize(address + data.len(), 0);
+                }
+                bytes[address..address + data.len()].copy_from_slice(data);
+            }
+            0x01 => break,
+            0x02 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            0x04 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            0x03 | 0x05 => (),
+            _ => return Err(error("unsupported record type")),
+        }
+    }
+
+    read_binary(&bytes, u16::from_be_bytes)
+}
+
+/// Reads `$readmemb` (radix 2) and `$readmemh` (radix 16) files, including
+/// `//` comments, `_` separators and `@address` directives.
+fn read_memory_init(text: &str, radix: u32) -> Result<Vec<u16>, FormatError> {
+    let mut words = Vec::new();
+    let mut address = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let code = line.split("//").next().unwrap_or_default();
+
+        for token in code.split_whitespace() {
+            if let Some(target) = token.strip_prefix('@') {
+                address = usize::from_str_radix(target, 16).map_err(|_| {
+                    FormatError::new(index + 1, &format!("invalid address `{}`", token))
+                })?;
+                continue;
+            }
+
+            let word = parse_word(&token.replace('_', ""), radix, index + 1)?;
+            if address >= ROM_SIZE {
+                return Err(FormatError::new(index + 1, "address past the end of ROM"));
+            }
+            if words.len() <= address {
+                words.resize(address + 1, 0);
+            }
+            words[address] = word;
+            address += 1;
+        }
+    }
+
+    Ok(words)
+}
+
+/// Reads Logisim images, where `N*value` stands for N copies of value.
+fn read_logisim(text: &str) -> Result<Vec<u16>, FormatError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == LOGISIM_HEADER => (),
+        _ => return Err(FormatError::new(1, "expected a `v2.0 raw` header")),
+    }
+
+    let mut words = Vec::new();
+    for (index, line) in lines {
+        let code = line.split('#').next().unwrap_or_default();
+
+        for token in code.split_whitespace() {
+            let (count, value) = match token.split_once('*') {
+                Some((count, value)) => {
+                    let count = count.parse::<usize>().map_err(|_| {
+                        FormatError::new(index + 1, &format!("invalid count `{}`", token))
+                    })?;
+                    (count, value)
+                }
+                None => (1, token),
+            };
+
+            let word = parse_word(value, 16, index + 1)?;
+            if count > ROM_SIZE - words.len() {
+                return Err(FormatError::new(index + 1, "address past the end of ROM"));
+            }
+            words.extend(std::iter::repeat_n(word, count));
+        }
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: [u16; 3] = [0x0002, 0xEC10, 0xFFFF];
+
+    #[test]
+    fn formats_by_name_and_extension() {
+        assert_eq!(Format::from_name("ihex"), Some(Format::IntelHex));
+        assert_eq!(Format::from_name("bogus"), None);
+
+        assert_eq!(Format::from_extension("Max.hack"), Some(Format::Hack));
+        assert_eq!(
+            Format::from_extension("out/Max.hex"),
+            Some(Format::IntelHex)
+        );
+        assert_eq!(Format::from_extension("Max.mem"), Some(Format::ReadMemH));
+        assert_eq!(Format::from_extension("Max.rom"), Some(Format::Logisim));
+        assert_eq!(Format::from_extension("Max"), None);
+        assert_eq!(Format::Logisim.extension(), "rom");
+    }
+
+    #[test]
+    fn writers() {
+        assert_eq!(
+            Format::Hack.write(&WORDS),
+            b"0000000000000010\n1110110000010000\n1111111111111111\n"
+        );
+        assert_eq!(
+            Format::BinaryBigEndian.write(&WORDS),
+            vec![0x00, 0x02, 0xEC, 0x10, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            Format::BinaryLittleEndian.write(&WORDS),
+            vec![0x02, 0x00, 0x10, 0xEC, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            Format::IntelHex.write(&WORDS),
+            b":060000000002EC10FFFFFE\n:00000001FF\n"
+        );
+        assert_eq!(Format::ReadMemH.write(&WORDS), b"0002\nec10\nffff\n");
+        assert_eq!(Format::Logisim.write(&WORDS), b"v2.0 raw\n2 ec10 ffff\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let words: Vec<u16> = (0..100).map(|i| i * 661).collect();
+
+        for (format, _, _) in FORMATS.iter() {
+            let data = format.write(&words);
+            assert_eq!(format.read(&data), Ok(words.clone()), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn readers() {
+        let memh = "// Max\n@2\nec10 // D=A\nffff\n";
+        assert_eq!(
+            Format::ReadMemH.read(memh.as_bytes()),
+            Ok(vec![0, 0, 0xEC10, 0xFFFF])
+        );
+
+        let memb = "0000_0000_0000_0010\n";
+        assert_eq!(Format::ReadMemB.read(memb.as_bytes()), Ok(vec![2]));
+
+        let logisim = "v2.0 raw\n3*0 ec10 # load\n";
+        assert_eq!(
+            Format::Logisim.read(logisim.as_bytes()),
+            Ok(vec![0, 0, 0, 0xEC10])
+        );
+    }
+
+    #[test]
+    fn read_errors() {
+        assert_eq!(
+            Format::IntelHex.read(b":060000000002EC10FFFFFF\n"),
+            Err(FormatError::new(1, "checksum mismatch"))
+        );
+        assert_eq!(
+            Format::BinaryBigEndian.read(&[0x00, 0x02, 0xEC]),
+            Err(FormatError::new(0, "file ends in the middle of a word"))
+        );
+        assert_eq!(
+            Format::Hack.read(b"0000000000000010\n101\n"),
+            Err(FormatError::new(2, "invalid word `101`"))
+        );
+        assert_eq!(
+            Format::Logisim.read(b"2 ec10\n"),
+            Err(FormatError::new(1, "expected a `v2.0 raw` header"))
+        );
+    }
+
+    #[test]
+    fn rejects_images_past_rom() {
+        let past_rom = |line| Err(FormatError::new(line, "address past the end of ROM"));
+
+        // An extended address record moves the next data to 64K
+        assert_eq!(
+            Format::IntelHex.read(b":020000040001F9\n:020000000002FC\n"),
+            past_rom(2)
+        );
+        assert_eq!(
+            Format::ReadMemH.read(b"0002\n@FFFFFFFF\n0002\n"),
+            past_rom(3)
+        );
+        assert_eq!(
+            Format::ReadMemH.read(b"@7fff\n0002\n").unwrap().len(),
+            ROM_SIZE
+        );
+        assert_eq!(
+            Format::Logisim.read(b"v2.0 raw\n2\n4294967295*0\n"),
+            past_rom(3)
+        );
+        assert_eq!(
+            Format::Logisim.read(b"v2.0 raw\n32768*0\n").unwrap().len(),
+            ROM_SIZE
+        );
+    }
+}
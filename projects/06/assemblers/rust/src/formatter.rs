@@ -0,0 +1,252 @@
+//! Rewrites assembly into one canonical style, as `hackfmt` does. The
+//! formatter works on source lines rather than the expanded program, so
+//! macros, includes and comments stay where they were written:
+//!
+//! - Labels and directives start the line and instructions are indented
+//!   under them, one more level inside a macro definition.
+//! - Instructions lose their inner whitespace and C-instructions are spelled
+//!   the way the disassembler prints them, e.g. `D = 1 + M` as `D=M+1`.
+//! - Trailing comments line up at one column. Comment lines take the
+//!   indentation of the code right after them, and start the line when a
+//!   blank line follows.
+//! - Runs of blank lines collapse into one.
+//!
+//! Formatting is idempotent, and a formatted program assembles to the same
+//! words as the original.
+
+use crate::instruction::{Comp, Dest, Instruction, Jump};
+use crate::lexer;
+use crate::parser::{CommandType, Parser};
+
+const INDENT: &str = "    ";
+/// Trailing comments start at this column, counting from 0, unless the
+/// code is longer.
+const COMMENT_COLUMN: usize = 24;
+
+/// One source line split into its formatted code and its comment.
+struct FormatLine<'a> {
+    code: String,
+    comment: Option<&'a str>,
+    level: usize,
+}
+
+pub fn format(source: &str) -> String {
+    let mut lines: Vec<Option<FormatLine>> = Vec::new();
+    let mut in_macro = false;
+
+    for line in lexer::lines(source) {
+        let raw = line.text.trim_end();
+        let code = lexer::strip_comment(raw);
+        let comment = raw.get(code.len()..).filter(|comment| !comment.is_empty());
+        let directive = lexer::words(code)
+            .next()
+            .filter(|word| word.starts_with('.'));
+
+        let nesting = match directive {
+            Some(".macro") => {
+                in_macro = true;
+                0
+            }
+            Some(".endm") => {
+                in_macro = false;
+                0
+            }
+            _ => in_macro as usize,
+        };
+        let (code, level) = match directive {
+            Some(".include") => (format!(".include {}", code.trim()[8..].trim()), nesting),
+            Some(_) => (words(code), nesting),
+            None => match instruction(code) {
+                Some((code, true)) => (code, nesting + 1),
+                Some((code, false)) => (code, nesting),
+                None => (String::new(), nesting),
+            },
+        };
+
+        if code.is_empty() && comment.is_none() {
+            lines.push(None);
+        } else {
+            lines.push(Some(FormatLine {
+                code,
+                comment,
+                level,
+            }));
+        }
+    }
+
+    // Walk backwards so each comment line sees the code after it
+    let mut level = 0;
+    for line in lines.iter_mut().rev() {
+        match line {
+            Some(line) if line.code.is_empty() => line.level = level,
+            Some(line) => level = line.level,
+            None => level = 0,
+        }
+    }
+
+    let mut formatted = String::new();
+    let mut blank = false;
+    for line in &lines {
+        let line = match line {
+            Some(line) => line,
+            None => {
+                blank = !formatted.is_empty();
+                continue;
+            }
+        };
+        if blank {
+            formatted.push('\n');
+            blank = false;
+        }
+
+        let mut text = INDENT.repeat(line.level);
+        text.push_str(&line.code);
+        if let Some(comment) = line.comment {
+            if !line.code.is_empty() {
+                let width = text.chars().count();
+                text.push_str(&" ".repeat(COMMENT_COLUMN.saturating_sub(width).max(1)));
+            }
+            text.push_str(comment);
+        }
+        formatted.push_str(&text);
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+/// The formatted code of a line that is not a directive, and whether it is
+/// indented under a label. Lines the parser does not recognise, such as
+/// macro calls, only have their whitespace collapsed.
+fn instruction(code: &str) -> Option<(String, bool)> {
+    let parser = Parser::new(code);
+    if !parser.has_more_commands() {
+        return if code.trim().is_empty() {
+            None
+        } else {
+            Some((words(code), true))
+        };
+    }
+
+    let compact = || lexer::compact(&code.into()).map(|code| code.to_string());
+    let formatted = match parser.command_type() {
+        CommandType::Jump => return compact().map(|label| (label, false)),
+        CommandType::Address => compact()?,
+        CommandType::Computation => {
+            let command = parser.parse_command().node;
+            match (
+                Dest::parse(&command.dest),
+                Comp::parse(&command.comp),
+                Jump::parse(&command.jump),
+            ) {
+                (Some(dest), Some(comp), Some(jump)) => {
+                    Instruction::Compute { dest, comp, jump }.to_string()
+                }
+                _ => words(code),
+            }
+        }
+        // `goto` and `if` are separated by words, other pseudo-instructions
+        // read like the instructions they lower to
+        _ => match lexer::words(code).next() {
+            Some("goto") | Some("if") => words(code),
+            _ => compact()?,
+        },
+    };
+
+    Some((formatted, true))
+}
+
+/// The words of `code` separated by single spaces.
+fn words(code: &str) -> String {
+    code.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[test]
+    fn formats() {
+        let source = "// Adds 1 to R0\r
+\r
+\r
+   @ R0\r
+D = 1 + M     // increment\r
+\t  DM=D\r
+  .equ   ROWS  256\r
+  // loop forever\r
+(  END  )\r
+  if D >  0   goto   END\r
+@END // the comment column\r
+        0 ; JMP\r
+  M[ i ] = D\r
+.macro   PUSH  value\r
+(AGAIN)\r
+  @value\r
+  DM=A  \r
+.endm\r
+PUSH  R1,2\r
+.include   \"../../lib/screen/fill.asm\"  // shared\r
+// the end\r
+\r
+";
+
+        assert_eq!(
+            format(source),
+            "// Adds 1 to R0
+
+    @R0
+    D=M+1               // increment
+    MD=D
+.equ ROWS 256
+// loop forever
+(END)
+    if D > 0 goto END
+    @END                // the comment column
+    0;JMP
+    M[i]=D
+.macro PUSH value
+    (AGAIN)
+        @value
+        MD=A
+.endm
+    PUSH R1,2
+.include \"../../lib/screen/fill.asm\" // shared
+// the end
+"
+        );
+    }
+
+    #[test]
+    fn idempotent() {
+        let source = "
+            @i
+            M = 1 // i = 1
+            // count to 100
+        (LOOP)
+            @i
+            D = M
+            @ 100
+            D = D - A
+            @END
+            D ; JGT
+            @i
+            M = 1 + M
+            @LOOP
+            0 ; JMP
+        (END)
+            @END
+            0 ; JMP
+        ";
+        let formatted = format(source);
+
+        assert_eq!(format(&formatted), formatted);
+        assert_eq!(
+            Assembler::build("", formatted).unwrap().words(),
+            Assembler::build("", source.to_string()).unwrap().words()
+        );
+        assert_eq!(format(""), "");
+        assert_eq!(format("\n\n"), "");
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fmt, ops::BitOr};
+
+/// The destination bits of a C-instruction: any combination of A, D and M.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Dest(u8);
+
+impl Dest {
+    pub const NONE: Dest = Dest(0);
+    pub const M: Dest = Dest(0b001);
+    pub const D: Dest = Dest(0b010);
+    pub const A: Dest = Dest(0b100);
+
+    pub fn from_bits(bits: u16) -> Dest {
+        Dest((bits & 0b111) as u8)
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0 as u16
+    }
+
+    pub fn contains(self, other: Dest) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Parses the registers to store to, in any order, e.g. `MD` or `DM`.
+    /// Each register may only be named once.
+    pub fn parse(mnemonic: &str) -> Option<Dest> {
+        let mut dest = Dest::NONE;
+        for register in mnemonic.chars() {
+            let register = match register {
+                'A' => Dest::A,
+                'D' => Dest::D,
+                'M' => Dest::M,
+                _ => return None,
+            };
+            if dest.contains(register) {
+                return None;
+            }
+            dest = dest | register;
+        }
+
+        Some(dest)
+    }
+}
+
+impl BitOr for Dest {
+    type Output = Dest;
+
+    fn bitor(self, other: Dest) -> Dest {
+        Dest(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for Dest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (register, name) in &[(Dest::A, "A"), (Dest::M, "M"), (Dest::D, "D")] {
+            if self.contains(*register) {
+                write!(f, "{}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The 28 computations of the Hack ALU.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comp {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    A,
+    M,
+    NotD,
+    NotA,
+    NotM,
+    NegD,
+    NegA,
+    NegM,
+    DPlusOne,
+    APlusOne,
+    MPlusOne,
+    DMinusOne,
+    AMinusOne,
+    MMinusOne,
+    DPlusA,
+    DPlusM,
+    DMinusA,
+    DMinusM,
+    AMinusD,
+    MMinusD,
+    DAndA,
+    DAndM,
+    DOrA,
+    DOrM,
+}
+
+/// Each computation with its mnemonic and its `a c1..c6` bits.
+const COMPUTATIONS: [(Comp, &str, u16); 28] = [
+    (Comp::Zero, "0", 0b0101010),
+    (Comp::One, "1", 0b0111111),
+    (Comp::MinusOne, "-1", 0b0111010),
+    (Comp::D, "D", 0b0001100),
+    (Comp::A, "A", 0b0110000),
+    (Comp::M, "M", 0b1110000),
+    (Comp::NotD, "!D", 0b0001101),
+    (Comp::NotA, "!A", 0b0110001),
+    (Comp::NotM, "!M", 0b1110001),
+    (Comp::NegD, "-D", 0b0001111),
+    (Comp::NegA, "-A", 0b0110011),
+    (Comp::NegM, "-M", 0b1110011),
+    (Comp::DPlusOne, "D+1", 0b0011111),
+    (Comp::APlusOne, "A+1", 0b0110111),
+    (Comp::MPlusOne, "M+1", 0b1110111),
+    (Comp::DMinusOne, "D-1", 0b0001110),
+    (Comp::AMinusOne, "A-1", 0b0110010),
+    (Comp::MMinusOne, "M-1", 0b1110010),
+    (Comp::DPlusA, "D+A", 0b0000010),
+    (Comp::DPlusM, "D+M", 0b1000010),
+    (Comp::DMinusA, "D-A", 0b0010011),
+    (Comp::DMinusM, "D-M", 0b1010011),
+    (Comp::AMinusD, "A-D", 0b0000111),
+    (Comp::MMinusD, "M-D", 0b1000111),
+    (Comp::DAndA, "D&A", 0b0000000),
+    (Comp::DAndM, "D&M", 0b1000000),
+    (Comp::DOrA, "D|A", 0b0010101),
+    (Comp::DOrM, "D|M", 0b1010101),
+];
+
+impl Comp {
+    fn entry(self) -> &'static (Comp, &'static str, u16) {
+        COMPUTATIONS
+            .iter()
+            .find(|(comp, _, _)| *comp == self)
+            .expect("every computation is in the table")
+    }
+
+    /// Parses a computation, with the operands of `+`, `&` and `|` in
+    /// either order, e.g. `A+D` for `D+A` or `1+M` for `M+1`.
+    pub fn parse(mnemonic: &str) -> Option<Comp> {
+        let lookup = |mnemonic: &str| {
+            COMPUTATIONS
+                .iter()
+                .find(|(_, entry, _)| *entry == mnemonic)
+                .map(|(comp, _, _)| *comp)
+        };
+
+        lookup(mnemonic).or_else(|| {
+            let index = mnemonic.find(['+', '&', '|'])?;
+            let (left, right) = (&mnemonic[..index], &mnemonic[index + 1..]);
+            lookup(&format!("{}{}{}", right, &mnemonic[index..=index], left))
+        })
+    }
+
+    /// Why `mnemonic` is not a computation, for the binary forms the ALU
+    /// cannot compute.
+    pub fn diagnose(mnemonic: &str) -> Option<&'static str> {
+        let index = mnemonic.find(['+', '-', '&', '|'])?;
+        let (left, right) = (&mnemonic[..index], &mnemonic[index + 1..]);
+        let registers = ["A", "D", "M"];
+
+        if !registers.contains(&left) || !registers.contains(&right) {
+            None
+        } else if left == right {
+            Some("the ALU cannot combine a register with itself")
+        } else if left != "D" && right != "D" {
+            Some("one operand must be D, the other A or M")
+        } else {
+            None
+        }
+    }
+
+    /// Every computation, in the order of the course's table.
+    pub fn all() -> impl Iterator<Item = Comp> {
+        COMPUTATIONS.iter().map(|(comp, _, _)| *comp)
+    }
+
+    /// Decodes the 7 `a c1..c6` bits, if they form one of the 28 forms.
+    pub fn from_bits(bits: u16) -> Option<Comp> {
+        COMPUTATIONS
+            .iter()
+            .find(|(_, _, entry)| *entry == bits & 0x7F)
+            .map(|(comp, _, _)| *comp)
+    }
+
+    pub fn bits(self) -> u16 {
+        self.entry().2
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        self.entry().1
+    }
+
+    /// Whether the ALU takes M rather than A as its second input.
+    pub fn reads_memory(self) -> bool {
+        self.bits() & 0b1000000 != 0
+    }
+}
+
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())
+    }
+}
+
+/// The jump condition of a C-instruction, in encoding order.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Jump {
+    Null,
+    Jgt,
+    Jeq,
+    Jge,
+    Jlt,
+    Jne,
+    Jle,
+    Jmp,
+}
+
+const JUMPS: [(Jump, &str); 8] = [
+    (Jump::Null, ""),
+    (Jump::Jgt, "JGT"),
+    (Jump::Jeq, "JEQ"),
+    (Jump::Jge, "JGE"),
+    (Jump::Jlt, "JLT"),
+    (Jump::Jne, "JNE"),
+    (Jump::Jle, "JLE"),
+    (Jump::Jmp, "JMP"),
+];
+
+impl Jump {
+    pub fn parse(mnemonic: &str) -> Option<Jump> {
+        JUMPS
+            .iter()
+            .find(|(_, entry)| *entry == mnemonic)
+            .map(|(jump, _)| *jump)
+    }
+
+    pub fn from_bits(bits: u16) -> Jump {
+        JUMPS[(bits & 0b111) as usize].0
+    }
+
+    pub fn bits(self) -> u16 {
+        self as u16
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        JUMPS[self as usize].1
+    }
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    /// `@value`, where value fits in 15 bits.
+    Address(u16),
+    Compute {
+        dest: Dest,
+        comp: Comp,
+        jump: Jump,
+    },
+}
+
+impl Instruction {
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Address(value) => value,
+            Instruction::Compute { dest, comp, jump } => {
+                0b111 << 13 | comp.bits() << 6 | dest.bits() << 3 | jump.bits()
+            }
+        }
+    }
+
+    /// Decodes a machine word. C-instructions must start with `111` and
+    /// compute one of the 28 documented forms.
+    pub fn decode(word: u16) -> Option<Instruction> {
+        if word & 0x8000 == 0 {
+            return Some(Instruction::Address(word));
+        }
+        if word & 0xE000 != 0xE000 {
+            return None;
+        }
+
+        Some(Instruction::Compute {
+            dest: Dest::from_bits(word >> 3),
+            comp: Comp::from_bits(word >> 6)?,
+            jump: Jump::from_bits(word),
+        })
+    }
+
+    pub fn reads_or_writes_memory(&self) -> bool {
+        match self {
+            Instruction::Compute { dest, comp, .. } => {
+                dest.contains(Dest::M) || comp.reads_memory()
+            }
+            Instruction::Address(_) => false,
+        }
+    }
+
+    pub fn is_jump(&self) -> bool {
+        match self {
+            Instruction::Compute { jump, .. } => *jump != Jump::Null,
+            Instruction::Address(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Address(value) => write!(f, "@{}", value),
+            Instruction::Compute { dest, comp, jump } => {
+                if !dest.is_empty() {
+                    write!(f, "{}=", dest)?;
+                }
+                write!(f, "{}", comp)?;
+                if *jump != Jump::Null {
+                    write!(f, ";{}", jump)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dest() {
+        assert_eq!(Dest::parse(""), Some(Dest::NONE));
+        assert_eq!(Dest::parse("MD"), Some(Dest::M | Dest::D));
+        assert_eq!(Dest::parse("AMD").map(Dest::bits), Some(0b111));
+        assert_eq!(Dest::parse("DM"), Some(Dest::M | Dest::D));
+        assert_eq!(Dest::parse("DMA"), Dest::parse("AMD"));
+        assert_eq!(Dest::parse("DD"), None);
+        assert_eq!(Dest::parse("X"), None);
+        assert_eq!((Dest::A | Dest::D).to_string(), "AD");
+    }
+
+    #[test]
+    fn comp() {
+        for (comp, mnemonic, bits) in COMPUTATIONS.iter() {
+            assert_eq!(Comp::parse(mnemonic), Some(*comp));
+            assert_eq!(Comp::from_bits(*bits), Some(*comp));
+            assert_eq!(comp.mnemonic(), *mnemonic);
+        }
+
+        assert_eq!(Comp::all().count(), 28);
+        assert_eq!(Comp::parse("A+D"), Some(Comp::DPlusA));
+        assert_eq!(Comp::parse("M+D"), Some(Comp::DPlusM));
+        assert_eq!(Comp::parse("1+D"), Some(Comp::DPlusOne));
+        assert_eq!(Comp::parse("A&D"), Some(Comp::DAndA));
+        assert_eq!(Comp::parse("M|D"), Some(Comp::DOrM));
+        assert_eq!(Comp::parse("1-D"), None);
+        assert_eq!(Comp::parse("D+X"), None);
+
+        assert_eq!(
+            Comp::diagnose("D+D"),
+            Some("the ALU cannot combine a register with itself")
+        );
+        assert_eq!(
+            Comp::diagnose("A+M"),
+            Some("one operand must be D, the other A or M")
+        );
+        assert_eq!(Comp::diagnose("D+X"), None);
+        assert_eq!(Comp::from_bits(0b0000001), None);
+        assert!(Comp::DPlusM.reads_memory());
+        assert!(!Comp::DPlusA.reads_memory());
+    }
+
+    #[test]
+    fn jump() {
+        assert_eq!(Jump::parse(""), Some(Jump::Null));
+        assert_eq!(Jump::parse("JLE"), Some(Jump::Jle));
+        assert_eq!(Jump::parse("JMZ"), None);
+        assert_eq!(Jump::from_bits(0b101), Jump::Jne);
+        assert_eq!(Jump::Jge.bits(), 0b011);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let instruction = Instruction::Compute {
+            dest: Dest::M | Dest::D,
+            comp: Comp::DPlusM,
+            jump: Jump::Jge,
+        };
+        assert_eq!(instruction.encode(), 0b1111000010011011);
+        assert_eq!(Instruction::decode(0b1111000010011011), Some(instruction));
+        assert_eq!(instruction.to_string(), "MD=D+M;JGE");
+
+        assert_eq!(Instruction::decode(42), Some(Instruction::Address(42)));
+        assert_eq!(Instruction::Address(42).encode(), 42);
+
+        // C-instructions must start with 111 and use a known computation
+        assert_eq!(Instruction::decode(0b1010101010101010), None);
+        assert_eq!(Instruction::decode(0b1110000001000000), None);
+    }
+
+    #[test]
+    fn round_trip_all_words() {
+        for word in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(word) {
+                assert_eq!(instruction.encode(), word);
+            }
+        }
+    }
+}
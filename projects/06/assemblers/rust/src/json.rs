@@ -0,0 +1,391 @@
+//! Just enough JSON for the language server and symbol maps: a value type
+//! that keeps object keys in order, a parser and a compact printer.
+
+use crate::error::FormatError;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from its members, in order.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (String::from(key), value))
+                .collect(),
+        )
+    }
+
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value of a number that is a whole, non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, FormatError> {
+        let mut parser = JsonParser { text, position: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Json {
+        Json::Number(f64::from(value))
+    }
+}
+
+impl From<u16> for Json {
+    fn from(value: u16) -> Json {
+        Json::Number(f64::from(value))
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Json {
+        Json::String(String::from(text))
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Json {
+        Json::String(text)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json {
+        Json::Array(values)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                f.write_str("[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(members) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    /// Errors are reported against the line they are found on.
+    fn error(&self, message: &str) -> FormatError {
+        let line = self.text[..self.position].matches('\n').count() + 1;
+        FormatError::new(line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), FormatError> {
+        if self.text[self.position..].starts_with(token) {
+            self.position += token.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", token)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, FormatError> {
+        self.whitespace();
+        match self.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, FormatError> {
+        let rest = &self.text[self.position..];
+        let length = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        let number = rest[..length]
+            .parse()
+            .map_err(|_| self.error(&format!("invalid number `{}`", &rest[..length])))?;
+        self.position += length;
+        Ok(Json::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, FormatError> {
+        self.expect("\"")?;
+        let mut text = String::new();
+
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.position += c.len_utf8();
+            match c {
+                '"' => return Ok(text),
+                '\\' => text.push(self.escape()?),
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, FormatError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.position += c.len_utf8();
+        let escaped = match c {
+            '"' | '\\' | '/' => c,
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.code_unit()?;
+                // Characters outside the basic plane take two escapes
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    self.expect("\\u")?;
+                    let low = self.code_unit()?;
+                    0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                } else {
+                    high
+                };
+                char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        Ok(escaped)
+    }
+
+    fn code_unit(&mut self) -> Result<u32, FormatError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .ok_or_else(|| self.error("invalid escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid escape"))?;
+        self.position += 4;
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, FormatError> {
+        self.expect("[")?;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, FormatError> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let text = r#" {"id": 1, "params": {"text": "@R0\n\"a\"\\ \u00e9\ud83d\ude00",
+            "list": [true, false, null, -2.5e1, []], "empty": {}}} "#;
+        let json = Json::parse(text).unwrap();
+
+        assert_eq!(json.get("id").and_then(Json::as_usize), Some(1));
+        let params = json.get("params").unwrap();
+        assert_eq!(
+            params.get("text").and_then(Json::as_str),
+            Some("@R0\n\"a\"\\ é😀")
+        );
+        assert_eq!(
+            params
+                .get("list")
+                .and_then(Json::as_array)
+                .map(<[Json]>::len),
+            Some(5)
+        );
+        assert_eq!(
+            json.to_string(),
+            r#"{"id":1,"params":{"text":"@R0\n\"a\"\\ é😀","list":[true,false,null,-25,[]],"empty":{}}}"#
+        );
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(
+            Json::object(vec![
+                ("a", Json::from(Some(2usize))),
+                ("b", Json::from(None::<&str>))
+            ])
+            .to_string(),
+            r#"{"a":2,"b":null}"#
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| Json::parse(text).unwrap_err().to_string();
+
+        assert_eq!(error(""), "1: error: unexpected end of input");
+        assert_eq!(error("{\n\"a\" 1}"), "2: error: expected `:`");
+        assert_eq!(error("[1 2]"), "1: error: expected `,` or `]`");
+        assert_eq!(error("\"abc"), "1: error: unterminated string");
+        assert_eq!(error("\"\\x\""), "1: error: invalid escape");
+        assert_eq!(error("1 2"), "1: error: trailing characters");
+        assert_eq!(error("-"), "1: error: invalid number `-`");
+    }
+}
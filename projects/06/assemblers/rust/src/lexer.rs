@@ -0,0 +1,242 @@
+//! Splits source text into lines, code and words. The functions over `&str`
+//! borrow from the text they are given, and `SourceText` lets the lines and
+//! spans of a file share its text by reference counting, so code written
+//! without inner whitespace is never copied. Code with whitespace inside it,
+//! such as `D = M`, is compacted into a new string, and pseudo-instructions
+//! lower to new lines.
+
+use std::{
+    fmt,
+    ops::{Deref, Range},
+    rc::Rc,
+};
+
+/// A line of source, numbered from 1, without its line ending.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RawLine<'a> {
+    pub number: usize,
+    /// Byte offset of the line in the text.
+    pub start: usize,
+    pub text: &'a str,
+}
+
+impl<'a> RawLine<'a> {
+    pub fn code(&self) -> &'a str {
+        strip_comment(self.text)
+    }
+}
+
+pub struct Lines<'a> {
+    text: &'a str,
+    offset: usize,
+    number: usize,
+}
+
+/// The lines of `text`, split on `\n` or `\r\n` like `str::lines`.
+pub fn lines(text: &str) -> Lines<'_> {
+    Lines {
+        text,
+        offset: 0,
+        number: 0,
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = RawLine<'a>;
+
+    fn next(&mut self) -> Option<RawLine<'a>> {
+        if self.offset >= self.text.len() {
+            return None;
+        }
+
+        let rest = &self.text[self.offset..];
+        let (length, next) = match rest.find('\n') {
+            Some(index) => (index, index + 1),
+            None => (rest.len(), rest.len()),
+        };
+        let text = &rest[..length];
+        let line = RawLine {
+            number: self.number + 1,
+            start: self.offset,
+            text: text.strip_suffix('\r').unwrap_or(text),
+        };
+
+        self.offset += next;
+        self.number += 1;
+        Some(line)
+    }
+}
+
+/// The line up to its `//` comment, if it has one.
+pub fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+/// The column the code on a line starts at, counting from 1.
+pub fn column(code: &str) -> usize {
+    code.find(|c: char| !c.is_whitespace()).unwrap_or(0) + 1
+}
+
+/// The words of a directive or macro call, separated by whitespace or
+/// commas.
+pub fn words(code: &str) -> impl Iterator<Item = &str> {
+    code.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+}
+
+/// The code on `line` with its comment and all whitespace removed, or `None`
+/// if there is none. The result shares the line's text unless whitespace
+/// separates the code, as in `D = M`.
+pub fn compact(line: &SourceText) -> Option<SourceText> {
+    let code = strip_comment(line);
+    let trimmed = code.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    if trimmed.contains(char::is_whitespace) {
+        let text: String = trimmed.split_whitespace().collect();
+        Some(SourceText::from(text))
+    } else {
+        let start = code.len() - code.trim_start().len();
+        Some(line.slice(start..start + trimmed.len()))
+    }
+}
+
+/// Part of a source text shared by reference counting, so cloning it or
+/// slicing it never copies the text.
+#[derive(Clone)]
+pub struct SourceText {
+    source: Rc<str>,
+    range: Range<usize>,
+}
+
+impl SourceText {
+    pub fn new(source: Rc<str>) -> SourceText {
+        let range = 0..source.len();
+        SourceText { source, range }
+    }
+
+    /// The part of this text in `range`, which is relative to its start.
+    pub fn slice(&self, range: Range<usize>) -> SourceText {
+        let start = self.range.start + range.start;
+        assert!(start + range.len() <= self.range.end);
+
+        SourceText {
+            source: Rc::clone(&self.source),
+            range: start..start + range.len(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source[self.range.clone()]
+    }
+
+    /// Splits the text into lines that share it.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, SourceText)> + '_ {
+        lines(self.as_str()).map(move |line| {
+            let text = self.slice(line.start..line.start + line.text.len());
+            (line.number, text)
+        })
+    }
+}
+
+impl Deref for SourceText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<String> for SourceText {
+    fn from(text: String) -> SourceText {
+        SourceText::new(Rc::from(text))
+    }
+}
+
+impl From<&str> for SourceText {
+    fn from(text: &str) -> SourceText {
+        SourceText::new(Rc::from(text))
+    }
+}
+
+impl PartialEq for SourceText {
+    fn eq(&self, other: &SourceText) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialEq<&str> for SourceText {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Debug for SourceText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SourceText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_lines() {
+        let text = "@2 // two\r\n\nD=A\n";
+        let lines: Vec<RawLine> = lines(text).collect();
+
+        assert_eq!(
+            lines,
+            [
+                RawLine {
+                    number: 1,
+                    start: 0,
+                    text: "@2 // two",
+                },
+                RawLine {
+                    number: 2,
+                    start: 11,
+                    text: "",
+                },
+                RawLine {
+                    number: 3,
+                    start: 12,
+                    text: "D=A",
+                },
+            ]
+        );
+        assert_eq!(lines[0].code(), "@2 ");
+        assert_eq!(column("    D=A"), 5);
+        assert_eq!(
+            words("  PUSH R1, 2 // comment").collect::<Vec<_>>(),
+            ["PUSH", "R1", "2", "//", "comment"]
+        );
+    }
+
+    #[test]
+    fn shares_text() {
+        let source = SourceText::from("  @LOOP // back\n  D = M\n// only a comment");
+        let lines: Vec<(usize, SourceText)> = source.lines().collect();
+
+        assert_eq!(lines[0].1, "  @LOOP // back");
+        assert_eq!(compact(&lines[0].1), Some(SourceText::from("@LOOP")));
+        assert!(Rc::ptr_eq(
+            &compact(&lines[0].1).unwrap().source,
+            &source.source
+        ));
+        assert_eq!(compact(&lines[1].1), Some(SourceText::from("D=M")));
+        assert_eq!(compact(&lines[2].1), None);
+    }
+}
@@ -0,0 +1,23 @@
+pub mod analysis;
+pub mod assembler;
+pub mod cli;
+pub mod code_gen;
+pub mod disassembler;
+pub mod document;
+pub mod error;
+pub mod expression;
+pub mod formats;
+pub mod formatter;
+pub mod instruction;
+pub mod json;
+pub mod lexer;
+pub mod linker;
+pub mod lint;
+pub mod listing;
+pub mod lsp;
+pub mod object;
+pub mod parser;
+pub mod platform;
+pub mod pseudo;
+pub mod symbol_map;
+pub mod symbols;
@@ -0,0 +1,197 @@
+use crate::error::{LinkError, LinkErrors};
+use crate::object::ObjectFile;
+use crate::platform::Platform;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+
+/// Combines objects into one program, placed in ROM in the order given.
+/// Variables are allocated in the platform's variable range in order of
+/// first use, as the assembler does, unless an object exports a label by
+/// that name.
+pub fn link(platform: &Platform, objects: &[(&str, ObjectFile)]) -> Result<Vec<u16>, LinkErrors> {
+    let mut symbols = Symbols::with_platform(platform);
+    let mut exported_by: HashMap<&str, &str> = HashMap::new();
+    let mut errors = LinkErrors::new();
+
+    let mut bases = Vec::new();
+    let mut size: usize = 0;
+    for (file, object) in objects {
+        bases.push(size as u16);
+        size += object.code.len();
+        if size > platform.rom_size {
+            errors.push(LinkError::new(file, "the program does not fit in ROM"));
+            return Err(errors);
+        }
+    }
+
+    for ((file, object), base) in objects.iter().zip(&bases) {
+        for (name, address) in &object.exports {
+            if let Some(other) = exported_by.insert(name, file) {
+                errors.push(LinkError::new(
+                    file,
+                    &format!("symbol `{}` is also exported by {}", name, other),
+                ));
+            } else if symbols.get(name).is_some() {
+                errors.push(LinkError::new(
+                    file,
+                    &format!("symbol `{}` is predefined", name),
+                ));
+            } else {
+                match base
+                    .checked_add(*address)
+                    .filter(|&address| usize::from(address) <= platform.rom_size)
+                {
+                    Some(address) => symbols.add_jump_symbol(name.clone(), address),
+                    None => errors.push(LinkError::new(
+                        file,
+                        &format!("symbol `{}` is past the end of ROM", name),
+                    )),
+                }
+            }
+        }
+    }
+
+    let mut program = Vec::with_capacity(size);
+    for ((file, object), base) in objects.iter().zip(&bases) {
+        let mut code = object.code.clone();
+
+        for index in &object.relocations {
+            let word = &mut code[*index as usize];
+            match word
+                .checked_add(*base)
+                .filter(|&address| usize::from(address) < platform.rom_size)
+            {
+                Some(address) => *word = address,
+                None => errors.push(LinkError::new(
+                    file,
+                    &format!("word {} points past the end of ROM", index),
+                )),
+            }
+        }
+        for (index, name) in &object.imports {
+            match exported_by.get(name.as_str()) {
+                Some(_) => code[*index as usize] = symbols.get(name).unwrap_or_default(),
+                None => errors.push(LinkError::new(
+                    file,
+                    &format!("undefined symbol `{}`", name),
+                )),
+            }
+        }
+        for (index, name) in &object.variables {
+            match symbols.get_address(name.clone()) {
+                Some(address) => code[*index as usize] = address,
+                None => errors.push(LinkError::new(
+                    file,
+                    &format!("no RAM left for variable `{}`", name),
+                )),
+            }
+        }
+
+        program.extend(code);
+    }
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    fn object(source: &str) -> ObjectFile {
+        Assembler::build_object(
+            &Platform::default(),
+            &[("", source)],
+            &|_| Ok(String::new()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn links_objects() {
+        let main = object(
+            "
+            .extern Math.multiply
+            @i
+            (LOOP)
+            @Math.multiply
+            0;JMP
+            @LOOP
+            ",
+        );
+        let math = object(
+            "
+            (Math.multiply)
+            @sum
+            @i
+            @Math.multiply
+            ",
+        );
+
+        let linked = link(
+            &Platform::default(),
+            &[("Main.hobj", main), ("Math.hobj", math)],
+        )
+        .unwrap();
+        let one_shot = Assembler::build_files(
+            &[
+                ("Main.asm", "@i\n(LOOP)\n@Math.multiply\n0;JMP\n@LOOP"),
+                ("Math.asm", "(Math.multiply)\n@sum\n@i\n@Math.multiply"),
+            ],
+            &|_| Ok(String::new()),
+        )
+        .unwrap()
+        .words();
+
+        assert_eq!(linked, vec![16, 4, 0xEA87, 1, 17, 16, 4]);
+        assert_eq!(linked, one_shot);
+    }
+
+    #[test]
+    fn link_errors() {
+        let main = object(".extern Missing\n@Missing\n(LOOP)");
+        let other = object("(LOOP)");
+
+        let errors = link(
+            &Platform::default(),
+            &[("Main.hobj", main), ("Other.hobj", other)],
+        )
+        .unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Other.hobj: error: symbol `LOOP` is also exported by Main.hobj",
+                "Main.hobj: error: undefined symbol `Missing`",
+            ]
+        );
+    }
+
+    #[test]
+    fn relocations_stay_in_rom() {
+        let main = object("@0\nD=A");
+        let far = ObjectFile {
+            code: vec![0x7FFF],
+            exports: vec![(String::from("FAR"), 1)],
+            relocations: vec![0],
+            ..ObjectFile::default()
+        };
+
+        let errors = link(
+            &Platform::default(),
+            &[("Main.hobj", main), ("Far.hobj", far)],
+        )
+        .unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            errors,
+            vec!["Far.hobj: error: word 0 points past the end of ROM"]
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Warnings for code that assembles but is probably wrong. A line allows a
+//! warning by naming it in its comment, as in `@tmp // allow(single-use-variable)`,
+//! with several names separated by commas. Lines a pseudo-instruction
+//! lowers to are covered by the comment on the pseudo-instruction.
+
+use crate::assembler::{AssembledLine, Assembly};
+use crate::error::{AssembleWarning, WarningKind};
+use crate::expression::Expression;
+use crate::instruction::{Dest, Instruction, Jump};
+use crate::lexer;
+use crate::parser::{location, CommandType};
+use std::collections::{HashMap, HashSet};
+
+pub fn lint(assembly: &Assembly) -> Vec<AssembleWarning> {
+    let mut lint = Lint {
+        assembly,
+        allowed: allowed(assembly),
+        variables: assembly
+            .symbols
+            .variables()
+            .into_iter()
+            .map(|(variable, _)| variable)
+            .collect(),
+        warnings: Vec::new(),
+    };
+
+    lint.unused_symbols();
+    lint.case_mismatches();
+    lint.loads();
+    lint.unreachable_code();
+
+    let mut warnings = lint.warnings;
+    sort(&mut warnings, assembly);
+    warnings
+}
+
+/// Sorts warnings like errors: by file in the order the files were read,
+/// then by line and column.
+pub(crate) fn sort(warnings: &mut [AssembleWarning], assembly: &Assembly) {
+    let files = assembly.files(&[]);
+    let position = |file: &str| files.iter().position(|&other| other == file);
+    warnings.sort_by(|a, b| {
+        (position(&a.file), a.line, a.column).cmp(&(position(&b.file), b.line, b.column))
+    });
+}
+
+/// The warnings each line allows, by file and line number.
+pub(crate) fn allowed(assembly: &Assembly) -> HashMap<(&str, usize), Vec<&str>> {
+    let mut allowed: HashMap<(&str, usize), Vec<&str>> = HashMap::new();
+
+    for line in &assembly.lines {
+        let raw = line.span.raw.as_str();
+        let comment = match raw.find("//") {
+            Some(index) => &raw[index + 2..],
+            None => continue,
+        };
+        let names = comment
+            .find("allow(")
+            .map(|index| &comment[index + 6..])
+            .and_then(|names| names.find(')').map(|end| &names[..end]));
+
+        if let Some(names) = names {
+            allowed
+                .entry((&line.span.file, line.span.line))
+                .or_default()
+                .extend(names.split(',').map(str::trim));
+        }
+    }
+
+    allowed
+}
+
+/// The code of `line` as it is quoted in warnings.
+pub(crate) fn code(line: &AssembledLine) -> String {
+    lexer::compact(&line.span.raw)
+        .map(|code| code.to_string())
+        .unwrap_or_default()
+}
+
+struct Lint<'a> {
+    assembly: &'a Assembly,
+    allowed: HashMap<(&'a str, usize), Vec<&'a str>>,
+    variables: HashSet<&'a str>,
+    warnings: Vec<AssembleWarning>,
+}
+
+impl<'a> Lint<'a> {
+    fn warn(&mut self, kind: WarningKind, line: &AssembledLine, text: &str, note: Option<String>) {
+        let span = &line.span;
+        if let Some(names) = self.allowed.get(&(&span.file, span.line)) {
+            if names.contains(&kind.name()) {
+                return;
+            }
+        }
+
+        self.warnings.push(AssembleWarning {
+            file: span.file.clone(),
+            line: span.line,
+            column: span.column,
+            text: String::from(text),
+            kind,
+            note,
+        });
+    }
+
+    /// Labels nothing refers to, and variables referred to only once, which
+    /// are often a misspelled label or a variable that is never read.
+    fn unused_symbols(&mut self) {
+        let lines = &self.assembly.lines;
+        let mut uses: HashMap<&str, Vec<&AssembledLine>> = HashMap::new();
+        for line in lines
+            .iter()
+            .filter(|line| line.command != CommandType::Jump)
+        {
+            for symbol in &line.symbols {
+                uses.entry(symbol).or_default().push(line);
+            }
+        }
+
+        for line in lines
+            .iter()
+            .filter(|line| line.command == CommandType::Jump)
+        {
+            if let Some(label) = line.symbols.first() {
+                if !uses.contains_key(label.as_str()) {
+                    self.warn(WarningKind::UnusedLabel, line, label, None);
+                }
+            }
+        }
+        for (variable, _) in self.assembly.symbols.variables() {
+            if let Some([line]) = uses.get(variable).map(Vec::as_slice) {
+                self.warn(WarningKind::SingleUseVariable, line, variable, None);
+            }
+        }
+    }
+
+    /// Symbols spelled like another symbol or a predefined one except for
+    /// case, such as `loop` and `LOOP`. Each spelling after the first is
+    /// reported where it first appears.
+    fn case_mismatches(&mut self) {
+        let symbols = &self.assembly.symbols;
+        let mut spellings: HashMap<String, (&str, &AssembledLine)> = HashMap::new();
+        let mut reported = HashSet::new();
+
+        for line in &self.assembly.lines {
+            for symbol in &line.symbols {
+                let upper = symbol.to_uppercase();
+                if !symbols.is_predefined(symbol) && symbols.is_predefined(&upper) {
+                    if reported.insert(symbol.as_str()) {
+                        let note = format!("`{}` is predefined", upper);
+                        self.warn(WarningKind::CaseMismatch, line, symbol, Some(note));
+                    }
+                    continue;
+                }
+
+                match spellings.get(&symbol.to_lowercase()) {
+                    Some(&(first, at)) if first != symbol => {
+                        if reported.insert(symbol.as_str()) {
+                            let note = format!(
+                                "also spelled `{}` at {}",
+                                first,
+                                location(&at.span.file, at.span.line)
+                            );
+                            self.warn(WarningKind::CaseMismatch, line, symbol, Some(note));
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        spellings.insert(symbol.to_lowercase(), (symbol, line));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Instructions that use the address an A-instruction just loaded in a
+    /// way that looks wrong: jumping to a variable or register, and reading
+    /// or writing memory computed from `SCREEN` or `KBD` that may not be on
+    /// the screen or keyboard.
+    fn loads(&mut self) {
+        let mut load: Option<&AssembledLine> = None;
+
+        for line in &self.assembly.lines {
+            match line.instruction {
+                _ if line.command == CommandType::Jump => load = None,
+                Some(Instruction::Address(_)) => load = Some(line),
+                Some(Instruction::Compute { dest, comp, jump }) => {
+                    if let Some(load) = load {
+                        if jump != Jump::Null {
+                            self.jump_to_data(load, line);
+                        }
+                        if comp.reads_memory() || dest.contains(Dest::M) {
+                            self.memory_access(load);
+                        }
+                    }
+                    load = None;
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn jump_to_data(&mut self, load: &AssembledLine, line: &AssembledLine) {
+        if let [symbol] = load.symbols.as_slice() {
+            if self.variables.contains(symbol.as_str())
+                || self.assembly.symbols.is_predefined(symbol)
+            {
+                let note = format!("A holds the address of `{}`", symbol);
+                self.warn(WarningKind::JumpToData, line, &code(line), Some(note));
+            }
+        }
+    }
+
+    fn memory_access(&mut self, load: &AssembledLine) {
+        let address = match load.instruction {
+            Some(Instruction::Address(address)) => address,
+            _ => return,
+        };
+        // The devices span from the screen to the keyboard, if the platform
+        // has both
+        let symbols = &self.assembly.symbols;
+        let device = |name: &str| symbols.get(name).filter(|_| symbols.is_predefined(name));
+        let devices = match (device("SCREEN"), device("KBD")) {
+            (Some(screen), Some(keyboard)) => screen..=keyboard,
+            _ => return,
+        };
+        if !load
+            .symbols
+            .iter()
+            .any(|symbol| symbol == "SCREEN" || symbol == "KBD")
+        {
+            return;
+        }
+
+        let text = code(load);
+        if let Ok(Expression::Symbol(_)) = Expression::parse(&text[1..]) {
+            return;
+        }
+
+        let note = match load
+            .symbols
+            .iter()
+            .find(|symbol| self.variables.contains(symbol.as_str()))
+        {
+            Some(variable) => format!("`{}` is the address of a variable, not its value", variable),
+            None if !devices.contains(&address) => {
+                format!("address {} is outside the screen and keyboard", address)
+            }
+            None => return,
+        };
+        self.warn(WarningKind::UncheckedMemory, load, &text, Some(note));
+    }
+
+    /// The first instruction after an unconditional jump, unless a label
+    /// or a numeric A-instruction lets other code jump to it.
+    fn unreachable_code(&mut self) {
+        let targets: HashSet<u16> = self
+            .assembly
+            .lines
+            .iter()
+            .filter(|line| line.command == CommandType::Address && line.symbols.is_empty())
+            .filter_map(|line| match line.instruction {
+                Some(Instruction::Address(address)) => Some(address),
+                _ => None,
+            })
+            .collect();
+        let mut after_jump = false;
+
+        for line in &self.assembly.lines {
+            if line.command == CommandType::Jump {
+                after_jump = false;
+            } else if let Some(instruction) = line.instruction {
+                if after_jump && !targets.contains(&line.address) {
+                    self.warn(WarningKind::UnreachableCode, line, &code(line), None);
+                }
+                after_jump = matches!(
+                    instruction,
+                    Instruction::Compute {
+                        jump: Jump::Jmp,
+                        ..
+                    }
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::Assembler;
+
+    fn warnings(program: &str) -> Vec<String> {
+        Assembler::build("", program.to_string())
+            .unwrap()
+            .warnings
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn warnings_for_each_kind() {
+        let program = "
+            @counter
+            M=0
+            (LOOP)
+            @countr
+            D=M
+            @counter
+            M=M+1
+            @loop
+            D;JGT
+            @SCREEN-1
+            M=-1
+            @SCREEN+counter
+            D=M
+            @counter
+            0;JMP
+            @r0
+            (UNUSED)
+            @LOOP
+            0;JMP
+            ";
+
+        assert_eq!(
+            warnings(program),
+            [
+                "5:13: warning: variable is used only once `countr` [single-use-variable]",
+                "9:13: warning: variable is used only once `loop` [single-use-variable]",
+                "9:13: warning: symbol differs from another only in case `loop` \
+                 (also spelled `LOOP` at line 4) [case-mismatch]",
+                "10:13: warning: jump to a data address `D;JGT` \
+                 (A holds the address of `loop`) [jump-to-data]",
+                "11:13: warning: memory address may be out of range `@SCREEN-1` \
+                 (address 16383 is outside the screen and keyboard) [unchecked-memory]",
+                "13:13: warning: memory address may be out of range `@SCREEN+counter` \
+                 (`counter` is the address of a variable, not its value) [unchecked-memory]",
+                "16:13: warning: jump to a data address `0;JMP` \
+                 (A holds the address of `counter`) [jump-to-data]",
+                "17:13: warning: variable is used only once `r0` [single-use-variable]",
+                "17:13: warning: symbol differs from another only in case `r0` \
+                 (`R0` is predefined) [case-mismatch]",
+                "17:13: warning: unreachable code `@r0` [unreachable-code]",
+                "18:13: warning: label is never used `UNUSED` [unused-label]",
+            ]
+        );
+    }
+
+    #[test]
+    fn allowed_warnings() {
+        let program = "
+            @tmp // allow(single-use-variable)
+            M=0
+            (END)
+            @SCREEN-2
+            D=M
+            goto END
+            goto END // allow(unreachable-code)
+            (UNUSED) // allow(unused-label, case-mismatch)
+            if D>0 goto R13
+            ";
+
+        assert_eq!(
+            warnings(program),
+            [
+                "5:13: warning: memory address may be out of range `@SCREEN-2` \
+                 (address 16382 is outside the screen and keyboard) [unchecked-memory]",
+                "10:13: warning: jump to a data address `D;JGT` \
+                 (A holds the address of `R13`) [jump-to-data]",
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_follow_file_order() {
+        let main = "@Zeta.once\nM=0\n(END)\n@END\n0;JMP";
+        let assembly =
+            Assembler::build_files(&[("Main.asm", main), ("A.asm", "@a.once\nM=0")], &|_| {
+                Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+            })
+            .unwrap();
+        let files: Vec<&str> = assembly
+            .warnings
+            .iter()
+            .map(|warning| warning.file.as_ref())
+            .collect();
+
+        assert_eq!(files, ["Main.asm", "A.asm", "A.asm"]);
+    }
+}
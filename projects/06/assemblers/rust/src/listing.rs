@@ -0,0 +1,149 @@
+use crate::assembler::Assembly;
+
+pub fn generate_listing(assembly: &Assembly) -> String {
+    let mut listing = String::from("ROM    Binary              Line  Source\n");
+
+    let mut file = assembly.lines.first().map(|line| &line.span.file);
+    for line in &assembly.lines {
+        // Lines of another file, from an include or the next source file,
+        // start with its name
+        if Some(&line.span.file) != file {
+            file = Some(&line.span.file);
+            listing.push_str(&format!(
+                "{:05}  {:<16}  {:>6}  // {}\n",
+                line.address, "", "", line.span.file
+            ));
+        }
+
+        let word = line
+            .instruction
+            .map(|instruction| format!("{:016b}", instruction.encode()))
+            .unwrap_or_default();
+        // Lines expanded from a macro are marked with one `+` per level
+        let expansion = match line.span.depth {
+            0 => String::new(),
+            depth => format!("{} ", "+".repeat(depth)),
+        };
+        listing.push_str(&format!(
+            "{:05}  {:<16}  {:>6}  {}{}\n",
+            line.address,
+            word,
+            line.span.line,
+            expansion,
+            line.span.raw.trim()
+        ));
+    }
+
+    listing.push_str("\nLabels:\n");
+    for (label, address) in assembly.symbols.labels() {
+        listing.push_str(&format!("{:05}  {}\n", address, label));
+    }
+
+    listing.push_str("\nVariables:\n");
+    for (variable, address) in assembly.symbols.variables() {
+        listing.push_str(&format!("{:05}  {}\n", address, variable));
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[test]
+    fn listing() {
+        let program = "
+            // Test Program
+            @i
+            M=1 // i = 1
+        (LOOP)
+            @LOOP
+            0;JMP
+        "
+        .to_string();
+
+        let assembly = Assembler::build("", program).unwrap();
+        let listing = vec![
+            "ROM    Binary              Line  Source",
+            "00000  0000000000010000       3  @i",
+            "00001  1110111111001000       4  M=1 // i = 1",
+            "00002                         5  (LOOP)",
+            "00002  0000000000000010       6  @LOOP",
+            "00003  1110101010000111       7  0;JMP",
+            "",
+            "Labels:",
+            "00002  LOOP",
+            "",
+            "Variables:",
+            "00016  i",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(generate_listing(&assembly), listing);
+    }
+
+    #[test]
+    fn macro_listing() {
+        let program = "
+            .macro LOAD x
+                @x
+                D=M
+            .endm
+            .macro SWAP a, b
+                LOAD a
+                @b
+            .endm
+            SWAP R1, R2
+            goto 0
+        "
+        .to_string();
+
+        let assembly = Assembler::build("", program).unwrap();
+        let listing = [
+            "ROM    Binary              Line  Source",
+            "00000                        10  SWAP R1, R2",
+            "00000                         7  + LOAD R1",
+            "00000  0000000000000001       3  ++ @R1",
+            "00001  1111110000010000       4  ++ D=M",
+            "00002  0000000000000010       8  + @R2",
+            "00003                        11  goto 0",
+            "00003  0000000000000000      11  + @0",
+            "00004  1110101010000111      11  + 0;JMP",
+            "",
+            "Labels:",
+            "",
+            "Variables:",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(generate_listing(&assembly), listing);
+    }
+
+    #[test]
+    fn file_names() {
+        let assembly =
+            Assembler::build_files(&[("Main.asm", "@R1\n@R2"), ("Math.asm", "@R3")], &|_| {
+                Ok(String::new())
+            })
+            .unwrap();
+        let listing = [
+            "ROM    Binary              Line  Source",
+            "00000  0000000000000001       1  @R1",
+            "00001  0000000000000010       2  @R2",
+            "00002                            // Math.asm",
+            "00002  0000000000000011       1  @R3",
+            "",
+            "Labels:",
+            "",
+            "Variables:",
+            "",
+        ]
+        .join("\n");
+
+        assert_eq!(generate_listing(&assembly), listing);
+    }
+}
@@ -0,0 +1,882 @@
+//! A language server for Hack assembly, speaking JSON-RPC over stdio as
+//! `hack-lsp`. Each open file is assembled on every change, and requests
+//! are answered from the resulting `Document`:
+//!
+//! - diagnostics for errors and warnings,
+//! - go to definition and find references for labels, constants and
+//!   variables,
+//! - hover with a symbol's value and the words a line encodes to,
+//! - completion of symbols after `@` or `goto`, and of dest, comp and jump
+//!   mnemonics in C-instructions,
+//! - document symbols listing the labels.
+//!
+//! `Server::handle` answers one decoded message, so the server can be
+//! driven in-process without any framing.
+
+use crate::document::{Document, Range};
+use crate::instruction::{Comp, Dest, Jump};
+use crate::json::Json;
+use crate::lexer;
+use crate::platform::Platform;
+use crate::symbol_map::SymbolKind;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+};
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// Kinds from the protocol's `CompletionItemKind` and `SymbolKind`
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_VARIABLE: usize = 6;
+const COMPLETION_KEYWORD: usize = 14;
+const COMPLETION_CONSTANT: usize = 21;
+const SYMBOL_FUNCTION: usize = 12;
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+/// Documents are synced by sending their whole text.
+const SYNC_FULL: usize = 1;
+
+/// A failed request: a JSON-RPC error code and message.
+type RequestError = (i32, String);
+
+pub struct Server {
+    platform: Platform,
+    /// Open documents by URI.
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new(platform: Platform) -> Server {
+        Server {
+            platform,
+            documents: HashMap::new(),
+            shutdown: false,
+            exited: false,
+        }
+    }
+
+    /// Reads messages from `input` and writes replies to `output` until the
+    /// client sends `exit` or closes the input.
+    pub fn serve<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+        while let Some(body) = read_message(&mut input)? {
+            let replies = match Json::parse(&body) {
+                Ok(message) => self.handle(&message),
+                Err(error) => vec![error_response(Json::Null, (PARSE_ERROR, error.to_string()))],
+            };
+            for reply in &replies {
+                write_message(&mut output, reply)?;
+            }
+            output.flush()?;
+
+            if self.exited {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The process exit code the protocol asks for: 0 after an orderly
+    /// `shutdown`, 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.shutdown {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Handles one message from the client, returning the response to a
+    /// request and any notifications for the client.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str);
+        let params = message.get("params").unwrap_or(&Json::Null);
+
+        match (message.get("id"), method) {
+            (Some(id), Some(method)) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => Json::object(vec![
+                        ("jsonrpc", Json::from("2.0")),
+                        ("id", id.clone()),
+                        ("result", result),
+                    ]),
+                    Err(error) => error_response(id.clone(), error),
+                };
+                vec![response]
+            }
+            (None, Some(method)) => self.notification(method, params),
+            // The server sends no requests, so expects no responses
+            (Some(_), None) => Vec::new(),
+            (None, None) => vec![error_response(
+                Json::Null,
+                (INVALID_REQUEST, String::from("message has no method")),
+            )],
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RequestError> {
+        match method {
+            "initialize" => Ok(Json::object(vec![
+                ("capabilities", capabilities()),
+                (
+                    "serverInfo",
+                    Json::object(vec![
+                        ("name", Json::from("hack-lsp")),
+                        ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, document, line, character) = self.position(params)?;
+                let location = document
+                    .occurrence_at(line, character)
+                    .and_then(|occurrence| document.definition(&occurrence.name))
+                    .map(|definition| location(uri, definition.range));
+                Ok(Json::from(location))
+            }
+            "textDocument/references" => {
+                let (uri, document, line, character) = self.position(params)?;
+                let declaration = params
+                    .get("context")
+                    .and_then(|context| context.get("includeDeclaration"))
+                    .and_then(Json::as_bool)
+                    .unwrap_or(true);
+                let locations = match document.occurrence_at(line, character) {
+                    Some(occurrence) => {
+                        let definition = document.definition(&occurrence.name);
+                        document
+                            .references(&occurrence.name)
+                            .filter(|reference| declaration || Some(*reference) != definition)
+                            .map(|reference| location(uri, reference.range))
+                            .collect()
+                    }
+                    None => Vec::new(),
+                };
+                Ok(Json::from(locations))
+            }
+            "textDocument/hover" => {
+                let (_, document, line, character) = self.position(params)?;
+                Ok(hover(document, line, character))
+            }
+            "textDocument/completion" => {
+                let (_, document, line, character) = self.position(params)?;
+                Ok(Json::from(completions(document, line, character)))
+            }
+            "textDocument/documentSymbol" => {
+                let (_, document) = self.document(params)?;
+                let symbols = document
+                    .labels()
+                    .map(|label| {
+                        Json::object(vec![
+                            ("name", Json::from(display_name(document, &label.name))),
+                            ("detail", Json::from(document.describe(&label.name))),
+                            ("kind", Json::from(SYMBOL_FUNCTION)),
+                            ("range", range(label.range)),
+                            ("selectionRange", range(label.range)),
+                        ])
+                    })
+                    .collect::<Vec<Json>>();
+                Ok(Json::from(symbols))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let uri = document
+            .and_then(|document| document.get("uri"))
+            .and_then(Json::as_str);
+
+        let text = match (method, uri) {
+            ("exit", _) => {
+                self.exited = true;
+                return Vec::new();
+            }
+            ("textDocument/didOpen", Some(_)) => document
+                .and_then(|document| document.get("text"))
+                .and_then(Json::as_str),
+            // The server asks for whole documents, so the last change holds
+            // the full text
+            ("textDocument/didChange", Some(_)) => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(<[Json]>::last)
+                .and_then(|change| change.get("text"))
+                .and_then(Json::as_str),
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => return Vec::new(),
+        };
+
+        match (uri, text) {
+            (Some(uri), Some(text)) => {
+                let document = Document::new(
+                    &self.platform,
+                    &uri_path(uri),
+                    String::from(text),
+                    &|path| fs::read_to_string(path),
+                );
+                let diagnostics = document
+                    .diagnostics()
+                    .into_iter()
+                    .map(|diagnostic| {
+                        let mut members = vec![
+                            ("range", range(diagnostic.range)),
+                            (
+                                "severity",
+                                Json::from(if diagnostic.error {
+                                    SEVERITY_ERROR
+                                } else {
+                                    SEVERITY_WARNING
+                                }),
+                            ),
+                            ("source", Json::from("hack-asm")),
+                            ("message", Json::from(diagnostic.message)),
+                        ];
+                        if let Some(code) = diagnostic.code {
+                            members.push(("code", Json::from(code)));
+                        }
+                        Json::object(members)
+                    })
+                    .collect();
+                self.documents.insert(String::from(uri), document);
+                vec![publish_diagnostics(uri, diagnostics)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), RequestError> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, String::from("missing textDocument.uri")))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("`{}` is not open", uri)))?;
+        Ok((uri, document))
+    }
+
+    fn position<'a>(
+        &'a self,
+        params: &'a Json,
+    ) -> Result<(&'a str, &'a Document, usize, usize), RequestError> {
+        let (uri, document) = self.document(params)?;
+        let position = params.get("position");
+        let field = |name: &str| {
+            position
+                .and_then(|position| position.get(name))
+                .and_then(Json::as_usize)
+                .ok_or_else(|| (INVALID_PARAMS, format!("missing position.{}", name)))
+        };
+        Ok((uri, document, field("line")?, field("character")?))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        ("textDocumentSync", Json::from(SYNC_FULL)),
+        ("definitionProvider", Json::from(true)),
+        ("referencesProvider", Json::from(true)),
+        ("hoverProvider", Json::from(true)),
+        (
+            "completionProvider",
+            Json::object(vec![(
+                "triggerCharacters",
+                Json::from(vec![Json::from("@"), Json::from("="), Json::from(";")]),
+            )]),
+        ),
+        ("documentSymbolProvider", Json::from(true)),
+    ])
+}
+
+fn hover(document: &Document, line: usize, character: usize) -> Json {
+    let occurrence = document.occurrence_at(line, character);
+    let mut sections = Vec::new();
+    if let Some(occurrence) = occurrence {
+        if let Some(description) = document.describe(&occurrence.name) {
+            let name = display_name(document, &occurrence.name);
+            sections.push(format!("`{}`: {}", name, description));
+        }
+    }
+
+    let instructions = document.instructions(line);
+    if !instructions.is_empty() {
+        let mut words = String::from("```\n");
+        for (address, instruction) in instructions {
+            words.push_str(&format!(
+                "{:05}  {:016b}  {}\n",
+                address,
+                instruction.encode(),
+                instruction
+            ));
+        }
+        words.push_str("```");
+        sections.push(words);
+    }
+
+    if sections.is_empty() {
+        return Json::Null;
+    }
+    let mut members = vec![(
+        "contents",
+        Json::object(vec![
+            ("kind", Json::from("markdown")),
+            ("value", Json::from(sections.join("\n\n"))),
+        ]),
+    )];
+    if let Some(occurrence) = occurrence {
+        members.push(("range", range(occurrence.range)));
+    }
+    Json::object(members)
+}
+
+/// Symbols where the code before the cursor expects an address, otherwise
+/// the mnemonics for the part of a C-instruction being written.
+fn completions(document: &Document, line: usize, character: usize) -> Vec<Json> {
+    let prefix = document.prefix(line, character);
+    if prefix.contains("//") {
+        return Vec::new();
+    }
+    let code = prefix.trim_start();
+    // The word before the one being written
+    let mut words: Vec<&str> = lexer::words(code).collect();
+    if !code.ends_with(char::is_whitespace) {
+        words.pop();
+    }
+    let after_goto = words.last() == Some(&"goto");
+    let item = |label: String, kind: usize, detail: Option<String>| {
+        Json::object(vec![
+            ("label", Json::from(label)),
+            ("kind", Json::from(kind)),
+            ("detail", Json::from(detail)),
+        ])
+    };
+
+    if code.starts_with('@') || after_goto {
+        return document
+            .symbols
+            .symbols
+            .iter()
+            .filter(|entry| !after_goto || entry.kind == SymbolKind::Label)
+            .filter(|entry| is_visible(document, &entry.scoped_name()))
+            .map(|entry| {
+                let kind = match entry.kind {
+                    SymbolKind::Label => COMPLETION_FUNCTION,
+                    SymbolKind::Variable => COMPLETION_VARIABLE,
+                    SymbolKind::Predefined | SymbolKind::Constant => COMPLETION_CONSTANT,
+                };
+                let name = entry.scoped_name();
+                item(
+                    display_name(document, &name),
+                    kind,
+                    document.describe(&name),
+                )
+            })
+            .collect();
+    }
+
+    let jumps = || (1..8).map(|bits| Jump::from_bits(bits).mnemonic().to_string());
+    let comps = || Comp::all().map(|comp| comp.mnemonic().to_string());
+    let mnemonics: Vec<String> = if code.contains(';') {
+        jumps().collect()
+    } else if code.contains('=') {
+        comps().collect()
+    } else {
+        (1..8)
+            .map(|bits| format!("{}=", Dest::from_bits(bits)))
+            .chain(comps())
+            .collect()
+    };
+    mnemonics
+        .into_iter()
+        .map(|mnemonic| item(mnemonic, COMPLETION_KEYWORD, None))
+        .collect()
+}
+
+/// Whether a symbol can be written in the document: private symbols of
+/// other files and labels made for macro expansions cannot.
+fn is_visible(document: &Document, name: &str) -> bool {
+    let name = display_name(document, name);
+    let private = name.contains("::");
+    let expansion = name.starts_with('_') && name.contains('$');
+    !private && !expansion
+}
+
+/// A symbol as written in the document, without the file qualifying a
+/// private one.
+fn display_name(document: &Document, name: &str) -> String {
+    let prefix = format!("{}::", document.path);
+    name.strip_prefix(&prefix).unwrap_or(name).to_string()
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("line", Json::from(line)),
+        ("character", Json::from(character)),
+    ])
+}
+
+fn range(range: Range) -> Json {
+    Json::object(vec![
+        ("start", position(range.line, range.start)),
+        ("end", position(range.line, range.end)),
+    ])
+}
+
+fn location(uri: &str, location: Range) -> Json {
+    Json::object(vec![("uri", Json::from(uri)), ("range", range(location))])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", Json::from(uri)),
+                ("diagnostics", Json::from(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: Json, (code, message): RequestError) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::from(code)),
+                ("message", Json::from(message)),
+            ]),
+        ),
+    ])
+}
+
+/// The file a `file://` URI names, so includes are read relative to it.
+/// Other URIs are used as the file name as they are.
+fn uri_path(uri: &str) -> String {
+    let path = match uri.strip_prefix("file://") {
+        Some(path) => path,
+        None => return String::from(uri),
+    };
+
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8_lossy(&bytes).into_owned();
+
+    // `file:///C:/...` on Windows
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => path[1..].to_string(),
+        _ => path,
+    }
+}
+
+/// Reads one message framed by a `Content-Length` header, or `None` at the
+/// end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///work/Main.asm";
+
+    /// Plays the editor's side of a session, one message at a time.
+    struct Client {
+        server: Server,
+        next_id: usize,
+        /// Diagnostics last published for `URI`.
+        diagnostics: Vec<Json>,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let mut client = Client {
+                server: Server::new(Platform::hack()),
+                next_id: 1,
+                diagnostics: Vec::new(),
+            };
+            let result = client.request("initialize", Json::object(vec![]));
+            assert!(result.get("capabilities").is_some());
+            client.notify("initialized", Json::object(vec![]));
+            client
+        }
+
+        fn request(&mut self, method: &str, params: Json) -> Json {
+            let id = self.next_id;
+            self.next_id += 1;
+            let replies = self.send(Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("id", Json::from(id)),
+                ("method", Json::from(method)),
+                ("params", params),
+            ]));
+
+            assert_eq!(replies.len(), 1);
+            assert_eq!(replies[0].get("id"), Some(&Json::from(id)));
+            match replies[0].get("result") {
+                Some(result) => result.clone(),
+                None => replies[0].get("error").unwrap().clone(),
+            }
+        }
+
+        fn notify(&mut self, method: &str, params: Json) {
+            self.send(Json::object(vec![
+                ("jsonrpc", Json::from("2.0")),
+                ("method", Json::from(method)),
+                ("params", params),
+            ]));
+        }
+
+        /// Sends a message, keeping published diagnostics and returning the
+        /// other replies.
+        fn send(&mut self, message: Json) -> Vec<Json> {
+            let mut replies = Vec::new();
+            for reply in self.server.handle(&message) {
+                let method = reply.get("method").and_then(Json::as_str);
+                if method == Some("textDocument/publishDiagnostics") {
+                    let params = reply.get("params").unwrap();
+                    assert_eq!(params.get("uri").and_then(Json::as_str), Some(URI));
+                    self.diagnostics = params
+                        .get("diagnostics")
+                        .and_then(Json::as_array)
+                        .unwrap()
+                        .to_vec();
+                } else {
+                    replies.push(reply);
+                }
+            }
+            replies
+        }
+
+        fn open(&mut self, text: &str) {
+            self.notify(
+                "textDocument/didOpen",
+                Json::object(vec![(
+                    "textDocument",
+                    Json::object(vec![
+                        ("uri", Json::from(URI)),
+                        ("languageId", Json::from("hack-asm")),
+                        ("version", Json::from(1)),
+                        ("text", Json::from(text)),
+                    ]),
+                )]),
+            );
+        }
+
+        fn change(&mut self, text: &str) {
+            self.notify(
+                "textDocument/didChange",
+                Json::object(vec![
+                    (
+                        "textDocument",
+                        Json::object(vec![("uri", Json::from(URI)), ("version", Json::from(2))]),
+                    ),
+                    (
+                        "contentChanges",
+                        Json::from(vec![Json::object(vec![("text", Json::from(text))])]),
+                    ),
+                ]),
+            );
+        }
+
+        fn at(&mut self, method: &str, line: usize, character: usize) -> Json {
+            self.request(
+                method,
+                Json::object(vec![
+                    ("textDocument", Json::object(vec![("uri", Json::from(URI))])),
+                    ("position", position(line, character)),
+                    (
+                        "context",
+                        Json::object(vec![("includeDeclaration", Json::from(true))]),
+                    ),
+                ]),
+            )
+        }
+
+        /// The ranges of the locations in a result, as `line:start-end`.
+        fn ranges(result: &Json) -> Vec<String> {
+            let field = |range: &Json, end: &str, name: &str| {
+                range.get(end).unwrap().get(name).unwrap().to_string()
+            };
+            result
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|location| {
+                    let range = location.get("range").unwrap();
+                    format!(
+                        "{}:{}-{}",
+                        field(range, "start", "line"),
+                        field(range, "start", "character"),
+                        field(range, "end", "character")
+                    )
+                })
+                .collect()
+        }
+
+        fn labels(result: &Json) -> Vec<String> {
+            result
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item.get("label").unwrap().as_str().unwrap().to_string())
+                .collect()
+        }
+    }
+
+    const PROGRAM: &str = "\
+// Counts down from 10
+    @10
+    D=A
+    @count
+    M=D
+(LOOP)
+    @count
+    MD=M-1
+    @LOOP
+    D;JGT
+(END)
+    goto END
+";
+
+    #[test]
+    fn diagnostics() {
+        let mut client = Client::new();
+        client.open(PROGRAM);
+        assert_eq!(client.diagnostics, []);
+
+        client.change(&PROGRAM.replace("MD=M-1", "MD=M-X"));
+        assert_eq!(
+            client.diagnostics[0].to_string(),
+            "{\"range\":{\"start\":{\"line\":7,\"character\":7},\
+             \"end\":{\"line\":7,\"character\":10}},\"severity\":1,\
+             \"source\":\"hack-asm\",\"message\":\"unknown computation `M-X`\"}"
+        );
+
+        client.change("@tmp\nM=0\n");
+        assert_eq!(client.diagnostics.len(), 1);
+        assert_eq!(
+            client.diagnostics[0].get("code").and_then(Json::as_str),
+            Some("single-use-variable")
+        );
+        assert_eq!(client.diagnostics[0].get("severity"), Some(&Json::from(2)));
+
+        client.notify(
+            "textDocument/didClose",
+            Json::object(vec![(
+                "textDocument",
+                Json::object(vec![("uri", Json::from(URI))]),
+            )]),
+        );
+        assert_eq!(client.diagnostics, []);
+    }
+
+    #[test]
+    fn definitions_and_references() {
+        let mut client = Client::new();
+        client.open(PROGRAM);
+
+        let definition = client.at("textDocument/definition", 8, 6);
+        assert_eq!(
+            definition,
+            location(
+                URI,
+                Range {
+                    line: 5,
+                    start: 1,
+                    end: 5,
+                }
+            )
+        );
+        assert_eq!(
+            Client::ranges(&client.at("textDocument/references", 3, 5)),
+            ["3:5-10", "6:5-10"]
+        );
+        assert_eq!(
+            Client::ranges(&client.at("textDocument/references", 11, 10)),
+            ["10:1-4", "11:9-12"]
+        );
+        assert_eq!(client.at("textDocument/definition", 2, 4), Json::Null);
+    }
+
+    #[test]
+    fn hover() {
+        let mut client = Client::new();
+        client.open(PROGRAM);
+
+        let hover = client.at("textDocument/hover", 8, 6);
+        let contents = hover.get("contents").unwrap();
+        assert_eq!(
+            contents.get("value").and_then(Json::as_str),
+            Some("`LOOP`: label, ROM 4\n\n```\n00006  0000000000000100  @4\n```")
+        );
+        let hover = client.at("textDocument/hover", 3, 6);
+        assert!(hover
+            .get("contents")
+            .and_then(|contents| contents.get("value"))
+            .and_then(Json::as_str)
+            .unwrap()
+            .starts_with("`count`: variable, RAM 16"));
+        assert_eq!(client.at("textDocument/hover", 0, 3), Json::Null);
+    }
+
+    #[test]
+    fn completion_and_symbols() {
+        let mut client = Client::new();
+        client.open(&format!("{}    @\n    D;\n    AM=\n    goto \n", PROGRAM));
+
+        let symbols = Client::labels(&client.at("textDocument/completion", 12, 5));
+        assert!(symbols.contains(&String::from("LOOP")));
+        assert!(symbols.contains(&String::from("count")));
+        assert!(symbols.contains(&String::from("SCREEN")));
+        assert_eq!(
+            Client::labels(&client.at("textDocument/completion", 13, 6)),
+            ["JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"]
+        );
+        let comps = Client::labels(&client.at("textDocument/completion", 14, 7));
+        assert_eq!(comps.len(), 28);
+        assert_eq!(
+            Client::labels(&client.at("textDocument/completion", 15, 9)),
+            ["LOOP", "END"]
+        );
+        let start = Client::labels(&client.at("textDocument/completion", 1, 4));
+        assert_eq!(start[..3], ["M=", "D=", "MD="]);
+
+        let result = client.request(
+            "textDocument/documentSymbol",
+            Json::object(vec![(
+                "textDocument",
+                Json::object(vec![("uri", Json::from(URI))]),
+            )]),
+        );
+        let names: Vec<&str> = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|symbol| symbol.get("name").and_then(Json::as_str))
+            .collect();
+        assert_eq!(names, ["LOOP", "END"]);
+    }
+
+    #[test]
+    fn errors_and_shutdown() {
+        let mut client = Client::new();
+        let error = client.request("textDocument/formatting", Json::Null);
+        assert_eq!(error.get("code"), Some(&Json::from(METHOD_NOT_FOUND)));
+        let error = client.at("textDocument/hover", 0, 0);
+        assert_eq!(error.get("code"), Some(&Json::from(INVALID_PARAMS)));
+
+        assert_eq!(client.server.exit_code(), 1);
+        assert_eq!(client.request("shutdown", Json::Null), Json::Null);
+        client.notify("exit", Json::Null);
+        assert_eq!(client.server.exit_code(), 0);
+    }
+
+    #[test]
+    fn framing() {
+        let mut input = Vec::new();
+        for message in [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            "{not json",
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+        ] {
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", message.len(), message).bytes());
+        }
+        let mut output = Vec::new();
+        let mut server = Server::new(Platform::hack());
+        server.serve(&input[..], &mut output).unwrap();
+
+        let mut output = &output[..];
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        // Nothing is read after `exit`
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0].get("id"), Some(&Json::from(1)));
+        assert_eq!(
+            replies[1].get("error").and_then(|error| error.get("code")),
+            Some(&Json::from(PARSE_ERROR))
+        );
+        assert_eq!(replies[2].get("result"), Some(&Json::Null));
+        assert_eq!(server.exit_code(), 0);
+    }
+
+    #[test]
+    fn uri_paths() {
+        assert_eq!(
+            uri_path("file:///work/My%20Programs/Max.asm"),
+            "/work/My Programs/Max.asm"
+        );
+        assert_eq!(uri_path("file:///C:/hack/Max.asm"), "C:/hack/Max.asm");
+        assert_eq!(uri_path("untitled:Untitled-1"), "untitled:Untitled-1");
+    }
+}
@@ -1,9 +1,4 @@
-mod assembler;
-mod cli;
-mod code_gen;
-mod parser;
-mod symbols;
-
+use hack_assembler::cli;
 use std::{env, process};
 
 fn main() {
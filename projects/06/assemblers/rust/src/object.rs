@@ -0,0 +1,197 @@
+//! Relocatable object files, assembled from part of a program and combined
+//! by the linker. Addresses in an object count from its first instruction.
+//!
+//! Objects are stored as text, one record per line:
+//!
+//! ```text
+//! hack-object 1
+//! words 3
+//! 0000000000000010
+//! 1110101010000111
+//! 0000000000000000
+//! export LOOP 0
+//! relocate 0
+//! import 2 Math.multiply
+//! variable 1 counter
+//! ```
+
+use crate::error::FormatError;
+
+const HEADER: &str = "hack-object 1";
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ObjectFile {
+    pub code: Vec<u16>,
+    /// Labels other objects may refer to, with their address.
+    pub exports: Vec<(String, u16)>,
+    /// Words holding an address in this object, which move with it.
+    pub relocations: Vec<u16>,
+    /// Words holding the address of a label another object exports.
+    pub imports: Vec<(u16, String)>,
+    /// Words holding the address of a variable, allocated at link time.
+    pub variables: Vec<(u16, String)>,
+}
+
+impl ObjectFile {
+    pub fn write(&self) -> String {
+        let mut text = format!("{}\nwords {}\n", HEADER, self.code.len());
+        for word in &self.code {
+            text.push_str(&format!("{:016b}\n", word));
+        }
+        for (name, address) in &self.exports {
+            text.push_str(&format!("export {} {}\n", name, address));
+        }
+        for index in &self.relocations {
+            text.push_str(&format!("relocate {}\n", index));
+        }
+        for (index, name) in &self.imports {
+            text.push_str(&format!("import {} {}\n", index, name));
+        }
+        for (index, name) in &self.variables {
+            text.push_str(&format!("variable {} {}\n", index, name));
+        }
+        text
+    }
+
+    pub fn read(text: &str) -> Result<ObjectFile, FormatError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(FormatError::new(1, "not a Hack object file"));
+        }
+
+        let mut object = ObjectFile::default();
+        let mut words = None;
+
+        for (number, line) in lines {
+            let error = |message: &str| FormatError::new(number, message);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            if let Some(remaining) = words {
+                if remaining > 0 {
+                    let word = u16::from_str_radix(line, 2)
+                        .map_err(|_| error(&format!("invalid word `{}`", line)))?;
+                    object.code.push(word);
+                    words = Some(remaining - 1);
+                    continue;
+                }
+            }
+
+            let index = |field: &str| -> Result<u16, FormatError> {
+                match field.parse::<u16>() {
+                    Ok(index) if (index as usize) < object.code.len() => Ok(index),
+                    _ => Err(error(&format!("invalid word index `{}`", field))),
+                }
+            };
+            // The linker adds to relocated words and replaces imported ones,
+            // which must stay A-instructions
+            let address = |field: &str| -> Result<u16, FormatError> {
+                let index = index(field)?;
+                if object.code[index as usize] & 0x8000 == 0 {
+                    Ok(index)
+                } else {
+                    Err(error(&format!("word {} is not an A-instruction", index)))
+                }
+            };
+
+            match fields.as_slice() {
+                ["words", count] if words.is_none() => {
+                    let count = count
+                        .parse::<usize>()
+                        .map_err(|_| error("invalid word count"))?;
+                    words = Some(count);
+                }
+                // A label may follow the last instruction
+                ["export", name, address] => {
+                    let address = address
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|&address| address as usize <= object.code.len())
+                        .ok_or_else(|| error(&format!("invalid address `{}`", address)))?;
+                    object.exports.push((name.to_string(), address));
+                }
+                ["relocate", word] => {
+                    let word = address(word)?;
+                    object.relocations.push(word);
+                }
+                ["import", word, name] => {
+                    let word = address(word)?;
+                    object.imports.push((word, name.to_string()));
+                }
+                ["variable", word, name] => {
+                    let word = index(word)?;
+                    object.variables.push((word, name.to_string()));
+                }
+                [] => {}
+                _ => return Err(error(&format!("unknown record `{}`", line))),
+            }
+        }
+
+        match words {
+            Some(0) => Ok(object),
+            Some(_) => Err(FormatError::new(0, "file ends in the middle of the code")),
+            None => Err(FormatError::new(0, "missing word count")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let object = ObjectFile {
+            code: vec![2, 0xEA87, 0],
+            exports: vec![(String::from("LOOP"), 0)],
+            relocations: vec![0],
+            imports: vec![(2, String::from("Math.multiply"))],
+            variables: vec![(1, String::from("counter"))],
+        };
+        let text = object.write();
+
+        assert_eq!(
+            text,
+            "hack-object 1\nwords 3\n0000000000000010\n1110101010000111\n\
+             0000000000000000\nexport LOOP 0\nrelocate 0\nimport 2 Math.multiply\n\
+             variable 1 counter\n"
+        );
+        assert_eq!(ObjectFile::read(&text), Ok(object));
+    }
+
+    #[test]
+    fn read_errors() {
+        let error = |text: &str| ObjectFile::read(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("0000000000000010\n"),
+            "1: error: not a Hack object file"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 2\n0000000000000010\n"),
+            "error: file ends in the middle of the code"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 1\n2\nrelocate 1\n"),
+            "3: error: invalid word `2`"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 1\n0000000000000010\nrelocate 1\n"),
+            "4: error: invalid word index `1`"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 1\n0000000000000010\nexport X 65535\n"),
+            "4: error: invalid address `65535`"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 1\n1110101010000111\nrelocate 0\n"),
+            "4: error: word 0 is not an A-instruction"
+        );
+        assert_eq!(
+            error("hack-object 1\nwords 0\nsection text\n"),
+            "3: error: unknown record `section text`"
+        );
+    }
+}
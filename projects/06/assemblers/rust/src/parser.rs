@@ -1,4 +1,19 @@
-use regex::Regex;
+use crate::error::{AssembleError, ErrorKind};
+use crate::expression::Expression;
+use crate::lexer::{self, SourceText};
+use crate::pseudo;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+// Guards against macros that expand into themselves
+const MAX_MACRO_DEPTH: usize = 16;
+// Guards against macros whose expansions multiply, such as one calling the
+// previous twice. Twice the ROM leaves room for labels.
+const MAX_EXPANDED_LINES: usize = 0x10000;
 
 #[derive(Debug, PartialEq)]
 pub struct Command {
@@ -7,62 +22,488 @@ pub struct Command {
     pub jump: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CommandType {
     Address,
     Jump,
     Computation,
+    /// A macro call, pseudo-instruction or include, followed by the lines it
+    /// expanded to.
+    Expansion,
+    /// `.equ NAME expr`, or its synonym `.define NAME expr`.
+    Constant,
+    /// `.extern NAME`, a label another object file defines.
+    Extern,
+}
+
+impl CommandType {
+    /// Whether the command takes up a ROM address.
+    pub fn is_instruction(self) -> bool {
+        matches!(self, CommandType::Address | CommandType::Computation)
+    }
+}
+
+/// Where a normalized command came from in the original source.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub raw: SourceText,
+    /// How many macro or pseudo-instruction expansions deep the line is, 0
+    /// for lines written out in the source.
+    pub depth: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+struct Line {
+    span: Span,
+    text: SourceText,
+    /// Set for lines the preprocessor already identified, otherwise the type
+    /// follows from the text.
+    command: Option<CommandType>,
+    /// Whether the line was lowered from a pseudo-instruction, so its text
+    /// is not what the source says.
+    lowered: bool,
+}
+
+/// One line of source, as read from a file or substituted into a macro body.
+#[derive(Clone)]
+struct SourceLine {
+    file: Rc<str>,
+    number: usize,
+    raw: SourceText,
+    /// For a line lowered from a pseudo-instruction, the column the
+    /// pseudo-instruction starts at.
+    column: Option<usize>,
+}
+
+impl SourceLine {
+    fn read(file: &Rc<str>, contents: &SourceText) -> Vec<SourceLine> {
+        contents
+            .lines()
+            .map(|(number, raw)| SourceLine {
+                file: Rc::clone(file),
+                number,
+                raw,
+                column: None,
+            })
+            .collect()
+    }
+
+    /// Splits a directive or macro call into its name and arguments.
+    fn words(&self) -> Vec<String> {
+        lexer::words(lexer::strip_comment(&self.raw))
+            .map(String::from)
+            .collect()
+    }
+
+    fn directive(&self) -> Option<&str> {
+        lexer::words(lexer::strip_comment(&self.raw)).next()
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+}
+
+/// Reads the file an `.include` names.
+pub type Loader<'a> = &'a dyn Fn(&Path) -> io::Result<String>;
+
+/// Expands includes, macros and pseudo-instructions while splitting the
+/// sources into lines. Lines coming from a macro keep the file and line
+/// number they have in its definition, with the arguments already
+/// substituted into their raw text. Lowered pseudo-instructions keep the
+/// line of the pseudo-instruction.
+struct Preprocessor<'a> {
+    load: Loader<'a>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    /// How many lines macros have expanded to, and the call being expanded
+    /// from the source, where a too large expansion is reported.
+    expanded_lines: usize,
+    call: Option<SourceLine>,
+    /// The files being included, each with the line of its `.include`.
+    including: Vec<(Rc<str>, Option<usize>)>,
+    lines: Vec<Line>,
+    errors: Vec<AssembleError>,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn run(sources: &[(&str, &str)], load: Loader<'a>) -> (Vec<Line>, Vec<AssembleError>) {
+        let mut preprocessor = Preprocessor {
+            load,
+            macros: HashMap::new(),
+            expansions: 0,
+            expanded_lines: 0,
+            call: None,
+            including: Vec::new(),
+            lines: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for (file, contents) in sources {
+            let file: Rc<str> = Rc::from(*file);
+            preprocessor.including.push((Rc::clone(&file), None));
+            let contents = SourceText::from(*contents);
+            preprocessor.process(&SourceLine::read(&file, &contents), 0);
+            preprocessor.including.pop();
+        }
+
+        (preprocessor.lines, preprocessor.errors)
+    }
+
+    fn process(&mut self, source: &[SourceLine], depth: usize) {
+        let mut index = 0;
+
+        while index < source.len() {
+            let line = &source[index];
+            index += 1;
+
+            match line.directive() {
+                Some(".macro") => {
+                    let end = source[index..]
+                        .iter()
+                        .position(|line| line.directive() == Some(".endm"));
+
+                    match end {
+                        Some(length) => {
+                            let words = line.words();
+                            self.define(line, &words[1..], &source[index..index + length]);
+                            index += length + 1;
+                        }
+                        None => {
+                            self.error(ErrorKind::MalformedMacro, line, line.raw.trim(), None);
+                            index = source.len();
+                        }
+                    }
+                }
+                Some(".endm") => self.error(ErrorKind::MalformedMacro, line, line.raw.trim(), None),
+                Some(".include") => {
+                    self.push_line(line, depth, Some(CommandType::Expansion));
+                    self.include(line);
+                }
+                Some(name) if self.macros.contains_key(name) => {
+                    self.push_line(line, depth, Some(CommandType::Expansion));
+                    self.expand(name, &line.words()[1..], line, depth);
+                }
+                Some(".equ") | Some(".define") => {
+                    self.push_line(line, depth, Some(CommandType::Constant))
+                }
+                Some(".extern") => self.push_line(line, depth, Some(CommandType::Extern)),
+                _ => self.push_line(line, depth, None),
+            }
+        }
+    }
+
+    /// `.include "path"`, where the path is relative to the including file.
+    fn include(&mut self, line: &SourceLine) {
+        let code = lexer::strip_comment(&line.raw);
+        let path = code
+            .trim()
+            .strip_prefix(".include")
+            .map(str::trim)
+            .and_then(|path| path.strip_prefix('"'))
+            .and_then(|path| path.strip_suffix('"'))
+            .filter(|path| !path.is_empty());
+        let path = match path {
+            Some(path) => Preprocessor::normalize(
+                &Path::new(line.file.as_ref())
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(path),
+            ),
+            None => return self.error(ErrorKind::MalformedInclude, line, code.trim(), None),
+        };
+        let file: Rc<str> = Rc::from(path.to_string_lossy().as_ref());
+
+        if let Some(start) = self
+            .including
+            .iter()
+            .position(|(including, _)| Path::new(including.as_ref()) == path)
+        {
+            let note = match self.including.get(start + 1) {
+                Some((_, Some(number))) => {
+                    format!(
+                        "the cycle starts at {}",
+                        location(&self.including[start].0, *number)
+                    )
+                }
+                _ => String::from("a file cannot include itself"),
+            };
+            return self.error(ErrorKind::IncludeCycle, line, &file, Some(note));
+        }
+
+        match (self.load)(&path) {
+            Ok(contents) => {
+                self.including.push((Rc::clone(&file), Some(line.number)));
+                let contents = SourceText::from(contents);
+                self.process(&SourceLine::read(&file, &contents), 0);
+                self.including.pop();
+            }
+            Err(error) => self.error(
+                ErrorKind::IncludeNotFound,
+                line,
+                &file,
+                Some(error.to_string()),
+            ),
+        }
+    }
+
+    /// Resolves `.` and `..` in `path` without touching the file system, so
+    /// every way of naming a file ends up with the same name.
+    fn normalize(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir
+                    if matches!(
+                        normalized.components().next_back(),
+                        Some(Component::Normal(_))
+                    ) =>
+                {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+        normalized
+    }
+
+    fn define(&mut self, line: &SourceLine, header: &[String], body: &[SourceLine]) {
+        let nested = body.iter().any(|line| line.directive() == Some(".macro"));
+        let valid = match header.split_first() {
+            Some((name, params)) => {
+                !self.macros.contains_key(name)
+                    && Parser::is_valid_symbol(name)
+                    && params.iter().all(|param| Parser::is_valid_symbol(param))
+            }
+            None => false,
+        };
+
+        if nested || !valid {
+            self.error(ErrorKind::MalformedMacro, line, line.raw.trim(), None);
+            return;
+        }
+
+        self.macros.insert(
+            header[0].clone(),
+            Macro {
+                params: header[1..].to_vec(),
+                body: body.to_vec(),
+            },
+        );
+    }
+
+    fn expand(&mut self, name: &str, args: &[String], line: &SourceLine, depth: usize) {
+        if depth >= MAX_MACRO_DEPTH {
+            self.error(ErrorKind::MacroRecursion, line, name, None);
+            return;
+        }
+
+        if args.len() != self.macros[name].params.len() {
+            self.error(ErrorKind::MacroArguments, line, line.raw.trim(), None);
+            return;
+        }
+        if depth == 0 {
+            self.call = Some(line.clone());
+        }
+        if self.expanded_lines > MAX_EXPANDED_LINES {
+            // Reported once for each call in the source, not for every call
+            // nested in it
+            if let Some(call) = self.call.take() {
+                let note = format!("more than {} lines", MAX_EXPANDED_LINES);
+                self.error(ErrorKind::MacroSize, &call, call.raw.trim(), Some(note));
+            }
+            return;
+        }
+
+        let definition = &self.macros[name];
+        // Labels declared in the body are renamed so every expansion gets
+        // its own copy, private to the file like any symbol starting with `_`
+        self.expansions += 1;
+        let mut substitutions: HashMap<String, String> = HashMap::new();
+        for line in &definition.body {
+            let text = lexer::compact(&line.raw).unwrap_or_else(|| SourceText::from(""));
+            if let Some(label) = text
+                .strip_prefix('(')
+                .and_then(|label| label.strip_suffix(')'))
+            {
+                let local = format!("_{}${}.{}", label, name, self.expansions);
+                substitutions.insert(label.to_string(), local);
+            }
+        }
+        for (param, arg) in definition.params.iter().zip(args) {
+            substitutions.insert(param.clone(), arg.clone());
+        }
+
+        let body: Vec<SourceLine> = definition
+            .body
+            .iter()
+            .map(|line| SourceLine {
+                raw: SourceText::from(Preprocessor::substitute(&line.raw, &substitutions)),
+                ..line.clone()
+            })
+            .collect();
+        self.process(&body, depth + 1);
+        if depth == 0 {
+            self.call = None;
+        }
+    }
+
+    /// Replaces every symbol in `line` that has a substitution.
+    fn substitute(line: &str, substitutions: &HashMap<String, String>) -> String {
+        let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || "_.$:".contains(c);
+        let mut result = String::new();
+        let mut symbol = String::new();
+
+        for c in line.chars().chain(std::iter::once('\n')) {
+            if is_symbol_char(c) {
+                symbol.push(c);
+                continue;
+            }
+
+            match substitutions.get(symbol.as_str()) {
+                Some(replacement) => result.push_str(replacement),
+                None => result.push_str(&symbol),
+            }
+            symbol.clear();
+            result.push(c);
+        }
+
+        result.pop();
+        result
+    }
+
+    fn push_line(&mut self, line: &SourceLine, depth: usize, command: Option<CommandType>) {
+        let code = lexer::strip_comment(&line.raw);
+
+        if command.is_none() {
+            match pseudo::lower(code) {
+                Some(Ok(lowered)) => {
+                    self.push_line(line, depth, Some(CommandType::Expansion));
+                    for instruction in lowered {
+                        let lowered_line = SourceLine {
+                            raw: SourceText::from(instruction),
+                            column: line.column.or_else(|| Some(lexer::column(code))),
+                            ..line.clone()
+                        };
+                        self.push_line(&lowered_line, depth + 1, None);
+                    }
+                    return;
+                }
+                Some(Err(kind)) => {
+                    self.error(kind, line, code.trim(), None);
+                    return;
+                }
+                None => {}
+            }
+        }
+
+        if let Some(text) = lexer::compact(&line.raw) {
+            if self.call.is_some() {
+                self.expanded_lines += 1;
+            }
+            self.lines.push(Line {
+                span: Span {
+                    file: Rc::clone(&line.file),
+                    line: line.number,
+                    column: line.column.unwrap_or_else(|| lexer::column(code)),
+                    raw: line.raw.clone(),
+                    depth,
+                },
+                text,
+                command,
+                lowered: line.column.is_some(),
+            });
+        }
+    }
+
+    fn error(&mut self, kind: ErrorKind, line: &SourceLine, text: &str, note: Option<String>) {
+        self.errors.push(AssembleError {
+            file: Rc::clone(&line.file),
+            line: line.number,
+            column: lexer::column(&line.raw),
+            text: String::from(text),
+            kind,
+            note,
+        });
+    }
+}
+
+/// Where a line is, for messages that point at a second location.
+pub fn location(file: &str, line: usize) -> String {
+    if file.is_empty() {
+        format!("line {}", line)
+    } else {
+        format!("{}:{}", file, line)
+    }
 }
 
 pub struct Parser {
     current_line: usize,
-    program: Vec<String>,
+    program: Vec<Line>,
+    errors: Vec<AssembleError>,
 }
 
 impl Parser {
     pub fn new(raw_program: &str) -> Parser {
-        Parser {
-            current_line: 0,
-            program: Parser::normalize_program(raw_program),
-        }
+        Parser::with_file("", raw_program)
     }
 
-    fn normalize_program(raw_program: &str) -> Vec<String> {
-        raw_program
-            .lines()
-            .filter_map(|line| {
-                let line = Parser::strip_comments(line);
-                Parser::strip_whitespace(line)
-            })
-            .collect()
+    /// Reads included files from disk.
+    pub fn with_file(file: &str, raw_program: &str) -> Parser {
+        Parser::with_files(&[(file, raw_program)], &|path| fs::read_to_string(path))
     }
 
-    fn strip_comments(line: &str) -> String {
-        let re = Regex::new("//.*").unwrap();
-        re.replace(line, "").to_string()
+    /// Parses several files as one program, in order, reading included files
+    /// with `load`.
+    pub fn with_files(sources: &[(&str, &str)], load: Loader) -> Parser {
+        let (program, errors) = Preprocessor::run(sources, load);
+
+        Parser {
+            current_line: 0,
+            program,
+            errors,
+        }
     }
 
-    fn strip_whitespace(line: String) -> Option<String> {
-        let trimmed = line
-            .chars()
-            .filter(|c| !c.is_whitespace())
-            .collect::<String>();
+    /// Errors in includes, macro definitions and macro calls, found while
+    /// reading the source.
+    pub fn errors(&self) -> &[AssembleError] {
+        &self.errors
+    }
 
-        if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed)
+    pub(crate) fn is_valid_symbol(symbol: &str) -> bool {
+        match symbol.chars().next() {
+            Some(first) if !first.is_ascii_digit() => symbol
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c)),
+            _ => false,
         }
     }
 
-    fn get_program(&self) -> &Vec<String> {
-        &self.program
+    #[cfg(test)]
+    fn get_program(&self) -> Vec<&str> {
+        self.program.iter().map(|line| line.text.as_str()).collect()
     }
 
+    #[cfg(test)]
     fn get_current_line(&self) -> usize {
         self.current_line
     }
 
+    fn line(&self) -> &Line {
+        &self.program[self.current_line]
+    }
+
     pub fn has_more_commands(&self) -> bool {
         self.current_line < self.program.len()
     }
@@ -78,26 +519,122 @@ impl Parser {
     }
 
     pub fn command_type(&self) -> CommandType {
-        let command = self.program.get(self.current_line).unwrap();
-        let first_char = command.chars().nth(0).unwrap();
-        match first_char {
-            '@' => CommandType::Address,
-            '(' => CommandType::Jump,
-            _ => CommandType::Computation,
+        let command = &self.line().text;
+        if let Some(command_type) = self.line().command {
+            command_type
+        } else if command.starts_with('@') {
+            CommandType::Address
+        } else if command.starts_with('(') {
+            CommandType::Jump
+        } else {
+            CommandType::Computation
+        }
+    }
+
+    /// Builds an error pointing at `offset` characters into the current
+    /// command, mapped back to a column in the original source line. Errors
+    /// in lowered lines point at the pseudo-instruction.
+    pub fn error(&self, kind: ErrorKind, offset: usize, text: &str) -> AssembleError {
+        let span = &self.line().span;
+        let code = lexer::strip_comment(&span.raw);
+        let column = if self.line().lowered {
+            span.column
+        } else {
+            code.chars()
+                .enumerate()
+                .filter(|(_, c)| !c.is_whitespace())
+                .nth(offset)
+                .map_or(code.chars().count(), |(column, _)| column)
+                + 1
+        };
+
+        AssembleError {
+            file: Rc::clone(&span.file),
+            line: span.line,
+            column,
+            text: String::from(text),
+            kind,
+            note: None,
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.line().span
+    }
+
+    fn spanned<T>(&self, node: T) -> Spanned<T> {
+        Spanned {
+            node,
+            span: self.span().clone(),
+        }
+    }
+
+    pub fn get_symbol(&self) -> Result<Spanned<String>, AssembleError> {
+        let line = &self.line().text;
+        let symbol = match self.command_type() {
+            CommandType::Address => {
+                let symbol = &line[1..];
+                let is_number = !symbol.is_empty() && symbol.chars().all(|c| c.is_ascii_digit());
+
+                if is_number || Parser::is_valid_symbol(symbol) {
+                    symbol.to_string()
+                } else {
+                    return Err(self.error(ErrorKind::MalformedLabel, 0, line));
+                }
+            }
+            CommandType::Jump => match line[1..].strip_suffix(')') {
+                Some(symbol) if Parser::is_valid_symbol(symbol) => symbol.to_string(),
+                _ => return Err(self.error(ErrorKind::MalformedLabel, 0, line)),
+            },
+            CommandType::Extern => match line.strip_prefix(".extern") {
+                Some(symbol) if Parser::is_valid_symbol(symbol) => symbol.to_string(),
+                _ => return Err(self.error(ErrorKind::MalformedLabel, 0, line)),
+            },
+            _ => line.to_string(),
+        };
+
+        Ok(self.spanned(symbol))
+    }
+
+    /// The value of an A-instruction, which may be a constant expression.
+    pub fn get_expression(&self) -> Result<Spanned<Expression>, AssembleError> {
+        let line = &self.line().text;
+
+        match Expression::parse(&line[1..]) {
+            Ok(expression) => Ok(self.spanned(expression)),
+            Err(error) => Err(self.error(error.kind, error.offset + 1, line)),
         }
     }
 
-    pub fn get_symbol(&self) -> String {
-        let line = &self.program[self.current_line];
-        match self.command_type() {
-            CommandType::Address => line[1..].to_string(),
-            CommandType::Jump => line[1..line.len() - 1].to_string(),
-            _ => line.clone(),
+    /// The name and value of a `.equ` or `.define` directive.
+    pub fn get_constant(&self) -> Result<Spanned<(String, Expression)>, AssembleError> {
+        let code = lexer::strip_comment(&self.span().raw);
+        let mut words = code.split_whitespace();
+        let directive = words.next().unwrap_or_default();
+        let name = match words.next() {
+            Some(name) if Parser::is_valid_symbol(name) => name,
+            _ => return Err(self.error(ErrorKind::MalformedConstant, 0, code.trim())),
+        };
+
+        // Offsets in errors count non-whitespace characters
+        let rest = &code[code.find(directive).unwrap_or(0) + directive.len()..];
+        let rest = &rest[rest.find(name).unwrap_or(0) + name.len()..];
+        let skipped = directive.len() + name.len();
+
+        match Expression::parse(rest) {
+            Ok(expression) => Ok(self.spanned((name.to_string(), expression))),
+            Err(error) => {
+                let offset = rest[..error.offset]
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .count();
+                Err(self.error(error.kind, skipped + offset, code.trim()))
+            }
         }
     }
 
-    pub fn parse_command(&self) -> Command {
-        let line = &self.program[self.current_line];
+    pub fn parse_command(&self) -> Spanned<Command> {
+        let line = &self.line().text;
 
         let mut dest = String::new();
         let comp;
@@ -128,7 +665,7 @@ impl Parser {
             }
         }
 
-        Command { dest, comp, jump }
+        self.spanned(Command { dest, comp, jump })
     }
 }
 
@@ -175,8 +712,57 @@ mod tests {
         let a_command = "@R0";
         let l_command = "(LOOP)";
 
-        assert_eq!(Parser::new(a_command).get_symbol(), "R0");
-        assert_eq!(Parser::new(l_command).get_symbol(), "LOOP");
+        assert_eq!(Parser::new(a_command).get_symbol().unwrap().node, "R0");
+        assert_eq!(Parser::new(l_command).get_symbol().unwrap().node, "LOOP");
+    }
+
+    #[test]
+    fn malformed_symbol() {
+        let program = "
+            (LOOP
+              @1st
+            ()
+        ";
+        let mut parser = Parser::new(program);
+
+        let error = parser.get_symbol().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MalformedLabel);
+        assert_eq!((error.line, error.column), (2, 13));
+        assert_eq!(error.text, "(LOOP");
+
+        parser.advance();
+        let error = parser.get_symbol().unwrap_err();
+        assert_eq!((error.line, error.column), (3, 15));
+
+        parser.advance();
+        assert!(parser.get_symbol().is_err());
+    }
+
+    #[test]
+    fn spans() {
+        let program = "// header
+
+              @R0 // load R0
+            D = M
+        ";
+        let mut parser = Parser::with_file("Test.asm", program);
+
+        let symbol = parser.get_symbol().unwrap();
+        assert_eq!(
+            symbol.span,
+            Span {
+                file: Rc::from("Test.asm"),
+                line: 3,
+                column: 15,
+                raw: SourceText::from("              @R0 // load R0"),
+                depth: 0,
+            }
+        );
+
+        parser.advance();
+        let command = parser.parse_command();
+        assert_eq!((command.span.line, command.span.column), (4, 13));
+        assert_eq!(command.span.raw, "            D = M");
     }
 
     #[test]
@@ -196,11 +782,11 @@ mod tests {
 
         parser.advance();
         assert_eq!(parser.get_current_line(), 2);
-        assert_ne!(parser.has_more_commands(), true);
+        assert!(!parser.has_more_commands());
 
         parser.advance();
         assert_eq!(parser.get_current_line(), 2);
-        assert_ne!(parser.has_more_commands(), true);
+        assert!(!parser.has_more_commands());
 
         parser.reset();
         assert_eq!(parser.get_current_line(), 0);
@@ -217,7 +803,7 @@ mod tests {
         let mut parser = Parser::new(program);
 
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().node,
             Command {
                 dest: String::from("D"),
                 comp: String::from("D-M"),
@@ -227,7 +813,7 @@ mod tests {
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().node,
             Command {
                 dest: String::from("M"),
                 comp: String::from("!A"),
@@ -237,7 +823,7 @@ mod tests {
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().node,
             Command {
                 dest: String::new(),
                 comp: String::from("0"),
@@ -245,4 +831,223 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn macros() {
+        let program = "
+            .macro PUSH value
+                @value
+                D=A
+                (LOOP)
+                @LOOP
+            .endm
+            .macro TWICE a, b
+                PUSH a
+                PUSH b
+            .endm
+            TWICE 1, R2 // two pushes
+        ";
+        let mut parser = Parser::new(program);
+        assert!(parser.errors().is_empty());
+
+        assert_eq!(
+            parser.get_program(),
+            vec![
+                "TWICE1,R2",
+                "PUSH1",
+                "@1",
+                "D=A",
+                "(_LOOP$PUSH.2)",
+                "@_LOOP$PUSH.2",
+                "PUSHR2",
+                "@R2",
+                "D=A",
+                "(_LOOP$PUSH.3)",
+                "@_LOOP$PUSH.3",
+            ]
+        );
+
+        assert_eq!(parser.command_type(), CommandType::Expansion);
+        assert_eq!(parser.span().depth, 0);
+
+        parser.advance();
+        assert_eq!(parser.command_type(), CommandType::Expansion);
+        assert_eq!((parser.span().line, parser.span().depth), (9, 1));
+
+        parser.advance();
+        assert_eq!(parser.command_type(), CommandType::Address);
+        assert_eq!(parser.span().line, 3);
+        assert_eq!(parser.span().raw, "                @1");
+        assert_eq!(parser.span().depth, 2);
+    }
+
+    #[test]
+    fn macro_errors() {
+        let program = "
+            .macro LOOP
+                LOOP
+            .endm
+            LOOP
+            .macro PUSH x
+            .endm
+            PUSH
+            .endm
+            .macro 1st
+            .endm
+            .macro OPEN
+        ";
+        let parser = Parser::new(program);
+        let errors: Vec<(usize, ErrorKind)> = parser
+            .errors()
+            .iter()
+            .map(|error| (error.line, error.kind))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (3, ErrorKind::MacroRecursion),
+                (8, ErrorKind::MacroArguments),
+                (9, ErrorKind::MalformedMacro),
+                (10, ErrorKind::MalformedMacro),
+                (12, ErrorKind::MalformedMacro),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_size() {
+        // Each macro calls the one before four times, 4^9 lines in all
+        let mut program = String::from(".macro M0\nD=0\n.endm\n");
+        for level in 1..10 {
+            let call = format!("M{}\n", level - 1);
+            program.push_str(&format!(".macro M{}\n{}.endm\n", level, call.repeat(4)));
+        }
+        program.push_str("M9\nM9\n");
+        let parser = Parser::new(&program);
+        let errors: Vec<(usize, ErrorKind, &str)> = parser
+            .errors()
+            .iter()
+            .map(|error| (error.line, error.kind, error.text.as_str()))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (58, ErrorKind::MacroSize, "M9"),
+                (59, ErrorKind::MacroSize, "M9"),
+            ]
+        );
+        assert!(parser.program.len() < 2 * MAX_EXPANDED_LINES);
+    }
+
+    #[test]
+    fn expressions() {
+        let program = "
+            @SCREEN + 32*10
+            @(ROWS
+            .equ ROWS 256 // rows on the screen
+            .define COLS ROWS /
+            .equ 2nd 1
+        ";
+        let mut parser = Parser::new(program);
+
+        let expression = parser.get_expression().unwrap().node;
+        assert_eq!(expression.to_string(), "(SCREEN+(32*10))");
+
+        parser.advance();
+        let error = parser.get_expression().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MalformedExpression);
+        assert_eq!((error.line, error.column), (3, 19));
+
+        parser.advance();
+        assert_eq!(parser.command_type(), CommandType::Constant);
+        let (name, value) = parser.get_constant().unwrap().node;
+        assert_eq!((name.as_str(), value), ("ROWS", Expression::Number(256)));
+
+        parser.advance();
+        let error = parser.get_constant().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MalformedExpression);
+        assert_eq!((error.line, error.column), (5, 32));
+        assert_eq!(error.text, ".define COLS ROWS /");
+
+        parser.advance();
+        let error = parser.get_constant().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MalformedConstant);
+        assert_eq!((error.line, error.column), (6, 13));
+    }
+
+    fn load(path: &Path) -> io::Result<String> {
+        let contents = match path.to_str() {
+            Some("lib/math.asm") => "@R0 // math\n.include \"util.asm\"",
+            Some("lib/util.asm") => "@R1",
+            Some("lib/loop.asm") => ".include \"../lib/again.asm\"",
+            Some("lib/again.asm") => ".include \"loop.asm\"",
+            Some("self.asm") => ".include \"self.asm\"",
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+        };
+        Ok(contents.to_string())
+    }
+
+    #[test]
+    fn includes() {
+        let main = "
+            .include \"lib/math.asm\"
+            @R2
+        ";
+        let mut parser = Parser::with_files(&[("main.asm", main), ("other.asm", "@R3")], &load);
+        assert!(parser.errors().is_empty());
+        assert_eq!(
+            parser.get_program(),
+            vec![
+                ".include\"lib/math.asm\"",
+                "@R0",
+                ".include\"util.asm\"",
+                "@R1",
+                "@R2",
+                "@R3"
+            ]
+        );
+
+        let mut locations = Vec::new();
+        while parser.has_more_commands() {
+            locations.push((parser.span().file.to_string(), parser.span().line));
+            parser.advance();
+        }
+        assert_eq!(
+            locations,
+            vec![
+                (String::from("main.asm"), 2),
+                (String::from("lib/math.asm"), 1),
+                (String::from("lib/math.asm"), 2),
+                (String::from("lib/util.asm"), 1),
+                (String::from("main.asm"), 3),
+                (String::from("other.asm"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn include_errors() {
+        let main = "
+            .include \"lib/loop.asm\"
+            .include \"self.asm\"
+            .include \"missing.asm\"
+            .include lib/math.asm
+        ";
+        let parser = Parser::with_files(&[("main.asm", main)], &load);
+        let errors: Vec<String> = parser.errors().iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "lib/again.asm:1:1: error: include cycle `lib/loop.asm` \
+                 (the cycle starts at lib/loop.asm:1)",
+                "self.asm:1:1: error: include cycle `self.asm` \
+                 (a file cannot include itself)",
+                "main.asm:4:13: error: cannot read included file `missing.asm` (not found)",
+                "main.asm:5:13: error: malformed include `.include lib/math.asm`",
+            ]
+        );
+    }
 }
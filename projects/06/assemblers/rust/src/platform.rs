@@ -0,0 +1,231 @@
+//! Platform profiles describe a Hack variant: its ROM and RAM sizes, the
+//! RAM variables are allocated from and its predefined symbols. Besides the
+//! built-in profiles, a profile can be read from a text file with one
+//! record per line:
+//!
+//! ```text
+//! // The course platform with a serial port after the keyboard
+//! extends hack
+//! name hack-serial
+//! ram 24578
+//! symbol SERIAL 24577
+//! ```
+//!
+//! `extends` starts from a built-in profile and must come first. `rom` and
+//! `ram` are sizes in words, `variables FIRST END` allocates variables from
+//! `FIRST` up to but not including `END`, and `symbol NAME ADDRESS` adds a
+//! predefined symbol or moves an inherited one.
+
+use crate::error::FormatError;
+use crate::parser::Parser;
+use std::ops::Range;
+
+// A-instructions carry a 15 bit address
+const ADDRESS_SPACE: usize = 0x8000;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Platform {
+    pub name: String,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub variables: Range<u16>,
+    pub symbols: Vec<(String, u16)>,
+}
+
+impl Platform {
+    /// The computer of the course: 32K words of ROM, and 16K words of RAM
+    /// followed by the screen and the keyboard.
+    pub fn hack() -> Platform {
+        let mut symbols: Vec<(String, u16)> = (0..16)
+            .map(|register| (format!("R{}", register), register))
+            .collect();
+        for (symbol, address) in [
+            ("SCREEN", 16384),
+            ("KBD", 24576),
+            ("SP", 0),
+            ("LCL", 1),
+            ("ARG", 2),
+            ("THIS", 3),
+            ("THAT", 4),
+        ] {
+            symbols.push((String::from(symbol), address));
+        }
+
+        Platform {
+            name: String::from("hack"),
+            rom_size: ADDRESS_SPACE,
+            ram_size: 24577,
+            variables: 16..16384,
+            symbols,
+        }
+    }
+
+    /// Looks up a built-in profile: `hack`, or `hack32k` for the course
+    /// computer with RAM behind every address above the keyboard too.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "hack" => Some(Platform::hack()),
+            "hack32k" => Some(Platform {
+                name: String::from("hack32k"),
+                ram_size: ADDRESS_SPACE,
+                ..Platform::hack()
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn read(text: &str) -> Result<Platform, FormatError> {
+        let mut platform = Platform {
+            name: String::new(),
+            rom_size: 0,
+            ram_size: 0,
+            variables: 0..0,
+            symbols: Vec::new(),
+        };
+        let mut records = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| FormatError::new(index + 1, message);
+            let number = |field: &str| -> Result<usize, FormatError> {
+                field
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&number| number <= ADDRESS_SPACE)
+                    .ok_or_else(|| error(&format!("invalid size or address `{}`", field)))
+            };
+            let code = line.split("//").next().unwrap_or_default();
+            let fields: Vec<&str> = code.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            records += 1;
+
+            match fields.as_slice() {
+                ["extends", name] if records == 1 => {
+                    platform = Platform::from_name(name)
+                        .ok_or_else(|| error(&format!("unknown platform `{}`", name)))?;
+                }
+                ["name", name] => platform.name = name.to_string(),
+                ["rom", size] => platform.rom_size = number(size)?,
+                ["ram", size] => platform.ram_size = number(size)?,
+                ["variables", first, end] => {
+                    platform.variables = number(first)? as u16..number(end)? as u16;
+                }
+                ["symbol", name, address] => {
+                    if !Parser::is_valid_symbol(name) {
+                        return Err(error(&format!("invalid symbol `{}`", name)));
+                    }
+                    let address = number(address)? as u16;
+                    platform.symbols.retain(|(symbol, _)| symbol != name);
+                    platform.symbols.push((name.to_string(), address));
+                }
+                _ => return Err(error(&format!("unknown record `{}`", code.trim()))),
+            }
+        }
+
+        platform
+            .validate()
+            .map_err(|message| FormatError::new(0, &message))?;
+        Ok(platform)
+    }
+
+    /// Checks that the sizes are usable and every address fits in RAM.
+    fn validate(&self) -> Result<(), String> {
+        if self.rom_size == 0 || self.ram_size == 0 {
+            return Err(String::from("ROM and RAM sizes must be given"));
+        }
+        if self.variables.start > self.variables.end
+            || usize::from(self.variables.end) > self.ram_size
+        {
+            return Err(format!(
+                "variables {} to {} are not in RAM",
+                self.variables.start, self.variables.end
+            ));
+        }
+        match self
+            .symbols
+            .iter()
+            .find(|(_, address)| usize::from(*address) >= self.ram_size)
+        {
+            Some((symbol, address)) => {
+                Err(format!("symbol `{}` at {} is not in RAM", symbol, address))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Platform {
+        Platform::hack()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_ins() {
+        for name in ["hack", "hack32k"] {
+            let platform = Platform::from_name(name).unwrap();
+            assert_eq!(platform.name, name);
+            assert_eq!(platform.validate(), Ok(()));
+        }
+
+        let hack = Platform::hack();
+        assert_eq!(hack.symbols.len(), 23);
+        assert!(hack.symbols.contains(&(String::from("KBD"), 24576)));
+        assert_eq!(Platform::from_name("z80"), None);
+    }
+
+    #[test]
+    fn read() {
+        let platform = Platform::read(
+            "
+            // The course platform with a serial port after the keyboard
+            extends hack
+            name hack-serial
+            ram 24578 // one word more
+            variables 16 1024
+            symbol SERIAL 24577
+            symbol SCREEN 8192
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(platform.name, "hack-serial");
+        assert_eq!(platform.ram_size, 24578);
+        assert_eq!(platform.variables, 16..1024);
+        assert_eq!(platform.symbols.len(), 24);
+        assert!(platform.symbols.contains(&(String::from("SCREEN"), 8192)));
+        assert!(platform.symbols.contains(&(String::from("SERIAL"), 24577)));
+    }
+
+    #[test]
+    fn read_errors() {
+        let error = |text: &str| Platform::read(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("name tiny\nextends hack"),
+            "2: error: unknown record `extends hack`"
+        );
+        assert_eq!(
+            error("extends hack\nram 40000"),
+            "2: error: invalid size or address `40000`"
+        );
+        assert_eq!(
+            error("extends hack\nsymbol 2X 4"),
+            "2: error: invalid symbol `2X`"
+        );
+        assert_eq!(error("name tiny"), "error: ROM and RAM sizes must be given");
+        assert_eq!(
+            error("rom 1024\nram 512\nvariables 16 600"),
+            "error: variables 16 to 600 are not in RAM"
+        );
+        assert_eq!(
+            error("extends hack\nram 16384"),
+            "error: symbol `SCREEN` at 16384 is not in RAM"
+        );
+    }
+}
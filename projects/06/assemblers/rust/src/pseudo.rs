@@ -0,0 +1,218 @@
+//! Convenience forms that lower to plain Hack instructions before code
+//! generation:
+//!
+//! - `D=42`, `A=-7` and `AD=100` load a constant into A and/or D.
+//! - `@-1` loads a negative constant into A.
+//! - `goto LABEL` jumps unconditionally.
+//! - `if D>0 goto LABEL` jumps when a computation compares to 0, with any of
+//!   `>`, `>=`, `<`, `<=`, `==` or `!=`.
+//! - `M[addr]` names RAM at `addr` inside a C-instruction, e.g. `M[i]=D` or
+//!   `D=D+M[sum]`.
+
+use crate::error::ErrorKind;
+use crate::instruction::{Comp, Dest};
+use std::borrow::Cow;
+
+const CONDITIONS: [(&str, &str); 6] = [
+    (">=", "JGE"),
+    ("<=", "JLE"),
+    ("==", "JEQ"),
+    ("!=", "JNE"),
+    (">", "JGT"),
+    ("<", "JLT"),
+];
+
+/// Lowers `code`, a line with its comment removed, if it is a
+/// pseudo-instruction. Returns `None` for anything else, including the real
+/// instructions the pseudo forms resemble, such as `D=1` or `@1`.
+pub fn lower(code: &str) -> Option<Result<Vec<String>, ErrorKind>> {
+    let code = code.trim();
+    let mut words = code.split_whitespace();
+
+    match words.next() {
+        Some("goto") => return Some(lower_goto(words.collect::<Vec<_>>().as_slice())),
+        Some("if") => return Some(lower_if(&code[2..])),
+        _ => {}
+    }
+
+    // Most lines have no whitespace inside them, so avoid copying those
+    let text: Cow<str> = if code.contains(char::is_whitespace) {
+        Cow::Owned(code.split_whitespace().collect())
+    } else {
+        Cow::Borrowed(code)
+    };
+    if let Some(constant) = text.strip_prefix("@-") {
+        return if is_number(constant) {
+            Some(Ok(vec![format!("@{}", constant), String::from("A=-A")]))
+        } else {
+            None
+        };
+    }
+    if text.contains("M[") {
+        return Some(lower_memory(&text));
+    }
+
+    lower_constant(&text)
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
+
+fn lower_goto(words: &[&str]) -> Result<Vec<String>, ErrorKind> {
+    match words {
+        [label] => Ok(vec![format!("@{}", label), String::from("0;JMP")]),
+        _ => Err(ErrorKind::MalformedPseudo),
+    }
+}
+
+/// `if COMP OP 0 goto LABEL`, where the computation may only use D and
+/// constants, since A is loaded with the label.
+fn lower_if(rest: &str) -> Result<Vec<String>, ErrorKind> {
+    let (condition, label) = match rest.find(" goto ") {
+        Some(index) => (&rest[..index], rest[index + 6..].trim()),
+        None => return Err(ErrorKind::MalformedPseudo),
+    };
+    let condition: String = condition.split_whitespace().collect();
+    if label.is_empty() || label.contains(char::is_whitespace) {
+        return Err(ErrorKind::MalformedPseudo);
+    }
+
+    for (operator, jump) in CONDITIONS.iter() {
+        if let Some(index) = condition.find(operator) {
+            let comp = &condition[..index];
+            if &condition[index + operator.len()..] != "0" {
+                return Err(ErrorKind::MalformedPseudo);
+            }
+
+            return match Comp::parse(comp) {
+                Some(parsed) if !comp.contains('A') && !parsed.reads_memory() => {
+                    Ok(vec![format!("@{}", label), format!("{};{}", comp, jump)])
+                }
+                _ => Err(ErrorKind::MalformedPseudo),
+            };
+        }
+    }
+
+    Err(ErrorKind::MalformedPseudo)
+}
+
+/// Replaces every `M[addr]` with `M` after loading `addr` into A. All of them
+/// must name the same address, and nothing else may read A.
+fn lower_memory(text: &str) -> Result<Vec<String>, ErrorKind> {
+    let mut address: Option<&str> = None;
+    let mut instruction = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("M[") {
+        let end = match rest[start..].find(']') {
+            Some(end) => start + end,
+            None => return Err(ErrorKind::MalformedPseudo),
+        };
+        let inner = &rest[start + 2..end];
+        if inner.is_empty() || address.is_some_and(|address| address != inner) {
+            return Err(ErrorKind::MalformedPseudo);
+        }
+
+        address = Some(inner);
+        instruction.push_str(&rest[..start]);
+        instruction.push('M');
+        rest = &rest[end + 1..];
+    }
+    instruction.push_str(rest);
+
+    let comp = instruction.split(';').next().unwrap_or_default();
+    let comp = comp.rsplit('=').next().unwrap_or_default();
+    if comp.contains('A') || instruction.contains(['[', ']']) {
+        return Err(ErrorKind::MalformedPseudo);
+    }
+
+    Ok(vec![
+        format!("@{}", address.unwrap_or_default()),
+        instruction,
+    ])
+}
+
+/// `DEST=N` for constants other than the ALU's own 0, 1 and -1. Only A and D
+/// can be loaded, as writing M would need A to hold the address.
+fn lower_constant(text: &str) -> Option<Result<Vec<String>, ErrorKind>> {
+    let (dest, value) = text.split_at(text.find('=')?);
+    let value = &value[1..];
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    if !is_number(digits) || Comp::parse(value).is_some() {
+        return None;
+    }
+
+    let dest_bits = match Dest::parse(dest) {
+        Some(dest) if !dest.is_empty() => dest,
+        _ => return Some(Err(ErrorKind::BadDest)),
+    };
+    if dest_bits.contains(Dest::M) {
+        return Some(Err(ErrorKind::MalformedPseudo));
+    }
+
+    let mut code = vec![format!("@{}", digits)];
+    match (dest, negative) {
+        ("A", false) => {}
+        (_, false) => code.push(format!("{}=A", dest)),
+        (_, true) => code.push(format!("{}=-A", dest)),
+    }
+    Some(Ok(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(lines: &[&str]) -> Option<Result<Vec<String>, ErrorKind>> {
+        Some(Ok(lines.iter().map(|line| line.to_string()).collect()))
+    }
+
+    #[test]
+    fn real_instructions() {
+        for code in &["@1", "@LOOP", "D=1", "AM=-1", "D=M", "0;JMP", "(END)"] {
+            assert_eq!(lower(code), None);
+        }
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(lower("D=42"), ok(&["@42", "D=A"]));
+        assert_eq!(lower(" AD = 100 "), ok(&["@100", "AD=A"]));
+        assert_eq!(lower("A=7"), ok(&["@7"]));
+        assert_eq!(lower("D=-5"), ok(&["@5", "D=-A"]));
+        assert_eq!(lower("@-1"), ok(&["@1", "A=-A"]));
+        assert_eq!(lower("M=42"), Some(Err(ErrorKind::MalformedPseudo)));
+        assert_eq!(lower("X=42"), Some(Err(ErrorKind::BadDest)));
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(lower("goto END"), ok(&["@END", "0;JMP"]));
+        assert_eq!(lower("if D>0 goto LOOP"), ok(&["@LOOP", "D;JGT"]));
+        assert_eq!(lower("if D-1 != 0 goto LOOP"), ok(&["@LOOP", "D-1;JNE"]));
+        assert_eq!(lower("if D<=0 goto LOOP"), ok(&["@LOOP", "D;JLE"]));
+        assert_eq!(lower("goto"), Some(Err(ErrorKind::MalformedPseudo)));
+        assert_eq!(
+            lower("if M>0 goto LOOP"),
+            Some(Err(ErrorKind::MalformedPseudo))
+        );
+        assert_eq!(
+            lower("if D>1 goto LOOP"),
+            Some(Err(ErrorKind::MalformedPseudo))
+        );
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(lower("M[i]=D"), ok(&["@i", "M=D"]));
+        assert_eq!(lower("D = D+M[sum]"), ok(&["@sum", "D=D+M"]));
+        assert_eq!(lower("M[i]=M[i]+1"), ok(&["@i", "M=M+1"]));
+        assert_eq!(lower("M[i]=M[j]"), Some(Err(ErrorKind::MalformedPseudo)));
+        assert_eq!(lower("M[i]=A"), Some(Err(ErrorKind::MalformedPseudo)));
+        assert_eq!(lower("M[i=D"), Some(Err(ErrorKind::MalformedPseudo)));
+    }
+}
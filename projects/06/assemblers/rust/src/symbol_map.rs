@@ -0,0 +1,379 @@
+//! Symbol maps list the symbols of an assembled program with their
+//! addresses, so emulators, disassemblers and test harnesses can show names
+//! instead of numbers. A map is written as JSON or as text with one record
+//! per line:
+//!
+//! ```text
+//! hack-symbols 1
+//! platform hack
+//! predefined R0 0
+//! label LOOP 2
+//! label _loop 5 lib/util.asm
+//! constant ROWS 256
+//! variable i 16
+//! ```
+//!
+//! Predefined symbols come from the platform and variables are allocated by
+//! the assembler. Labels and constants are defined by the program; labels
+//! are ROM addresses, predefined symbols and variables RAM addresses.
+//! Symbols private to a file end with the file's name, which takes the rest
+//! of the line and so may contain spaces.
+
+use crate::assembler::{scoped_name, Assembly};
+use crate::error::FormatError;
+use crate::json::Json;
+use crate::parser::Parser;
+use crate::platform::Platform;
+
+const HEADER: &str = "hack-symbols 1";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolKind {
+    Predefined,
+    Label,
+    Constant,
+    Variable,
+}
+
+impl SymbolKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label => "label",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Variable => "variable",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<SymbolKind> {
+        [
+            SymbolKind::Predefined,
+            SymbolKind::Label,
+            SymbolKind::Constant,
+            SymbolKind::Variable,
+        ]
+        .iter()
+        .copied()
+        .find(|kind| kind.name() == name)
+    }
+
+    /// Who gave the symbol its address.
+    pub fn origin(self) -> &'static str {
+        match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label | SymbolKind::Constant => "user-defined",
+            SymbolKind::Variable => "auto-allocated",
+        }
+    }
+
+    /// The memory the address is in, or `None` for a constant.
+    pub fn memory(self) -> Option<&'static str> {
+        match self {
+            SymbolKind::Label => Some("rom"),
+            SymbolKind::Predefined | SymbolKind::Variable => Some("ram"),
+            SymbolKind::Constant => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub address: u16,
+    pub kind: SymbolKind,
+    /// The file a private symbol, one starting with `_`, belongs to.
+    pub file: Option<String>,
+}
+
+impl SymbolEntry {
+    /// The name the symbol has in the assembler's symbol table.
+    pub fn scoped_name(&self) -> String {
+        match &self.file {
+            Some(file) => scoped_name(file, &self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SymbolMap {
+    pub platform: String,
+    pub symbols: Vec<SymbolEntry>,
+}
+
+impl SymbolMap {
+    /// The symbols of `assembly`: predefined ones, then labels, constants
+    /// and variables, each ordered as `Symbols` lists them.
+    pub fn new(platform: &Platform, assembly: &Assembly) -> SymbolMap {
+        let symbols = &assembly.symbols;
+        let files = assembly.files(&[]);
+        // Private symbols are qualified as `file::_name` in the symbol table
+        let entry = |name: &str, address, kind| {
+            let file = files
+                .iter()
+                .filter(|file| {
+                    name.strip_prefix(*file)
+                        .is_some_and(|rest| rest.starts_with("::_"))
+                })
+                .max_by_key(|file| file.len());
+            SymbolEntry {
+                name: match file {
+                    Some(file) => String::from(&name[file.len() + 2..]),
+                    None => String::from(name),
+                },
+                address,
+                kind,
+                file: file.map(|file| file.to_string()),
+            }
+        };
+        let mut map = SymbolMap {
+            platform: platform.name.clone(),
+            symbols: Vec::new(),
+        };
+
+        for (kind, entries) in [
+            (SymbolKind::Predefined, symbols.predefined()),
+            (SymbolKind::Label, symbols.labels()),
+            (SymbolKind::Constant, symbols.constants()),
+            (SymbolKind::Variable, symbols.variables()),
+        ] {
+            map.symbols.extend(
+                entries
+                    .into_iter()
+                    .map(|(name, address)| entry(name, address, kind)),
+            );
+        }
+
+        map
+    }
+
+    /// The name at `address` in ROM, for labelling jump targets.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|entry| entry.kind == SymbolKind::Label && entry.address == address)
+            .map(|entry| entry.name.as_str())
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = format!("{}\nplatform {}\n", HEADER, self.platform);
+        for entry in &self.symbols {
+            text.push_str(&format!(
+                "{} {} {}",
+                entry.kind.name(),
+                entry.name,
+                entry.address
+            ));
+            if let Some(file) = &entry.file {
+                text.push_str(&format!(" {}", file));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn write_json(&self) -> String {
+        let mut json = format!(
+            "{{\n  \"platform\": {},\n  \"symbols\": [",
+            Json::from(self.platform.as_str())
+        );
+        for (index, entry) in self.symbols.iter().enumerate() {
+            let memory = match entry.kind.memory() {
+                Some(memory) => Json::from(memory).to_string(),
+                None => String::from("null"),
+            };
+            json.push_str(if index == 0 { "\n" } else { ",\n" });
+            let file = match &entry.file {
+                Some(file) => format!(", \"file\": {}", Json::from(file.as_str())),
+                None => String::new(),
+            };
+            json.push_str(&format!(
+                "    {{\"name\": {}, \"address\": {}, \"kind\": \"{}\", \
+                 \"origin\": \"{}\", \"memory\": {}{}}}",
+                Json::from(entry.name.as_str()),
+                entry.address,
+                entry.kind.name(),
+                entry.kind.origin(),
+                memory,
+                file
+            ));
+        }
+        if !self.symbols.is_empty() {
+            json.push_str("\n  ");
+        }
+        json.push_str("]\n}\n");
+        json
+    }
+
+    pub fn read(text: &str) -> Result<SymbolMap, FormatError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(FormatError::new(1, "not a symbol map")),
+        }
+
+        let mut map = SymbolMap::default();
+        for (number, line) in lines {
+            let error = |message: String| FormatError::new(number, &message);
+            let (kind, rest) = next_field(line);
+            let (name, rest) = next_field(rest);
+            let (address, rest) = next_field(rest);
+            let file = rest.trim_end();
+            match (kind, name, address) {
+                ("", _, _) => {}
+                ("platform", name, "") => map.platform = name.to_string(),
+                (kind, name, address) if !address.is_empty() => {
+                    let kind = SymbolKind::from_name(kind)
+                        .ok_or_else(|| error(format!("unknown record `{}`", line.trim())))?;
+                    if !Parser::is_valid_symbol(name) {
+                        return Err(error(format!("invalid symbol `{}`", name)));
+                    }
+                    if !file.is_empty() && !name.starts_with('_') {
+                        return Err(error(format!("symbol `{}` is not private", name)));
+                    }
+                    let address = address
+                        .parse()
+                        .map_err(|_| error(format!("invalid address `{}`", address)))?;
+                    map.symbols.push(SymbolEntry {
+                        name: name.to_string(),
+                        address,
+                        kind,
+                        file: Some(file.to_string()).filter(|file| !file.is_empty()),
+                    });
+                }
+                _ => return Err(error(format!("unknown record `{}`", line.trim()))),
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// Splits the first whitespace separated field off `text`.
+fn next_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (&text[..end], text[end..].trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    fn symbol_map() -> SymbolMap {
+        let program = "
+            .equ ROWS 256
+            @i
+            M=1
+        (LOOP)
+            @ROWS
+            D=A
+            @LOOP
+            0;JMP
+        "
+        .to_string();
+        let assembly = Assembler::build("", program).unwrap();
+        SymbolMap::new(&Platform::hack(), &assembly)
+    }
+
+    #[test]
+    fn from_assembly() {
+        let map = symbol_map();
+        let entry = |name: &str| map.symbols.iter().find(|entry| entry.name == name);
+
+        assert_eq!(map.platform, "hack");
+        assert_eq!(map.symbols.len(), 26);
+        assert_eq!(
+            map.symbols[0],
+            SymbolEntry {
+                name: String::from("R0"),
+                address: 0,
+                kind: SymbolKind::Predefined,
+                file: None,
+            }
+        );
+        assert_eq!(entry("KBD").unwrap().address, 24576);
+        assert_eq!(entry("LOOP").unwrap().kind, SymbolKind::Label);
+        assert_eq!(entry("ROWS").unwrap().address, 256);
+        assert_eq!(entry("i").unwrap().kind.origin(), "auto-allocated");
+        assert_eq!(map.label(2), Some("LOOP"));
+        assert_eq!(map.label(16), None);
+    }
+
+    #[test]
+    fn write_and_read() {
+        let map = symbol_map();
+        let text = map.write();
+
+        assert!(text.starts_with("hack-symbols 1\nplatform hack\npredefined R0 0\n"));
+        assert!(text.ends_with("label LOOP 2\nconstant ROWS 256\nvariable i 16\n"));
+        assert_eq!(SymbolMap::read(&text), Ok(map));
+
+        assert_eq!(
+            SymbolMap::read("hack-object 1").unwrap_err().to_string(),
+            "1: error: not a symbol map"
+        );
+        assert_eq!(
+            SymbolMap::read("hack-symbols 1\nlabel 2X 2")
+                .unwrap_err()
+                .to_string(),
+            "2: error: invalid symbol `2X`"
+        );
+        assert_eq!(
+            SymbolMap::read("hack-symbols 1\nalias LOOP 2")
+                .unwrap_err()
+                .to_string(),
+            "2: error: unknown record `alias LOOP 2`"
+        );
+    }
+
+    #[test]
+    fn private_symbols() {
+        let main = "@_loop\n(_loop)\n.include \"lib/my util.asm\"";
+        let util = "(_loop)\n@_loop\n0;JMP";
+        let assembly =
+            Assembler::build_files(&[("Main.asm", main)], &|_| Ok(util.to_string())).unwrap();
+        let map = SymbolMap::new(&Platform::hack(), &assembly);
+        let text = map.write();
+
+        assert!(text.ends_with("label _loop 1 Main.asm\nlabel _loop 1 lib/my util.asm\n"));
+        assert_eq!(SymbolMap::read(&text), Ok(map.clone()));
+        assert_eq!(
+            map.symbols.last().unwrap().scoped_name(),
+            "lib/my util.asm::_loop"
+        );
+        assert_eq!(
+            SymbolMap::read("hack-symbols 1\nlabel LOOP 2 Main.asm")
+                .unwrap_err()
+                .to_string(),
+            "2: error: symbol `LOOP` is not private"
+        );
+    }
+
+    #[test]
+    fn write_json() {
+        let json = symbol_map().write_json();
+
+        assert!(json.starts_with(
+            "{\n  \"platform\": \"hack\",\n  \"symbols\": [\n    \
+             {\"name\": \"R0\", \"address\": 0, \"kind\": \"predefined\", \
+             \"origin\": \"predefined\", \"memory\": \"ram\"},\n"
+        ));
+        assert!(json.ends_with(
+            "{\"name\": \"LOOP\", \"address\": 2, \"kind\": \"label\", \
+             \"origin\": \"user-defined\", \"memory\": \"rom\"},\n    \
+             {\"name\": \"ROWS\", \"address\": 256, \"kind\": \"constant\", \
+             \"origin\": \"user-defined\", \"memory\": null},\n    \
+             {\"name\": \"i\", \"address\": 16, \"kind\": \"variable\", \
+             \"origin\": \"auto-allocated\", \"memory\": \"ram\"}\n  ]\n}\n"
+        ));
+        assert_eq!(
+            SymbolMap::default().write_json(),
+            "{\n  \"platform\": \"\",\n  \"symbols\": []\n}\n"
+        );
+    }
+}
@@ -1,62 +1,120 @@
-use std::collections::HashMap;
+use crate::platform::Platform;
+use std::collections::{HashMap, HashSet};
 
 pub struct Symbols {
     next_free_address: u16,
+    variables_end: u16,
     table: HashMap<String, u16>,
+    predefined: HashSet<String>,
+    labels: Vec<String>,
+    constants: Vec<String>,
+    variables: Vec<String>,
 }
 
 impl Symbols {
     pub fn new() -> Symbols {
-        let mut table = HashMap::new();
-
-        table.insert(String::from("R0"), 0);
-        table.insert(String::from("R1"), 1);
-        table.insert(String::from("R2"), 2);
-        table.insert(String::from("R3"), 3);
-        table.insert(String::from("R4"), 4);
-        table.insert(String::from("R5"), 5);
-        table.insert(String::from("R6"), 6);
-        table.insert(String::from("R7"), 7);
-        table.insert(String::from("R8"), 8);
-        table.insert(String::from("R9"), 9);
-        table.insert(String::from("R10"), 10);
-        table.insert(String::from("R11"), 11);
-        table.insert(String::from("R12"), 12);
-        table.insert(String::from("R13"), 13);
-        table.insert(String::from("R14"), 14);
-        table.insert(String::from("R15"), 15);
-        table.insert(String::from("SCREEN"), 16384);
-        table.insert(String::from("KBD"), 24576);
-        table.insert(String::from("SP"), 0);
-        table.insert(String::from("LCL"), 1);
-        table.insert(String::from("ARG"), 2);
-        table.insert(String::from("THIS"), 3);
-        table.insert(String::from("THAT"), 4);
+        Symbols::with_platform(&Platform::default())
+    }
 
+    /// Starts from the platform's predefined symbols, allocating variables
+    /// from its variable range.
+    pub fn with_platform(platform: &Platform) -> Symbols {
         Symbols {
-            next_free_address: 15,
-            table,
+            next_free_address: platform.variables.start,
+            variables_end: platform.variables.end,
+            table: platform.symbols.iter().cloned().collect(),
+            predefined: platform
+                .symbols
+                .iter()
+                .map(|(symbol, _)| symbol.clone())
+                .collect(),
+            labels: Vec::new(),
+            constants: Vec::new(),
+            variables: Vec::new(),
         }
     }
 
-    pub fn get_address(&mut self, symbol: String) -> u16 {
+    /// The address of `symbol`, allocating a variable for it if it is not
+    /// defined. Returns `None` when the platform has no RAM left for it.
+    pub fn get_address(&mut self, symbol: String) -> Option<u16> {
         match self.table.get(&symbol) {
-            Some(&address) => address,
-            None => {
-                let address = self.add_variable(symbol);
-                address
-            }
+            Some(&address) => Some(address),
+            None => self.add_variable(symbol),
         }
     }
 
-    fn add_variable(&mut self, symbol: String) -> u16 {
+    /// Whether `symbol` is one of the platform's predefined symbols, such as
+    /// `R0` or `SCREEN`.
+    pub fn is_predefined(&self, symbol: &str) -> bool {
+        self.predefined.contains(symbol)
+    }
+
+    /// Looks a symbol up without allocating a variable for it.
+    pub fn get(&self, symbol: &str) -> Option<u16> {
+        self.table.get(symbol).copied()
+    }
+
+    fn add_variable(&mut self, symbol: String) -> Option<u16> {
+        if self.next_free_address >= self.variables_end {
+            return None;
+        }
+
+        let address = self.next_free_address;
         self.next_free_address += 1;
-        self.table.insert(symbol, self.next_free_address);
-        self.next_free_address
+        self.table.insert(symbol.clone(), address);
+        self.variables.push(symbol);
+        Some(address)
     }
 
     pub fn add_jump_symbol(&mut self, symbol: String, address: u16) {
-        self.table.insert(symbol, address);
+        self.table.insert(symbol.clone(), address);
+        self.labels.push(symbol);
+    }
+
+    pub fn add_constant(&mut self, symbol: String, value: u16) {
+        self.table.insert(symbol.clone(), value);
+        self.constants.push(symbol);
+    }
+
+    /// User defined labels ordered by ROM address.
+    pub fn labels(&self) -> Vec<(&str, u16)> {
+        let mut labels = self.entries(&self.labels);
+        labels.sort_by_key(|&(_, address)| address);
+        labels
+    }
+
+    /// The platform's predefined symbols ordered by address, then name.
+    pub fn predefined(&self) -> Vec<(&str, u16)> {
+        let mut predefined: Vec<(&str, u16)> = self
+            .predefined
+            .iter()
+            .map(|symbol| (symbol.as_str(), self.table[symbol]))
+            .collect();
+        predefined.sort_by_key(|&(symbol, address)| (address, symbol));
+        predefined
+    }
+
+    /// Constants in order of definition.
+    pub fn constants(&self) -> Vec<(&str, u16)> {
+        self.entries(&self.constants)
+    }
+
+    /// Allocated variables ordered by RAM address.
+    pub fn variables(&self) -> Vec<(&str, u16)> {
+        self.entries(&self.variables)
+    }
+
+    fn entries<'a>(&'a self, symbols: &'a [String]) -> Vec<(&'a str, u16)> {
+        symbols
+            .iter()
+            .map(|symbol| (symbol.as_str(), self.table[symbol]))
+            .collect()
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Symbols {
+        Symbols::new()
     }
 }
 
@@ -67,30 +125,73 @@ mod tests {
     #[test]
     fn get_address() {
         let mut symbols = Symbols::new();
-        assert_eq!(symbols.get_address(String::from("R0")), 0);
-        assert_eq!(symbols.get_address(String::from("R8")), 8);
-        assert_eq!(symbols.get_address(String::from("R15")), 15);
-        assert_eq!(symbols.get_address(String::from("SCREEN")), 16384);
-        assert_eq!(symbols.get_address(String::from("KBD")), 24576);
-        assert_eq!(symbols.get_address(String::from("SP")), 0);
-        assert_eq!(symbols.get_address(String::from("LCL")), 1);
-        assert_eq!(symbols.get_address(String::from("ARG")), 2);
-        assert_eq!(symbols.get_address(String::from("THIS")), 3);
-        assert_eq!(symbols.get_address(String::from("THAT")), 4);
+        assert_eq!(symbols.get_address(String::from("R0")), Some(0));
+        assert_eq!(symbols.get_address(String::from("R8")), Some(8));
+        assert_eq!(symbols.get_address(String::from("R15")), Some(15));
+        assert_eq!(symbols.get_address(String::from("SCREEN")), Some(16384));
+        assert_eq!(symbols.get_address(String::from("KBD")), Some(24576));
+        assert_eq!(symbols.get_address(String::from("SP")), Some(0));
+        assert_eq!(symbols.get_address(String::from("LCL")), Some(1));
+        assert_eq!(symbols.get_address(String::from("ARG")), Some(2));
+        assert_eq!(symbols.get_address(String::from("THIS")), Some(3));
+        assert_eq!(symbols.get_address(String::from("THAT")), Some(4));
     }
 
     #[test]
     fn new_symbols() {
         let mut symbols = Symbols::new();
-        assert_eq!(symbols.get_address(String::from("FOO")), 16);
-        assert_eq!(symbols.get_address(String::from("BAR")), 17);
-        assert_eq!(symbols.get_address(String::from("BAZ")), 18);
+        assert_eq!(symbols.get_address(String::from("FOO")), Some(16));
+        assert_eq!(symbols.get_address(String::from("BAR")), Some(17));
+        assert_eq!(symbols.get_address(String::from("BAZ")), Some(18));
+    }
+
+    #[test]
+    fn platform_variables() {
+        let platform = Platform {
+            variables: 100..102,
+            ..Platform::hack()
+        };
+        let mut symbols = Symbols::with_platform(&platform);
+        assert_eq!(symbols.get_address(String::from("FOO")), Some(100));
+        assert_eq!(symbols.get_address(String::from("BAR")), Some(101));
+        assert_eq!(symbols.get_address(String::from("BAZ")), None);
+        assert_eq!(symbols.get_address(String::from("FOO")), Some(100));
+        assert!(symbols.is_predefined("SCREEN"));
+        assert_eq!(
+            symbols.predefined()[..3],
+            [("R0", 0), ("SP", 0), ("LCL", 1)]
+        );
+        assert!(!symbols.is_predefined("FOO"));
+    }
+
+    #[test]
+    fn labels_and_variables() {
+        let mut symbols = Symbols::new();
+        symbols.add_jump_symbol(String::from("END"), 12);
+        symbols.add_jump_symbol(String::from("LOOP"), 3);
+        symbols.get_address(String::from("i"));
+        symbols.get_address(String::from("sum"));
+        symbols.get_address(String::from("LOOP"));
+        symbols.get_address(String::from("SCREEN"));
+
+        assert_eq!(symbols.labels(), vec![("LOOP", 3), ("END", 12)]);
+        assert_eq!(symbols.variables(), vec![("i", 16), ("sum", 17)]);
     }
 
     #[test]
     fn add_jump_symbol() {
         let mut symbols = Symbols::new();
         symbols.add_jump_symbol(String::from("FOO"), 42);
-        assert_eq!(symbols.get_address(String::from("FOO")), 42);
+        assert_eq!(symbols.get_address(String::from("FOO")), Some(42));
+    }
+
+    #[test]
+    fn constants() {
+        let mut symbols = Symbols::new();
+        symbols.add_constant(String::from("ROWS"), 256);
+        assert_eq!(symbols.get("ROWS"), Some(256));
+        assert_eq!(symbols.get("COLS"), None);
+        assert_eq!(symbols.constants(), vec![("ROWS", 256)]);
+        assert_eq!(symbols.variables(), vec![]);
     }
 }
@@ -1,9 +1,13 @@
+use crate::code_gen::Bootstrap;
+use crate::error::{ParseErrors, ValidationErrors};
 use crate::file_reader::{FileData, FileReader};
 use crate::vm_translator::Translator;
 use std::{error::Error, fs, path::Path};
 
 pub struct Config {
     path: String,
+    bootstrap: Option<Bootstrap>,
+    optimize: bool,
 }
 
 impl Config {
@@ -13,26 +17,107 @@ impl Config {
     {
         args.next();
 
-        let path = match args.next() {
+        let mut path = None;
+        let mut bootstrap = None;
+        let mut optimize = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bootstrap" => {
+                    bootstrap = match args.next().as_deref() {
+                        Some("none") => Some(Bootstrap::None),
+                        Some("jump") => Some(Bootstrap::Jump),
+                        Some("call") => Some(Bootstrap::Call),
+                        _ => return Err("--bootstrap expects one of none, jump or call."),
+                    };
+                }
+                "--optimize" | "-O" => optimize = true,
+                _ if arg.starts_with('-') => return Err("Unknown option."),
+                _ => path = Some(arg),
+            }
+        }
+
+        let path = match path {
             Some(p) => p,
             None => return Err("No file or directory given."),
         };
 
-        Ok(Config { path })
+        Ok(Config {
+            path,
+            bootstrap,
+            optimize,
+        })
     }
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let file_container = FileReader::process(&Path::new(&config.path))?;
+    let file_container = FileReader::process(Path::new(&config.path))?;
 
-    let mut translator = Translator::new();
+    let bootstrap = config
+        .bootstrap
+        .unwrap_or_else(|| file_container.bootstrap());
+    let mut translator = Translator::with_bootstrap(bootstrap);
+
+    let mut errors = ParseErrors::new();
 
     for FileData { filename, contents } in file_container.files {
-        translator.translate(filename, contents);
+        if let Err(file_errors) = translator.translate(filename, contents) {
+            errors.append(file_errors);
+        }
+    }
+
+    if !errors.is_empty() {
+        for error in errors.errors() {
+            eprintln!("{}", error);
+        }
+        return Err(Box::new(errors));
     }
 
-    let assembly_code = translator.output();
+    let print_errors = |errors: &ValidationErrors| {
+        for error in errors.errors() {
+            eprintln!("{}", error);
+        }
+    };
+
+    let assembly_code = if config.optimize {
+        let (assembly_code, stats) = translator.output_optimized().inspect_err(print_errors)?;
+        println!(
+            "Optimized {} instructions down to {}",
+            stats.before, stats.after
+        );
+        assembly_code
+    } else {
+        translator.output().inspect_err(print_errors)?
+    };
 
     fs::write(file_container.output_filename, assembly_code)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.into_iter()
+    }
+
+    #[test]
+    fn config() {
+        let config = Config::new(args(&["rust", "SimpleAdd.vm"])).unwrap();
+        assert_eq!(config.path, "SimpleAdd.vm");
+        assert_eq!(config.bootstrap, None);
+        assert!(!config.optimize);
+
+        let config = Config::new(args(&["rust", "--bootstrap", "jump", "Dir"])).unwrap();
+        assert_eq!(config.path, "Dir");
+        assert_eq!(config.bootstrap, Some(Bootstrap::Jump));
+
+        let config = Config::new(args(&["rust", "Dir", "-O"])).unwrap();
+        assert!(config.optimize);
+
+        assert!(Config::new(args(&["rust", "--bootstrap", "fast", "Dir"])).is_err());
+        assert!(Config::new(args(&["rust"])).is_err());
+    }
+}
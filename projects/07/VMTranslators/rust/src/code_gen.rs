@@ -1,5 +1,16 @@
 use crate::parser::{Command, Op, Segment};
 
+/// How the program starts before any translated VM code runs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Bootstrap {
+    /// No startup code, as expected by the project 07 tests.
+    None,
+    /// SP=256 followed by a plain jump to Sys.init.
+    Jump,
+    /// SP=256 followed by a full `call Sys.init 0`.
+    Call,
+}
+
 pub struct CodeGen {
     current_filename: String,
     goto_index: usize,
@@ -9,14 +20,22 @@ pub struct CodeGen {
 
 impl CodeGen {
     pub fn new() -> CodeGen {
-        CodeGen {
+        CodeGen::with_bootstrap(Bootstrap::None)
+    }
+
+    pub fn with_bootstrap(bootstrap: Bootstrap) -> CodeGen {
+        let mut code_gen = CodeGen {
             current_filename: String::from("Global"),
             goto_index: 0,
             return_index: 0,
             assembly_code: Vec::new(),
-        }
+        };
+
+        code_gen.gen_bootstrap(bootstrap);
+        code_gen
     }
 
+    #[cfg(test)]
     fn get_current_filename(&self) -> &String {
         &self.current_filename
     }
@@ -26,19 +45,26 @@ impl CodeGen {
     }
 
     pub fn output(&self) -> Vec<String> {
-        let mut code = vec![
-            // @SP = 256
-            String::from("@256"),
-            String::from("D=A"),
-            String::from("@R0"),
-            String::from("M=D"),
-            // Call Sys.init
-            String::from("@Sys.init"),
-            String::from("0;JMP"),
-        ];
-
-        code.extend(self.assembly_code.iter().cloned());
-        code
+        self.assembly_code.clone()
+    }
+
+    fn gen_bootstrap(&mut self, bootstrap: Bootstrap) {
+        if bootstrap == Bootstrap::None {
+            return;
+        }
+
+        // @SP = 256
+        self.assembly_code.push(String::from("@256"));
+        self.assembly_code.push(String::from("D=A"));
+        self.assembly_code.push(String::from("@R0"));
+        self.assembly_code.push(String::from("M=D"));
+
+        if bootstrap == Bootstrap::Call {
+            self.gen_call(String::from("Sys.init"), 0);
+        } else {
+            self.assembly_code.push(String::from("@Sys.init"));
+            self.assembly_code.push(String::from("0;JMP"));
+        }
     }
 
     pub fn get_assembly_code(&self) -> &Vec<String> {
@@ -457,6 +483,12 @@ impl CodeGen {
     }
 }
 
+impl Default for CodeGen {
+    fn default() -> CodeGen {
+        CodeGen::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -468,6 +500,39 @@ mod tests {
         assert_eq!(code_gen.get_assembly_code(), &Vec::<String>::new());
     }
 
+    #[test]
+    fn bootstrap_jump() {
+        let code_gen = CodeGen::with_bootstrap(Bootstrap::Jump);
+        assert_eq!(
+            code_gen.output(),
+            vec![
+                // @SP = 256
+                String::from("@256"),
+                String::from("D=A"),
+                String::from("@R0"),
+                String::from("M=D"),
+                // goto Sys.init
+                String::from("@Sys.init"),
+                String::from("0;JMP"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bootstrap_call() {
+        let mut code_gen = CodeGen::with_bootstrap(Bootstrap::Call);
+        let bootstrap = code_gen.output();
+
+        assert_eq!(&bootstrap[..4], &["@256", "D=A", "@R0", "M=D"]);
+        assert_eq!(bootstrap[4], "@RETURN0");
+        assert!(bootstrap.contains(&String::from("@Sys.init")));
+        assert_eq!(bootstrap.last().unwrap(), "(RETURN0)");
+
+        // calls made by the program itself get fresh return labels
+        code_gen.gen_command(Command::Call(String::from("Main.main"), 0));
+        assert_eq!(code_gen.output().last().unwrap(), "(RETURN1)");
+    }
+
     #[test]
     fn set_current_filename() {
         let mut code_gen = CodeGen::new();
@@ -0,0 +1,90 @@
+use std::{error::Error, fmt, rc::Rc};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub expected: &'static str,
+    /// The offending token, or `None` when the line ended too early.
+    pub found: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(
+            f,
+            "{}:{}: error: expected {}, found ",
+            self.line, self.column, self.expected
+        )?;
+        match &self.found {
+            Some(token) => write!(f, "`{}`", token),
+            None => write!(f, "end of line"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ValidationError {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(f, "{}: error: {}", self.line, self.message)
+    }
+}
+
+/// Every error found in one pass, so they can all be reported at once.
+#[derive(Debug, PartialEq)]
+pub struct Errors<E> {
+    errors: Vec<E>,
+}
+
+pub type ParseErrors = Errors<ParseError>;
+pub type ValidationErrors = Errors<ValidationError>;
+
+impl<E> Errors<E> {
+    pub fn new() -> Errors<E> {
+        Errors { errors: Vec::new() }
+    }
+
+    pub fn push(&mut self, error: E) {
+        self.errors.push(error);
+    }
+
+    pub fn append(&mut self, other: Errors<E>) {
+        self.errors.extend(other.errors);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &Vec<E> {
+        &self.errors
+    }
+}
+
+impl<E> Default for Errors<E> {
+    fn default() -> Errors<E> {
+        Errors::new()
+    }
+}
+
+impl<E> fmt::Display for Errors<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.errors.len() == 1 { "" } else { "s" };
+        write!(f, "aborting due to {} error{}", self.errors.len(), plural)
+    }
+}
+
+impl<E: fmt::Debug> Error for Errors<E> {}
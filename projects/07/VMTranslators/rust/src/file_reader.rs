@@ -1,3 +1,4 @@
+use crate::code_gen::Bootstrap;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{error::Error, fs, path::Path};
@@ -16,9 +17,11 @@ impl FileReader {
         })
     }
 
+    /// Removes the extension of the last path component only, so relative
+    /// paths like `../08/Sys.vm` keep their name.
     fn strip_extension(filename: &str) -> String {
         lazy_static! {
-            static ref EXT_RE: Regex = Regex::new("\\..+$").unwrap();
+            static ref EXT_RE: Regex = Regex::new("\\.[^./\\\\]+$").unwrap();
         }
 
         EXT_RE.replace(filename, "").to_string()
@@ -34,7 +37,7 @@ impl FileReader {
             }
         }
 
-        if FileReader::should_process(&path) {
+        if FileReader::should_process(path) {
             let filename = FileReader::strip_extension(path.to_str().unwrap());
             let contents = fs::read_to_string(path)?;
             file_list.push(FileData { filename, contents });
@@ -75,12 +78,37 @@ pub struct FileContainer {
     pub output_filename: String,
 }
 
+impl FileContainer {
+    /// Whether one of the input files is named `stem`.vm, e.g. `Sys`.
+    pub fn contains(&self, stem: &str) -> bool {
+        self.files
+            .iter()
+            .any(|file| Path::new(&file.filename).file_name() == Some(stem.as_ref()))
+    }
+
+    /// Programs with a Sys.vm start at Sys.init, anything else runs as is.
+    pub fn bootstrap(&self) -> Bootstrap {
+        if self.contains("Sys") {
+            Bootstrap::Call
+        } else {
+            Bootstrap::None
+        }
+    }
+}
+
 type FileListResult = Result<FileList, Box<dyn Error>>;
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn strip_extension() {
+        assert_eq!(FileReader::strip_extension("foo/Bar.vm"), "foo/Bar");
+        assert_eq!(FileReader::strip_extension("../foo/Bar.vm"), "../foo/Bar");
+        assert_eq!(FileReader::strip_extension("../foo.d/Bar"), "../foo.d/Bar");
+    }
+
     #[test]
     fn process_file() {
         match FileReader::process(Path::new("test_input/foo.vm")) {
@@ -119,7 +147,10 @@ mod tests {
                 ];
 
                 assert_eq!(files, file_data.files);
-                assert_eq!("test_input.asm", file_data.output_filename);
+                assert_eq!("test_input/test_input.asm", file_data.output_filename);
+                assert!(file_data.contains("qux"));
+                assert!(!file_data.contains("Sys"));
+                assert_eq!(file_data.bootstrap(), Bootstrap::None);
             }
             Err(err) => {
                 panic!("Error processing input path: {}", err)
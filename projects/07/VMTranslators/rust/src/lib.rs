@@ -0,0 +1,8 @@
+pub mod cli;
+pub mod code_gen;
+pub mod error;
+pub mod file_reader;
+pub mod optimizer;
+pub mod parser;
+pub mod validator;
+pub mod vm_translator;
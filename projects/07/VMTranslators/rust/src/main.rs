@@ -1,10 +1,5 @@
-mod cli;
-mod code_gen;
-mod file_reader;
-mod parser;
-mod vm_translator;
-
 use std::{env, process};
+use vm_translator::cli;
 
 fn main() {
     let config = cli::Config::new(env::args()).unwrap_or_else(|error| {
@@ -0,0 +1,367 @@
+//! Peephole optimizations over the assembly `CodeGen` emits. Every rewrite
+//! matches one of the code generator's own idioms and only fires when the
+//! registers it leaves with different values are overwritten before they are
+//! read again.
+
+const PUSH: [&str; 5] = ["@SP", "A=M", "M=D", "@SP", "M=M+1"];
+const POP: [&str; 3] = ["@SP", "AM=M-1", "D=M"];
+
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub before: usize,
+    pub after: usize,
+}
+
+/// A rewrite of `length` instructions into `code`, valid if the registers
+/// in `clobbers` are dead afterwards.
+struct Rewrite {
+    length: usize,
+    code: Vec<String>,
+    clobbers: &'static str,
+}
+
+impl Rewrite {
+    fn new(length: usize, code: &[&str], clobbers: &'static str) -> Option<Rewrite> {
+        Some(Rewrite {
+            length,
+            code: code.iter().map(|line| line.to_string()).collect(),
+            clobbers,
+        })
+    }
+}
+
+/// Optimizes until no rewrite applies anymore.
+pub fn optimize(code: &[String]) -> (Vec<String>, Stats) {
+    let before = count_instructions(code);
+    let mut code = code.to_vec();
+
+    loop {
+        let (optimized, changed) = optimize_pass(&code);
+        code = optimized;
+        if !changed {
+            break;
+        }
+    }
+
+    let after = count_instructions(&code);
+    (code, Stats { before, after })
+}
+
+/// Every line except label declarations.
+pub fn count_instructions(code: &[String]) -> usize {
+    code.iter().filter(|line| !line.starts_with('(')).count()
+}
+
+fn optimize_pass(code: &[String]) -> (Vec<String>, bool) {
+    let rules = [
+        push_pop,
+        binary_op,
+        pop_two,
+        unary_op,
+        zero_offset,
+        push_d,
+        push_constant,
+    ];
+    let mut optimized = Vec::with_capacity(code.len());
+    let mut changed = false;
+    let mut i = 0;
+
+    'lines: while i < code.len() {
+        for rule in &rules {
+            if let Some(rewrite) = rule(&code[i..]) {
+                let end = i + rewrite.length;
+                if rewrite.clobbers.chars().all(|r| is_dead(&code[end..], r)) {
+                    optimized.extend(rewrite.code);
+                    changed = true;
+                    i = end;
+                    continue 'lines;
+                }
+            }
+        }
+
+        optimized.push(code[i].clone());
+        i += 1;
+    }
+
+    (optimized, changed)
+}
+
+fn matches(code: &[String], at: usize, pattern: &[&str]) -> bool {
+    code.len() >= at + pattern.len()
+        && code[at..at + pattern.len()]
+            .iter()
+            .zip(pattern)
+            .all(|(line, expected)| line == expected)
+}
+
+/// A push straight into a pop leaves D as it was. The slot written above
+/// the stack pointer is a dead store.
+fn push_pop(code: &[String]) -> Option<Rewrite> {
+    if matches(code, 0, &PUSH) && matches(code, PUSH.len(), &POP) {
+        return Rewrite::new(PUSH.len() + POP.len(), &[], "A");
+    }
+    None
+}
+
+/// add, sub, and and or done in place on the stack: y is popped into D and
+/// x is updated where it stands.
+fn binary_op(code: &[String]) -> Option<Rewrite> {
+    if !(matches(code, 0, &POP)
+        && matches(code, 3, &["@R13", "M=D"])
+        && matches(code, 5, &POP)
+        && matches(code, 8, &["@R13"])
+        && matches(code, 10, &PUSH))
+    {
+        return None;
+    }
+
+    let op = match code[9].as_str() {
+        "D=D+M" => "M=D+M",
+        "D=D-M" => "M=M-D",
+        "D=D&M" => "M=D&M",
+        "D=D|M" => "M=D|M",
+        _ => return None,
+    };
+    Rewrite::new(15, &["@SP", "AM=M-1", "D=M", "A=A-1", op], "AD")
+}
+
+/// Two pops combined into D, as the comparisons do before jumping.
+fn pop_two(code: &[String]) -> Option<Rewrite> {
+    if !(matches(code, 0, &POP)
+        && matches(code, 3, &["@R13", "M=D"])
+        && matches(code, 5, &POP)
+        && matches(code, 8, &["@R13"]))
+    {
+        return None;
+    }
+
+    let op = match code.get(9).map(String::as_str) {
+        Some("D=D+M") => "D=D+M",
+        Some("D=D-M") => "D=M-D",
+        Some("D=D&M") => "D=D&M",
+        Some("D=D|M") => "D=D|M",
+        _ => return None,
+    };
+    Rewrite::new(
+        10,
+        &["@SP", "AM=M-1", "D=M", "A=A-1", op, "@SP", "M=M-1"],
+        "A",
+    )
+}
+
+/// neg and not done in place on the top of the stack.
+fn unary_op(code: &[String]) -> Option<Rewrite> {
+    if !(matches(code, 0, &POP) && matches(code, 4, &PUSH)) {
+        return None;
+    }
+
+    let op = match code[3].as_str() {
+        "D=-D" => "M=-M",
+        "D=!D" => "M=!M",
+        _ => return None,
+    };
+    Rewrite::new(9, &["@SP", "A=M-1", op], "AD")
+}
+
+/// Segment index 0 needs no addition.
+fn zero_offset(code: &[String]) -> Option<Rewrite> {
+    if matches(code, 0, &["@0", "A=D+A"]) {
+        return Rewrite::new(2, &["A=D"], "");
+    }
+    if matches(code, 0, &["@0", "D=D+A"]) {
+        return Rewrite::new(2, &[], "A");
+    }
+    None
+}
+
+/// Bumps SP first so the stack pointer is loaded once.
+fn push_d(code: &[String]) -> Option<Rewrite> {
+    if matches(code, 0, &PUSH) {
+        return Rewrite::new(5, &["@SP", "M=M+1", "A=M-1", "M=D"], "A");
+    }
+    None
+}
+
+/// 0 and 1 can be written to the stack without going through D.
+fn push_constant(code: &[String]) -> Option<Rewrite> {
+    if !(matches(code, 1, &["D=A", "@SP", "M=M+1", "A=M-1", "M=D"])) {
+        return None;
+    }
+
+    let constant = match code[0].as_str() {
+        "@0" => "M=0",
+        "@1" => "M=1",
+        _ => return None,
+    };
+    Rewrite::new(6, &["@SP", "M=M+1", "A=M-1", constant], "D")
+}
+
+/// Whether `register` (A or D) is overwritten before it is read when
+/// execution continues with `code`. A jump counts as a read, since the
+/// target may need it.
+fn is_dead(code: &[String], register: char) -> bool {
+    for line in code {
+        if line.starts_with('(') {
+            continue;
+        }
+        if line.starts_with('@') {
+            if register == 'A' {
+                return true;
+            }
+            continue;
+        }
+
+        let (dest, rest) = match line.find('=') {
+            Some(index) => (&line[..index], &line[index + 1..]),
+            None => ("", line.as_str()),
+        };
+        let (comp, jump) = match rest.find(';') {
+            Some(index) => (&rest[..index], true),
+            None => (rest, false),
+        };
+
+        let reads = match register {
+            'A' => jump || comp.contains('A') || comp.contains('M') || dest.contains('M'),
+            _ => jump || comp.contains(register),
+        };
+        if reads {
+            return false;
+        }
+        if dest.contains(register) {
+            return true;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(code: &[&str]) -> Vec<String> {
+        code.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn assert_optimizes(code: &[&str], expected: &[&str]) {
+        let (optimized, _) = optimize(&lines(code));
+        assert_eq!(optimized, lines(expected));
+    }
+
+    #[test]
+    fn push_pop_pair() {
+        let mut code = vec!["@LCL", "D=M"];
+        code.extend(&PUSH);
+        code.extend(&POP);
+        code.extend(&["@END", "D;JNE"]);
+
+        assert_optimizes(&code, &["@LCL", "D=M", "@END", "D;JNE"]);
+    }
+
+    #[test]
+    fn binary_ops() {
+        let mut code = POP.to_vec();
+        code.extend(&["@R13", "M=D"]);
+        code.extend(&POP);
+        code.extend(&["@R13", "D=D-M"]);
+        code.extend(&PUSH);
+        code.push("@R14");
+
+        assert_optimizes(&code, &["@SP", "AM=M-1", "D=M", "A=A-1", "M=M-D", "@R14"]);
+    }
+
+    #[test]
+    fn comparisons() {
+        let mut code = POP.to_vec();
+        code.extend(&["@R13", "M=D"]);
+        code.extend(&POP);
+        code.extend(&["@R13", "D=D-M", "@TRUTHY0", "D;JGT"]);
+
+        assert_optimizes(
+            &code,
+            &[
+                "@SP", "AM=M-1", "D=M", "A=A-1", "D=M-D", "@SP", "M=M-1", "@TRUTHY0", "D;JGT",
+            ],
+        );
+    }
+
+    #[test]
+    fn unary_ops() {
+        let mut code = POP.to_vec();
+        code.push("D=!D");
+        code.extend(&PUSH);
+        code.extend(&["@5", "D=A"]);
+
+        assert_optimizes(&code, &["@SP", "A=M-1", "M=!M", "@5", "D=A"]);
+    }
+
+    #[test]
+    fn pushes() {
+        let mut code = vec!["@0", "D=A"];
+        code.extend(&PUSH);
+        code.extend(&["@7", "D=A"]);
+        code.extend(&PUSH);
+        code.extend(&["@LCL", "D=M", "@0", "A=D+A", "D=M"]);
+        code.extend(&PUSH);
+        code.extend(&["@1", "D=A"]);
+        code.extend(&PUSH);
+        code.extend(&["@1", "D=A"]);
+        code.extend(&PUSH);
+        code.push("D=D+1");
+
+        assert_optimizes(
+            &code,
+            &[
+                "@SP", "M=M+1", "A=M-1", "M=0", "@7", "D=A", "@SP", "M=M+1", "A=M-1", "M=D",
+                "@LCL", "D=M", "A=D", "D=M", "@SP", "M=M+1", "A=M-1", "M=D", "@SP", "M=M+1",
+                "A=M-1", "M=1", "@1", "D=A", "@SP", "M=M+1", "A=M-1", "M=D", "D=D+1",
+            ],
+        );
+    }
+
+    #[test]
+    fn live_registers() {
+        // call relies on A pointing at SP after pushing THAT
+        let mut code = vec!["@THAT", "D=M"];
+        code.extend(&PUSH);
+        code.extend(&["D=M", "@5", "D=D-A"]);
+        assert_optimizes(&code, &code);
+
+        // A jump may need any register, so only the push is folded
+        let mut code = PUSH.to_vec();
+        code.extend(&POP);
+        code.push("0;JMP");
+        assert_optimizes(
+            &code,
+            &[
+                "@SP", "M=M+1", "A=M-1", "M=D", "@SP", "AM=M-1", "D=M", "0;JMP",
+            ],
+        );
+    }
+
+    #[test]
+    fn dead_registers() {
+        assert!(is_dead(&lines(&["(LOOP)", "@SP", "D=M"]), 'A'));
+        assert!(is_dead(&lines(&["@SP", "D=M"]), 'D'));
+        assert!(is_dead(&[], 'D'));
+        assert!(!is_dead(&lines(&["@SP", "M=D"]), 'D'));
+        assert!(!is_dead(&lines(&["M=D", "@SP"]), 'A'));
+        assert!(!is_dead(&lines(&["A=A+1"]), 'A'));
+        assert!(!is_dead(&lines(&["@END", "0;JMP"]), 'D'));
+    }
+
+    #[test]
+    fn stats() {
+        let mut code = vec!["(START)", "@0", "D=A"];
+        code.extend(&PUSH);
+
+        let (_, stats) = optimize(&lines(&code));
+        assert_eq!(
+            stats,
+            Stats {
+                before: 7,
+                after: 4
+            }
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use regex::Regex;
+use crate::error::ParseError;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq)]
 pub enum Segment {
@@ -38,130 +39,198 @@ pub enum Command {
     Return,
 }
 
+struct Token {
+    text: String,
+    column: usize,
+}
+
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+    end: usize,
+}
+
 pub struct Parser {
+    file: Rc<str>,
     current_line: usize,
-    program: Vec<String>,
+    program: Vec<Line>,
 }
 
 impl Parser {
     pub fn new(file_contents: String) -> Parser {
+        Parser::with_file("", file_contents)
+    }
+
+    /// Like `new`, with `file` reported in every error.
+    pub fn with_file(file: &str, file_contents: String) -> Parser {
         Parser {
+            file: Rc::from(file),
             current_line: 0,
-            program: Parser::normalize_program(file_contents),
+            program: Parser::tokenize(&file_contents),
         }
     }
 
-    fn normalize_program(file_contents: String) -> Vec<String> {
+    fn tokenize(file_contents: &str) -> Vec<Line> {
         file_contents
             .lines()
-            .filter_map(|line| {
-                let line = Parser::strip_comments(line);
-                Parser::strip_whitespace(line)
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let code = match line.find("//") {
+                    Some(start) => &line[..start],
+                    None => line,
+                };
+
+                let mut tokens: Vec<Token> = Vec::new();
+                let mut in_token = false;
+                for (column, c) in code.chars().enumerate() {
+                    match (c.is_whitespace(), in_token, tokens.last_mut()) {
+                        (true, _, _) => in_token = false,
+                        (false, true, Some(token)) => token.text.push(c),
+                        (false, _, _) => {
+                            tokens.push(Token {
+                                text: c.to_string(),
+                                column: column + 1,
+                            });
+                            in_token = true;
+                        }
+                    }
+                }
+
+                let end = code.trim_end().chars().count() + 1;
+                if tokens.is_empty() {
+                    None
+                } else {
+                    Some(Line {
+                        number: index + 1,
+                        tokens,
+                        end,
+                    })
+                }
             })
             .collect()
     }
 
-    fn strip_comments(line: &str) -> String {
-        let re = Regex::new("//.*").unwrap();
-        re.replace(line, "").to_string()
+    #[cfg(test)]
+    fn get_program(&self) -> Vec<String> {
+        self.program
+            .iter()
+            .map(|line| {
+                let words: Vec<&str> = line.tokens.iter().map(|t| t.text.as_str()).collect();
+                words.join(" ")
+            })
+            .collect()
     }
 
-    fn strip_whitespace(line: String) -> Option<String> {
-        let trim_edges_re = Regex::new("(^ +)|( +$)").unwrap();
-        let line = trim_edges_re.replace_all(&line, "").to_string();
-
-        let dedup_spaces_re = Regex::new(" +").unwrap();
-        let line = dedup_spaces_re.replace_all(&line, " ").to_string();
+    pub fn has_more_commands(&self) -> bool {
+        self.current_line < self.program.len()
+    }
 
-        if line.is_empty() {
-            None
-        } else {
-            Some(line)
+    pub fn advance(&mut self) {
+        if self.current_line < self.program.len() {
+            self.current_line += 1;
         }
     }
 
-    fn get_program(&self) -> &Vec<String> {
-        &self.program
+    /// The source line number of the current command.
+    pub fn line_number(&self) -> usize {
+        self.program[self.current_line].number
     }
 
-    pub fn has_more_commands(&self) -> bool {
-        self.current_line < self.program.len()
+    fn token(&self, position: usize) -> Option<&str> {
+        self.program[self.current_line]
+            .tokens
+            .get(position)
+            .map(|token| token.text.as_str())
     }
 
-    pub fn advance(&mut self) {
-        if self.current_line < self.program.len() {
-            self.current_line += 1;
+    fn error(&self, position: usize, expected: &'static str) -> ParseError {
+        let line = &self.program[self.current_line];
+        let (column, found) = match line.tokens.get(position) {
+            Some(token) => (token.column, Some(token.text.clone())),
+            None => (line.end, None),
+        };
+
+        ParseError {
+            file: Rc::clone(&self.file),
+            line: line.number,
+            column,
+            expected,
+            found,
         }
     }
 
-    fn get_op(&self) -> Op {
-        let command = &self.program[self.current_line];
-
-        match command.as_str() {
-            "add" => Op::Add,
-            "sub" => Op::Subtract,
-            "neg" => Op::Negate,
-            "eq" => Op::Equal,
-            "gt" => Op::GreaterThan,
-            "lt" => Op::LessThan,
-            "and" => Op::And,
-            "or" => Op::Or,
-            "not" => Op::Not,
-            _ => panic!("Invalid arithmetic operation: {}", command),
+    fn get_op(&self) -> Result<Op, ParseError> {
+        match self.token(0) {
+            Some("add") => Ok(Op::Add),
+            Some("sub") => Ok(Op::Subtract),
+            Some("neg") => Ok(Op::Negate),
+            Some("eq") => Ok(Op::Equal),
+            Some("gt") => Ok(Op::GreaterThan),
+            Some("lt") => Ok(Op::LessThan),
+            Some("and") => Ok(Op::And),
+            Some("or") => Ok(Op::Or),
+            Some("not") => Ok(Op::Not),
+            _ => Err(self.error(0, "a command")),
         }
     }
 
-    fn get_segment(&self) -> Segment {
-        let command = &self.program[self.current_line];
-        let mut parts = command.split_whitespace();
-
-        parts.next();
-        let segment = String::from(parts.next().unwrap());
-
-        match segment.as_str() {
-            "local" => Segment::Local,
-            "argument" => Segment::Argument,
-            "static" => Segment::Static,
-            "constant" => Segment::Constant,
-            "temp" => Segment::Temp,
-            "pointer" => Segment::Pointer,
-            "this" => Segment::This,
-            "that" => Segment::That,
-            _ => panic!("Invalid segment: {}", segment),
+    fn get_segment(&self) -> Result<Segment, ParseError> {
+        match self.token(1) {
+            Some("local") => Ok(Segment::Local),
+            Some("argument") => Ok(Segment::Argument),
+            Some("static") => Ok(Segment::Static),
+            Some("constant") => Ok(Segment::Constant),
+            Some("temp") => Ok(Segment::Temp),
+            Some("pointer") => Ok(Segment::Pointer),
+            Some("this") => Ok(Segment::This),
+            Some("that") => Ok(Segment::That),
+            _ => Err(self.error(1, "a memory segment")),
         }
     }
 
-    fn get_index(&self) -> usize {
-        let command = &self.program[self.current_line];
-        let mut parts = command.split_whitespace();
+    fn get_index(&self) -> Result<usize, ParseError> {
+        let index = self
+            .token(2)
+            .filter(|t| t.chars().all(|c| c.is_ascii_digit()));
 
-        parts.next();
-        parts.next();
-        parts.next().unwrap().parse::<usize>().unwrap()
+        match index.and_then(|index| index.parse::<usize>().ok()) {
+            Some(index) => Ok(index),
+            None => Err(self.error(2, "a non-negative integer")),
+        }
     }
 
-    fn get_label(&self) -> String {
-        let command = &self.program[self.current_line];
-        let mut parts = command.split_whitespace();
-
-        parts.next();
-        String::from(parts.next().unwrap())
+    fn get_label(&self) -> Result<String, ParseError> {
+        let is_symbol = |label: &&str| {
+            !label.starts_with(|c: char| c.is_ascii_digit())
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_.:$".contains(c))
+        };
+
+        match self.token(1).filter(is_symbol) {
+            Some(label) => Ok(String::from(label)),
+            None => Err(self.error(1, "a symbol")),
+        }
     }
 
-    pub fn parse_command(&self) -> Command {
-        let command = &self.program[self.current_line];
-
-        match &command[..2] {
-            "pu" => Command::Push(self.get_segment(), self.get_index()),
-            "po" => Command::Pop(self.get_segment(), self.get_index()),
-            "la" => Command::Label(self.get_label()),
-            "go" => Command::Goto(self.get_label()),
-            "if" => Command::IfGoto(self.get_label()),
-            "ca" => Command::Call(self.get_label(), self.get_index()),
-            "fu" => Command::Function(self.get_label(), self.get_index()),
-            "re" => Command::Return,
-            _ => Command::Arithmetic(self.get_op()),
+    pub fn parse_command(&self) -> Result<Command, ParseError> {
+        let (command, arguments) = match self.token(0) {
+            Some("push") => (Command::Push(self.get_segment()?, self.get_index()?), 3),
+            Some("pop") => (Command::Pop(self.get_segment()?, self.get_index()?), 3),
+            Some("label") => (Command::Label(self.get_label()?), 2),
+            Some("goto") => (Command::Goto(self.get_label()?), 2),
+            Some("if-goto") => (Command::IfGoto(self.get_label()?), 2),
+            Some("call") => (Command::Call(self.get_label()?, self.get_index()?), 3),
+            Some("function") => (Command::Function(self.get_label()?, self.get_index()?), 3),
+            Some("return") => (Command::Return, 1),
+            _ => (Command::Arithmetic(self.get_op()?), 1),
+        };
+
+        if self.token(arguments).is_some() {
+            return Err(self.error(arguments, "end of line"));
         }
+
+        Ok(command)
     }
 }
 
@@ -191,13 +260,15 @@ mod tests {
             String::from("add"),
             String::from("pop local 1"),
         ];
-        assert_eq!(parser.get_program(), &normalized_program);
+        assert_eq!(parser.get_program(), normalized_program);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn line_counter() {
         let program = "
         line 1
+
         line 2
         "
         .to_string();
@@ -206,8 +277,11 @@ mod tests {
 
         assert!(parser.has_more_commands());
 
+        assert_eq!(parser.line_number(), 2);
+
         parser.advance();
         assert!(parser.has_more_commands());
+        assert_eq!(parser.line_number(), 4);
 
         parser.advance();
         assert_eq!(parser.has_more_commands(), false);
@@ -223,22 +297,30 @@ mod tests {
 
         let mut parser = Parser::new(program);
 
-        assert_eq!(parser.get_op(), Op::Add);
+        assert_eq!(parser.get_op().unwrap(), Op::Add);
 
         parser.advance();
-        assert_eq!(parser.get_op(), Op::Equal);
+        assert_eq!(parser.get_op().unwrap(), Op::Equal);
     }
 
     #[test]
-    #[should_panic]
     fn invalid_op() {
         let program = "
         mult
         "
         .to_string();
 
-        let parser = Parser::new(program);
-        parser.get_op();
+        let parser = Parser::with_file("Foo.vm", program);
+        assert_eq!(
+            parser.get_op(),
+            Err(ParseError {
+                file: Rc::from("Foo.vm"),
+                line: 2,
+                column: 9,
+                expected: "a command",
+                found: Some(String::from("mult")),
+            })
+        );
     }
 
     #[test]
@@ -251,14 +333,13 @@ mod tests {
 
         let mut parser = Parser::new(program);
 
-        assert_eq!(parser.get_segment(), Segment::Argument);
+        assert_eq!(parser.get_segment().unwrap(), Segment::Argument);
 
         parser.advance();
-        assert_eq!(parser.get_segment(), Segment::Local);
+        assert_eq!(parser.get_segment().unwrap(), Segment::Local);
     }
 
     #[test]
-    #[should_panic]
     fn invalid_segment() {
         let program = "
         pop foo 1
@@ -266,7 +347,10 @@ mod tests {
         .to_string();
 
         let parser = Parser::new(program);
-        parser.get_segment();
+        let error = parser.get_segment().unwrap_err();
+        assert_eq!((error.line, error.column), (2, 13));
+        assert_eq!(error.expected, "a memory segment");
+        assert_eq!(error.found, Some(String::from("foo")));
     }
 
     #[test]
@@ -279,10 +363,10 @@ mod tests {
 
         let mut parser = Parser::new(program);
 
-        assert_eq!(parser.get_index(), 3);
+        assert_eq!(parser.get_index().unwrap(), 3);
 
         parser.advance();
-        assert_eq!(parser.get_index(), 10);
+        assert_eq!(parser.get_index().unwrap(), 10);
     }
 
     #[test]
@@ -294,7 +378,7 @@ mod tests {
 
         let parser = Parser::new(program);
 
-        assert_eq!(parser.get_label(), String::from("SOME_LABEL"));
+        assert_eq!(parser.get_label().unwrap(), String::from("SOME_LABEL"));
     }
 
     #[test]
@@ -314,45 +398,86 @@ mod tests {
 
         let mut parser = Parser::new(program);
 
-        assert_eq!(parser.parse_command(), Command::Push(Segment::Argument, 3));
+        assert_eq!(
+            parser.parse_command().unwrap(),
+            Command::Push(Segment::Argument, 3)
+        );
 
         parser.advance();
-        assert_eq!(parser.parse_command(), Command::Pop(Segment::Local, 10));
+        assert_eq!(
+            parser.parse_command().unwrap(),
+            Command::Pop(Segment::Local, 10)
+        );
 
         parser.advance();
-        assert_eq!(parser.parse_command(), Command::Arithmetic(Op::Add));
+        assert_eq!(
+            parser.parse_command().unwrap(),
+            Command::Arithmetic(Op::Add)
+        );
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().unwrap(),
             Command::Label(String::from("SOME_LABEL"))
         );
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().unwrap(),
             Command::Goto(String::from("GOTO_LABEL"))
         );
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().unwrap(),
             Command::IfGoto(String::from("IF_GOTO_LABEL"))
         );
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().unwrap(),
             Command::Call(String::from("Foo"), 3)
         );
 
         parser.advance();
         assert_eq!(
-            parser.parse_command(),
+            parser.parse_command().unwrap(),
             Command::Function(String::from("Foo"), 4)
         );
 
         parser.advance();
-        assert_eq!(parser.parse_command(), Command::Return);
+        assert_eq!(parser.parse_command().unwrap(), Command::Return);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let program = "
+        popx local 1
+        p
+        push local
+        push local -1
+        goto 1LOOP
+        return 0 // nothing to return
+        "
+        .to_string();
+
+        let mut parser = Parser::new(program);
+        let mut errors = Vec::new();
+        while parser.has_more_commands() {
+            errors.push(parser.parse_command().unwrap_err().to_string());
+            parser.advance();
+        }
+
+        assert_eq!(
+            errors,
+            vec![
+                "2:9: error: expected a command, found `popx`",
+                "3:9: error: expected a command, found `p`",
+                "4:19: error: expected a non-negative integer, found end of line",
+                "5:20: error: expected a non-negative integer, found `-1`",
+                "6:14: error: expected a symbol, found `1LOOP`",
+                "7:16: error: expected end of line, found `0`",
+            ]
+        );
     }
 }
@@ -0,0 +1,351 @@
+use crate::error::{ValidationError, ValidationErrors};
+use crate::parser::{Command, Segment};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    rc::Rc,
+};
+
+/// Static variables are allocated by the assembler from RAM 16 to 255.
+const STATIC_CAPACITY: usize = 240;
+const MAX_CONSTANT: usize = 32767;
+
+/// The labels and jumps of the function currently being checked. Commands
+/// before the first `function` form a scope of their own.
+struct Scope<'a> {
+    function: Option<&'a str>,
+    labels: HashSet<&'a str>,
+    jumps: Vec<(&'a str, usize)>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(function: Option<&'a str>) -> Scope<'a> {
+        Scope {
+            function,
+            labels: HashSet::new(),
+            jumps: Vec::new(),
+        }
+    }
+}
+
+/// Checks parsed commands for programs that would translate into broken
+/// assembly. Files are checked one at a time with `check_file`; whatever
+/// spans files, such as calls to undefined functions, is reported by
+/// `finish`.
+pub struct Validator {
+    functions: HashMap<String, (Rc<str>, usize)>,
+    calls: Vec<(String, Rc<str>, usize)>,
+    statics: HashSet<(String, usize)>,
+    errors: ValidationErrors,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator {
+            functions: HashMap::new(),
+            calls: Vec::new(),
+            statics: HashSet::new(),
+            errors: ValidationErrors::new(),
+        }
+    }
+
+    /// Checks the commands of one file, each paired with its line number.
+    pub fn check_file(&mut self, file: &str, commands: &[(usize, Command)]) {
+        let file: Rc<str> = Rc::from(file);
+        let stem = Path::new(file.as_ref())
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut scope = Scope::new(None);
+
+        for (line, command) in commands {
+            let line = *line;
+            let message = match command {
+                Command::Push(segment, index) | Command::Pop(segment, index) => {
+                    let pop = matches!(command, Command::Pop(..));
+                    self.check_segment(&stem, segment, *index, pop)
+                }
+                Command::Label(label) => {
+                    if scope.labels.insert(label) {
+                        None
+                    } else {
+                        Some(format!("label `{}` is defined twice", label))
+                    }
+                }
+                Command::Goto(label) | Command::IfGoto(label) => {
+                    scope.jumps.push((label, line));
+                    None
+                }
+                Command::Call(function, _) => {
+                    self.calls.push((function.clone(), Rc::clone(&file), line));
+                    None
+                }
+                Command::Function(function, _) => {
+                    self.check_jumps(&file, &scope);
+                    scope = Scope::new(Some(function));
+
+                    match self.functions.get(function) {
+                        Some((other_file, other_line)) => Some(format!(
+                            "function `{}` is already defined at {}:{}",
+                            function, other_file, other_line
+                        )),
+                        None => {
+                            self.functions
+                                .insert(function.clone(), (Rc::clone(&file), line));
+                            None
+                        }
+                    }
+                }
+                Command::Arithmetic(_) | Command::Return => None,
+            };
+
+            if let Some(message) = message {
+                self.errors.push(ValidationError {
+                    file: Rc::clone(&file),
+                    line,
+                    message,
+                });
+            }
+        }
+
+        self.check_jumps(&file, &scope);
+    }
+
+    fn check_segment(
+        &mut self,
+        stem: &str,
+        segment: &Segment,
+        index: usize,
+        pop: bool,
+    ) -> Option<String> {
+        match segment {
+            Segment::Constant if pop => Some(String::from("cannot pop into the constant segment")),
+            Segment::Constant if index > MAX_CONSTANT => Some(format!(
+                "constant {} is larger than {}",
+                index, MAX_CONSTANT
+            )),
+            Segment::Temp if index > 7 => Some(format!("temp {} is out of range 0-7", index)),
+            Segment::Pointer if index > 1 => Some(format!("pointer {} is out of range 0-1", index)),
+            Segment::Static => {
+                // The assembler hands out addresses by first use, so only the
+                // number of distinct statics matters, not the indices
+                let new = self.statics.insert((String::from(stem), index));
+                if new && self.statics.len() > STATIC_CAPACITY {
+                    Some(format!(
+                        "static {} does not fit in RAM 16-255, the program \
+                         uses more than {} static variables",
+                        index, STATIC_CAPACITY
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn check_jumps(&mut self, file: &Rc<str>, scope: &Scope) {
+        for (label, line) in &scope.jumps {
+            if scope.labels.contains(label) {
+                continue;
+            }
+
+            let message = match scope.function {
+                Some(function) => format!(
+                    "label `{}` is not defined in function `{}`",
+                    label, function
+                ),
+                None => format!("label `{}` is not defined", label),
+            };
+            self.errors.push(ValidationError {
+                file: Rc::clone(file),
+                line: *line,
+                message,
+            });
+        }
+    }
+
+    /// Reports calls to functions no file defined, along with every error
+    /// found so far.
+    pub fn finish(mut self) -> Result<(), ValidationErrors> {
+        for (function, file, line) in &self.calls {
+            if !self.functions.contains_key(function) {
+                self.errors.push(ValidationError {
+                    file: Rc::clone(file),
+                    line: *line,
+                    message: format!("call to undefined function `{}`", function),
+                });
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Validator {
+        Validator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn commands(program: &str) -> Vec<(usize, Command)> {
+        let mut parser = Parser::new(program.to_string());
+        let mut commands = Vec::new();
+
+        while parser.has_more_commands() {
+            commands.push((parser.line_number(), parser.parse_command().unwrap()));
+            parser.advance();
+        }
+        commands
+    }
+
+    fn messages(result: Result<(), ValidationErrors>) -> Vec<String> {
+        match result {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.errors().iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_program() {
+        let mut validator = Validator::new();
+        validator.check_file(
+            "Main.vm",
+            &commands(
+                "
+                function Main.main 0
+                push constant 32767
+                pop temp 7
+                push pointer 1
+                label LOOP
+                if-goto END
+                call Main.main 0
+                goto LOOP
+                label END
+                return
+                ",
+            ),
+        );
+
+        assert_eq!(validator.finish(), Ok(()));
+    }
+
+    #[test]
+    fn segments() {
+        let mut validator = Validator::new();
+        validator.check_file(
+            "Main.vm",
+            &commands(
+                "
+                pop constant 0
+                push constant 32768
+                push temp 8
+                pop pointer 2
+                ",
+            ),
+        );
+
+        assert_eq!(
+            messages(validator.finish()),
+            vec![
+                "Main.vm:2: error: cannot pop into the constant segment",
+                "Main.vm:3: error: constant 32768 is larger than 32767",
+                "Main.vm:4: error: temp 8 is out of range 0-7",
+                "Main.vm:5: error: pointer 2 is out of range 0-1",
+            ]
+        );
+    }
+
+    #[test]
+    fn statics() {
+        let program: Vec<(usize, Command)> = (0..=STATIC_CAPACITY)
+            .map(|index| (index + 1, Command::Push(Segment::Static, index)))
+            .collect();
+
+        let mut validator = Validator::new();
+        validator.check_file("Foo.vm", &program[..200]);
+        validator.check_file("Foo.vm", &program[..200]);
+        assert_eq!(messages(validator.finish()), Vec::<String>::new());
+
+        let mut validator = Validator::new();
+        validator.check_file("Foo.vm", &program[..200]);
+        validator.check_file("Bar.vm", &program[..41]);
+        assert_eq!(
+            messages(validator.finish()),
+            vec![
+                "Bar.vm:41: error: static 40 does not fit in RAM 16-255, \
+                 the program uses more than 240 static variables"
+            ]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let mut validator = Validator::new();
+        validator.check_file(
+            "Main.vm",
+            &commands(
+                "
+                goto START
+                label START
+                function Main.a 0
+                label LOOP
+                label LOOP
+                goto START
+                function Main.b 0
+                if-goto LOOP
+                ",
+            ),
+        );
+
+        assert_eq!(
+            messages(validator.finish()),
+            vec![
+                "Main.vm:6: error: label `LOOP` is defined twice",
+                "Main.vm:7: error: label `START` is not defined in function `Main.a`",
+                "Main.vm:9: error: label `LOOP` is not defined in function `Main.b`",
+            ]
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let mut validator = Validator::new();
+        validator.check_file(
+            "dir/Main.vm",
+            &commands(
+                "
+                function Main.main 0
+                call Sys.init 0
+                call Math.multiply 2
+                ",
+            ),
+        );
+        validator.check_file(
+            "dir/Sys.vm",
+            &commands(
+                "
+                function Sys.init 0
+                function Main.main 0
+                ",
+            ),
+        );
+
+        assert_eq!(
+            messages(validator.finish()),
+            vec![
+                "dir/Sys.vm:3: error: function `Main.main` is already defined at dir/Main.vm:2",
+                "dir/Main.vm:4: error: call to undefined function `Math.multiply`",
+            ]
+        );
+    }
+}
@@ -1,31 +1,89 @@
-use crate::code_gen::CodeGen;
-use crate::parser::Parser;
+use crate::code_gen::{Bootstrap, CodeGen};
+use crate::error::{ParseErrors, ValidationErrors};
+use crate::optimizer::{self, Stats};
+use crate::parser::{Command, Parser};
+use crate::validator::Validator;
 use std::path::Path;
 
 pub struct Translator {
     code_gen: CodeGen,
+    files: Vec<(String, Vec<(usize, Command)>)>,
 }
 
 impl Translator {
     pub fn new() -> Translator {
+        Translator::with_bootstrap(Bootstrap::None)
+    }
+
+    pub fn with_bootstrap(bootstrap: Bootstrap) -> Translator {
         Translator {
-            code_gen: CodeGen::new(),
+            code_gen: CodeGen::with_bootstrap(bootstrap),
+            files: Vec::new(),
         }
     }
 
-    pub fn translate(&mut self, filename: String, file_contents: String) {
-        let file_stem = Path::new(&filename).file_stem().unwrap().to_str().unwrap();
-        self.code_gen.set_current_filename(file_stem);
-        let mut parser = Parser::new(file_contents);
+    /// Parses one file, skipping over and reporting every line that doesn't
+    /// parse. Code is generated by `output` once all files are in.
+    pub fn translate(
+        &mut self,
+        filename: String,
+        file_contents: String,
+    ) -> Result<(), ParseErrors> {
+        let mut parser = Parser::with_file(&format!("{}.vm", filename), file_contents);
+        let mut commands = Vec::new();
+        let mut errors = ParseErrors::new();
 
         while parser.has_more_commands() {
-            self.code_gen.gen_command(parser.parse_command());
+            match parser.parse_command() {
+                Ok(command) => commands.push((parser.line_number(), command)),
+                Err(error) => errors.push(error),
+            }
             parser.advance();
         }
+
+        self.files.push((filename, commands));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates every file as a whole program and generates its assembly.
+    pub fn output(self) -> Result<String, ValidationErrors> {
+        Ok(self.generate()?.join("\n"))
+    }
+
+    /// Like `output`, with the peephole optimizer run over the assembly.
+    pub fn output_optimized(self) -> Result<(String, Stats), ValidationErrors> {
+        let (assembly_code, stats) = optimizer::optimize(&self.generate()?);
+        Ok((assembly_code.join("\n"), stats))
+    }
+
+    fn generate(mut self) -> Result<Vec<String>, ValidationErrors> {
+        let mut validator = Validator::new();
+        for (filename, commands) in &self.files {
+            validator.check_file(&format!("{}.vm", filename), commands);
+        }
+        validator.finish()?;
+
+        for (filename, commands) in self.files {
+            let file_stem = Path::new(&filename).file_stem().unwrap().to_str().unwrap();
+            self.code_gen.set_current_filename(file_stem);
+
+            for (_, command) in commands {
+                self.code_gen.gen_command(command);
+            }
+        }
+
+        Ok(self.code_gen.output())
     }
+}
 
-    pub fn output(&self) -> String {
-        self.code_gen.output().join("\n")
+impl Default for Translator {
+    fn default() -> Translator {
+        Translator::new()
     }
 }
 
@@ -35,7 +93,7 @@ mod tests {
 
     #[test]
     fn translate() {
-        let mut translator = Translator::new();
+        let mut translator = Translator::with_bootstrap(Bootstrap::Jump);
 
         let vm_program = "
         // Test VM Program
@@ -157,8 +215,52 @@ mod tests {
         ]
         .join("\n");
 
-        translator.translate(String::from("foo"), vm_program);
+        translator
+            .translate(String::from("foo"), vm_program)
+            .unwrap();
+
+        assert_eq!(translator.output().unwrap(), assembly_code);
+    }
+
+    #[test]
+    fn translate_errors() {
+        let mut translator = Translator::new();
+        let mut errors = ParseErrors::new();
+
+        let foo = String::from("push constant 1\npush constant\n");
+        let bar = String::from("add\npopx local 1\n");
+        for (filename, contents) in [("dir/Foo", foo), ("dir/Bar", bar)] {
+            if let Err(file_errors) = translator.translate(String::from(filename), contents) {
+                errors.append(file_errors);
+            }
+        }
+
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "dir/Foo.vm:2:14: error: expected a non-negative integer, found end of line",
+                "dir/Bar.vm:2:1: error: expected a command, found `popx`",
+            ]
+        );
+    }
+
+    #[test]
+    fn validation_errors() {
+        let mut translator = Translator::new();
+        let program = String::from("function Foo.main 0\npop constant 0\ncall Foo.bar 0\n");
+        translator
+            .translate(String::from("dir/Foo"), program)
+            .unwrap();
 
-        assert_eq!(translator.output(), assembly_code);
+        let errors = translator.output().unwrap_err();
+        let errors: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "dir/Foo.vm:2: error: cannot pop into the constant segment",
+                "dir/Foo.vm:3: error: call to undefined function `Foo.bar`",
+            ]
+        );
     }
 }
@@ -2,8 +2,115 @@
 D=A
 @R0
 M=D
+@RETURN0
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@LCL
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@ARG
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THIS
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+D=M
+@5
+D=D-A
+@0
+D=D-A
+@ARG
+M=D
+@SP
+D=M
+@LCL
+M=D
 @Sys.init
 0;JMP
+(RETURN0)
+(Sys.init)
+@4
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@RETURN1
+D=A
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@LCL
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@ARG
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THIS
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+@THAT
+D=M
+@SP
+A=M
+M=D
+@SP
+M=M+1
+D=M
+@5
+D=D-A
+@1
+D=D-A
+@ARG
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Main.fibonacci
+0;JMP
+(RETURN1)
+(Sys$WHILE)
+@Sys$WHILE
+0;JMP
 (Main.fibonacci)
 @ARG
 D=M
@@ -138,7 +245,7 @@ A=M
 M=D
 @SP
 M=M+1
-@RETURN0
+@RETURN2
 D=A
 @SP
 A=M
@@ -186,7 +293,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(RETURN0)
+(RETURN2)
 @ARG
 D=M
 @0
@@ -219,7 +326,7 @@ A=M
 M=D
 @SP
 M=M+1
-@RETURN1
+@RETURN3
 D=A
 @SP
 A=M
@@ -267,7 +374,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(RETURN1)
+(RETURN3)
 @SP
 AM=M-1
 D=M
@@ -324,64 +431,4 @@ D=M
 M=D
 @R14
 A=M
-0;JMP
-(Sys.init)
-@4
-D=A
-@SP
-A=M
-M=D
-@SP
-M=M+1
-@RETURN2
-D=A
-@SP
-A=M
-M=D
-@SP
-M=M+1
-@LCL
-D=M
-@SP
-A=M
-M=D
-@SP
-M=M+1
-@ARG
-D=M
-@SP
-A=M
-M=D
-@SP
-M=M+1
-@THIS
-D=M
-@SP
-A=M
-M=D
-@SP
-M=M+1
-@THAT
-D=M
-@SP
-A=M
-M=D
-@SP
-M=M+1
-D=M
-@5
-D=D-A
-@1
-D=D-A
-@ARG
-M=D
-@SP
-D=M
-@LCL
-M=D
-@Main.fibonacci
-0;JMP
-(RETURN2)
-(Sys$WHILE)
-@Sys$WHILE
 0;JMP
\ No newline at end of file
@@ -9,7 +9,7 @@
 load FibonacciElement.asm,
 output-file FibonacciElement.out,
 compare-to FibonacciElement.cmp,
-output-list RAM[0]%D1.6.1 RAM[256]%D1.6.1;
+output-list RAM[0]%D1.6.1 RAM[261]%D1.6.1;
 
 repeat 1800 {
   ticktock;